@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::combine::{self, CombineOptions, ItemType, Summary};
+use crate::ffopt::{self, OutputOptions, SplitOptions};
+use crate::progress::Progress;
+use crate::subtitle::{self, Format};
+
+const USAGE: &str = "Usage: speech-opt combine --audio FILE --srt FILE [OPTIONS]
+
+Cuts the audio at its silences and combines the pieces with the subtitle
+cues, like \"Combine audio & subtitle\" in the window, then prints a JSON
+summary.
+
+Options:
+  --audio FILE          Audio to cut
+  --srt FILE            Subtitle, SRT, WebVTT, ASS/SSA, LRC or plain text
+  --subtitle FILE       Same as --srt
+  --start audio|subtitle     Where the start times come from (subtitle)
+  --duration audio|subtitle  Where the durations come from (subtitle)
+  --out DIR             Output folder (.)
+  --name NAME           Output file name, without extension (the audio's)
+  --format FORMAT       Subtitle output: srt, vtt, ass, lrc or txt (srt)
+  --container EXT       Audio output: mp3, m4a, ogg, opus, flac or wav (mp3)
+  --codec CODEC         Audio encoder, like libmp3lame or aac
+  --bitrate RATE        Audio bitrate, like 192k
+  --sample-rate HZ      Audio sample rate, like 44100
+  --channels LAYOUT     Audio channel layout, like mono or stereo
+  --silence MS          Shortest silence between pieces (280)
+  --noise DB            Noise threshold of silences (-60)
+  --min-piece MS        Shortest piece, shorter ones are merged (0)
+  --padding MS          Silence kept around each piece (0)
+  --force               Combine even if the numbers of pieces and cues differ
+
+Exit status: 0 when combined, 1 on errors, 2 when the numbers of pieces and
+cues differ and --force isn't given.";
+
+#[derive(Debug)]
+struct Args {
+    audio: PathBuf,
+    subtitle: PathBuf,
+    split: SplitOptions,
+    combine: CombineOptions,
+    force: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Mismatch,
+    Error,
+}
+
+#[derive(Serialize)]
+struct Report {
+    status: Status,
+    audio: PathBuf,
+    subtitle: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio_pieces: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtitle_cues: Option<usize>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    summary: Option<Summary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 命令行模式，返回进程的退出码
+pub fn run(args: &[String]) -> i32 {
+    let args = match args.split_first() {
+        Some((command, rest)) if command == "combine" => parse_args(rest),
+        Some((command, _)) if command == "--help" || command == "-h" => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        _ => Err(anyhow::anyhow!("unknown command")),
+    };
+    let args = match args {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return 1;
+        }
+    };
+
+    let mut report = Report {
+        status: Status::Ok,
+        audio: args.audio.clone(),
+        subtitle: args.subtitle.clone(),
+        audio_pieces: None,
+        subtitle_cues: None,
+        summary: None,
+        error: None,
+    };
+    let code = match run_combine(&args, &mut report) {
+        Ok(code) => code,
+        Err(err) => {
+            report.status = Status::Error;
+            report.error = Some(format!("{:#}", err));
+            1
+        }
+    };
+    let _ = std::fs::remove_dir_all(&args.combine.temp_dir);
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).unwrap_or_default()
+    );
+    code
+}
+
+fn run_combine(args: &Args, report: &mut Report) -> Result<i32> {
+    let subtitles = subtitle::load(&args.subtitle)
+        .with_context(|| format!("failed to read {}", args.subtitle.display()))?;
+    let progress = Progress::default();
+    let pieces = ffopt::get_audio_pieces(&args.audio, &args.split, &progress)
+        .with_context(|| format!("failed to split {}", args.audio.display()))?;
+
+    if pieces.len() != subtitles.cues.len() && !args.force {
+        report.status = Status::Mismatch;
+        report.audio_pieces = Some(pieces.len());
+        report.subtitle_cues = Some(subtitles.cues.len());
+        return Ok(2);
+    }
+
+    let summary = combine::combine(&args.audio, &pieces, &subtitles, &args.combine, &progress)?;
+    report.summary = Some(summary);
+    Ok(0)
+}
+
+fn parse_args(args: &[String]) -> Result<Args> {
+    let mut audio = None;
+    let mut subtitle = None;
+    let mut name = None;
+    let mut split = SplitOptions::default();
+    let mut combine = CombineOptions {
+        start: ItemType::Subtitle,
+        duration: ItemType::Subtitle,
+        subtitle_format: Format::Srt,
+        output: OutputOptions {
+            container: "mp3".to_owned(),
+            ..Default::default()
+        },
+        out_dir: PathBuf::from("."),
+        name: String::new(),
+        temp_dir: std::env::temp_dir().join(format!("speech-opt-{}", std::process::id())),
+    };
+    let mut force = false;
+
+    let millis = |value: &str| -> Result<Duration> { Ok(Duration::from_millis(value.parse()?)) };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--force" {
+            force = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--audio" => audio = Some(PathBuf::from(value)),
+            "--srt" | "--subtitle" => subtitle = Some(PathBuf::from(value)),
+            "--start" => combine.start = value.parse()?,
+            "--duration" => combine.duration = value.parse()?,
+            "--out" => combine.out_dir = PathBuf::from(value),
+            "--name" => name = Some(value.to_owned()),
+            "--format" => {
+                combine.subtitle_format = Format::from_extension(value)
+                    .ok_or_else(|| anyhow::anyhow!("unknown subtitle format {}", value))?
+            }
+            "--container" => combine.output.container = value.to_owned(),
+            "--codec" => combine.output.codec = Some(value.to_owned()),
+            "--bitrate" => combine.output.bitrate = Some(value.to_owned()),
+            "--sample-rate" => combine.output.sample_rate = Some(value.parse()?),
+            "--channels" => combine.output.channel_layout = Some(value.to_owned()),
+            "--silence" => split.silence = millis(value)?,
+            "--noise" => split.noise_db = value.parse()?,
+            "--min-piece" => split.min_piece = millis(value)?,
+            "--padding" => split.padding = millis(value)?,
+            _ => return Err(anyhow::anyhow!("unknown option {}", flag)),
+        }
+    }
+
+    let audio = audio.ok_or_else(|| anyhow::anyhow!("--audio is required"))?;
+    let subtitle = subtitle.ok_or_else(|| anyhow::anyhow!("--srt is required"))?;
+    combine.name = match name {
+        Some(name) => name,
+        None => audio
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("no file name in {}", audio.display()))?,
+    };
+
+    Ok(Args {
+        audio,
+        subtitle,
+        split,
+        combine,
+        force,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args> {
+        let args: Vec<String> = args.split_whitespace().map(|x| x.to_owned()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(
+            "--audio in/a.mp3 --srt a.srt --start audio --duration subtitle --out out/ \
+             --format vtt --container m4a --bitrate 128k --noise -50 --padding 100 --force",
+        )
+        .unwrap();
+        assert_eq!(args.audio, PathBuf::from("in/a.mp3"));
+        assert_eq!(args.combine.start, ItemType::Audio);
+        assert_eq!(args.combine.duration, ItemType::Subtitle);
+        assert_eq!(args.combine.out_dir, PathBuf::from("out/"));
+        assert_eq!(args.combine.name, "a");
+        assert_eq!(args.combine.subtitle_format, Format::WebVtt);
+        assert_eq!(args.combine.output.container, "m4a");
+        assert_eq!(args.combine.output.bitrate.as_deref(), Some("128k"));
+        assert_eq!(args.split.noise_db, -50.0);
+        assert_eq!(args.split.padding, Duration::from_millis(100));
+        assert!(args.force);
+
+        assert!(parse("--audio a.mp3").is_err());
+        assert!(parse("--audio a.mp3 --srt a.srt --start both").is_err());
+        assert!(parse("--audio a.mp3 --srt a.srt --format doc").is_err());
+        assert!(parse("--audio a.mp3 --srt").is_err());
+    }
+}
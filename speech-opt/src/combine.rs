@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::ffopt::{self, OutputOptions};
+use crate::progress::Progress;
+use crate::subtitle::{self, Cue, Subtitles};
+
+/// 合成时的开始时间和时长取自音频还是字幕
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ItemType {
+    Audio = 0,
+    Subtitle = 1,
+}
+
+impl From<i32> for ItemType {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => ItemType::Subtitle,
+            1 => ItemType::Audio,
+            _ => ItemType::Subtitle,
+        }
+    }
+}
+
+impl FromStr for ItemType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "audio" => Ok(ItemType::Audio),
+            "subtitle" => Ok(ItemType::Subtitle),
+            _ => Err(anyhow::anyhow!("expected audio or subtitle, got {}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CombineOptions {
+    pub start: ItemType,
+    pub duration: ItemType,
+    pub subtitle_format: subtitle::Format,
+    pub output: OutputOptions,
+    pub out_dir: PathBuf,
+    /// 输出文件的名称，不含扩展名
+    pub name: String,
+    /// 存放裁剪出的片段
+    pub temp_dir: PathBuf,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    pub audio_pieces: usize,
+    pub subtitle_cues: usize,
+    /// 实际合成的条数，取两者中较少的
+    pub combined: usize,
+    pub audio_output: PathBuf,
+    pub subtitle_output: PathBuf,
+}
+
+/// 按照 `pieces` 裁剪音频，与字幕一一对应后合成音频和字幕。
+/// 片段与字幕条数不同时只合成前面对应得上的部分。
+/// 失败或取消时不留下裁剪出的片段和不完整的输出。
+pub fn combine(
+    audio: &PathBuf,
+    pieces: &[(Duration, Duration)],
+    subtitles: &Subtitles,
+    options: &CombineOptions,
+    progress: &Progress,
+) -> Result<Summary> {
+    std::fs::create_dir_all(&options.temp_dir)?;
+    std::fs::create_dir_all(&options.out_dir)?;
+
+    let audio_info = ffopt::cut_audio2(audio, pieces, &options.temp_dir, progress)?;
+    let result = combine_pieces(&audio_info, subtitles, options, progress);
+    for (_, _, piece) in &audio_info {
+        let _ = std::fs::remove_file(piece);
+    }
+    result
+}
+
+fn combine_pieces(
+    audio_info: &[(Duration, Duration, PathBuf)],
+    subtitles: &Subtitles,
+    options: &CombineOptions,
+    progress: &Progress,
+) -> Result<Summary> {
+    let count = usize::min(audio_info.len(), subtitles.cues.len());
+
+    let mut audio_joininfo = Vec::new();
+    let mut result = Subtitles {
+        cues: Vec::new(),
+        ass_header: subtitles.ass_header.clone(),
+    };
+    let mut length = Duration::ZERO;
+    for (cue, (audio_start, audio_duration, audio)) in subtitles.cues.iter().zip(audio_info) {
+        let start = match options.start {
+            ItemType::Audio => *audio_start,
+            ItemType::Subtitle => cue.start,
+        };
+
+        let duration = match options.duration {
+            ItemType::Audio => *audio_duration,
+            ItemType::Subtitle => cue.end.saturating_sub(cue.start),
+        };
+
+        length = length.max(start + *audio_duration);
+        audio_joininfo.push((start, audio.to_owned()));
+        result.cues.push(Cue {
+            start,
+            end: start + duration,
+            ..cue.clone()
+        });
+    }
+
+    let audio_output = output_path(options, &options.output.container);
+    ffopt::join_audios(
+        &audio_joininfo,
+        &audio_output,
+        &options.output,
+        length,
+        progress,
+    )?;
+
+    let subtitle_output = output_path(options, options.subtitle_format.extension());
+    if let Err(err) = subtitle::save(&result, &subtitle_output, options.subtitle_format) {
+        let _ = std::fs::remove_file(&audio_output);
+        return Err(err);
+    }
+
+    Ok(Summary {
+        audio_pieces: audio_info.len(),
+        subtitle_cues: subtitles.cues.len(),
+        combined: count,
+        audio_output,
+        subtitle_output,
+    })
+}
+
+fn output_path(options: &CombineOptions, extension: &str) -> PathBuf {
+    Path::new(&options.out_dir).join(format!("{}.{}", options.name, extension))
+}
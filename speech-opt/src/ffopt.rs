@@ -1,80 +1,293 @@
-use std::ffi::OsStr;
-use std::path::PathBuf;
-use std::process::Command;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::Result;
-use regex::Regex;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
 
-/// 获取音频的静音间隔，「duration」指的是静音判断间隔，实际调用如下命令：
+use crate::progress::{run_ffmpeg, Progress, Stage};
+
+/// 静音判断的默认音量阈值，与 ffmpeg silencedetect 的默认值相同
+pub const NOISE_DB: f64 = -60.0;
+
+/// 计算音量时的分帧长度
+const FRAME: Duration = Duration::from_millis(5);
+
+/// 获取音频的静音间隔，「duration」指的是静音判断间隔。
+/// 音频直接在进程内解码，每一帧中所有声道的峰值都低于阈值即视为静音，
+/// 连续静音达到「duration」的区间才会返回，结果与下面的命令相同：
 /// ```bash
 /// ffmpeg -i 文件名 -af silencedetect=d=时间间隔 -f null -
 /// ```
+/// 音频结尾处的静音没有结束时间，不会返回。
+#[cfg(test)]
 pub fn detect_silence<P>(audio: P, duration: Duration) -> Result<Vec<(Duration, Duration)>>
 where
     P: AsRef<OsStr>,
 {
-    let ff = Command::new("ffmpeg")
-        .arg("-i")
-        .arg(audio.as_ref())
-        .arg("-af")
-        .arg(format!("silencedetect=d={}", duration.as_secs_f64()))
-        .arg("-f")
-        .arg("null")
-        .arg("-")
-        .output()?;
+    detect_silence_below(audio, duration, NOISE_DB, &Progress::default()).map(|x| x.0)
+}
 
-    let result = String::from_utf8_lossy(&ff.stderr);
+/// 同 `detect_silence`，音量低于「noise_db」分贝即视为静音，同时返回解码出的音频长度
+pub fn detect_silence_below<P>(
+    audio: P,
+    duration: Duration,
+    noise_db: f64,
+    progress: &Progress,
+) -> Result<(Vec<(Duration, Duration)>, Duration)>
+where
+    P: AsRef<OsStr>,
+{
+    let peaks = frame_peaks(Path::new(audio.as_ref()), progress)?;
+    Ok((silence_ranges(&peaks, duration, noise_db), peaks.duration))
+}
 
-    let regex_end = Regex::new(
-        r"\[silencedetect.*?silence_end: (?P<silence_end>.*?) \| silence_duration: (?P<silence_duration>.*)",
-    ).unwrap();
+/// 找出音量低于「noise_db」分贝且持续达到「duration」的区间
+fn silence_ranges(peaks: &Peaks, duration: Duration, noise_db: f64) -> Vec<(Duration, Duration)> {
+    let noise = 10f32.powf(noise_db as f32 / 20.0);
 
     let mut silence_range = Vec::new();
+    let mut silence_start = None;
+
+    for (i, peak) in peaks.peaks.iter().enumerate() {
+        let time = peaks.frame * i as u32;
+        if *peak < noise {
+            silence_start.get_or_insert(time);
+        } else if let Some(start) = silence_start.take() {
+            if time - start >= duration {
+                silence_range.push((start, time));
+            }
+        }
+    }
+
+    silence_range
+}
+
+/// 解码后的音量
+struct Peaks {
+    /// 分帧长度
+    frame: Duration,
+    /// 每一帧中所有声道采样的最大绝对值
+    peaks: Vec<f32>,
+    /// 解码出的音频长度
+    duration: Duration,
+}
+
+/// 解码音频，返回每一帧的音量和音频长度。
+/// symphonia 不支持的格式（如 Opus、WMA）先用 ffmpeg 转成 WAV 再解码：
+/// ```bash
+/// ffmpeg -i 文件名 -c:a pcm_f32le 临时文件.wav
+/// ```
+fn frame_peaks(audio: &Path, progress: &Progress) -> Result<Peaks> {
+    match decode_peaks(audio, progress) {
+        Err(err) if is_unsupported(&err) => {
+            let temp = std::env::temp_dir().join(format!("speech-opt-{}.wav", std::process::id()));
+            let args: [&OsStr; 5] = [
+                "-y".as_ref(),
+                "-i".as_ref(),
+                audio.as_ref(),
+                "-c:a".as_ref(),
+                PIECE_CODEC.as_ref(),
+            ];
+            let result = run_ffmpeg(args.iter().chain([&temp.as_os_str()]), progress, |_| {})
+                .and_then(|_| decode_peaks(&temp, progress));
+            let _ = std::fs::remove_file(&temp);
+            result
+        }
+        result => result,
+    }
+}
+
+fn is_unsupported(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<SymphoniaError>(),
+        Some(SymphoniaError::Unsupported(_))
+    )
+}
 
-    for line in result.lines() {
-        if let Some(cap) = regex_end.captures(line) {
-            let silence_end = cap.name("silence_end").unwrap().as_str().parse().unwrap();
-            let silence_end = Duration::from_secs_f64(silence_end);
+fn decode_peaks(audio: &Path, progress: &Progress) -> Result<Peaks> {
+    let file = File::open(audio)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
 
-            let silence_duration = cap
-                .name("silence_duration")
-                .unwrap()
-                .as_str()
-                .parse()
-                .unwrap();
+    let mut hint = Hint::new();
+    if let Some(extension) = audio.extension().and_then(|x| x.to_str()) {
+        hint.with_extension(extension);
+    }
 
-            let silence_duration = Duration::from_secs_f64(silence_duration);
-            let silence_start = silence_end - silence_duration;
+    let format_options = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, stream, &format_options, &MetadataOptions::default())?
+        .format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow::anyhow!("no audio track in {}", audio.display()))?;
+    let track_id = track.id;
+    let total_frames = track.codec_params.n_frames;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| anyhow::anyhow!("unknown sample rate of {}", audio.display()))?;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let frame_len = (sample_rate as u128 * FRAME.as_millis() / 1000).max(1) as usize;
+    let frame = Duration::from_secs_f64(frame_len as f64 / sample_rate as f64);
+
+    let mut peaks = Vec::new();
+    let mut peak = 0f32;
+    let mut count = 0;
+    let mut decoded_frames = 0u64;
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        progress.check()?;
+        if let Some(total) = total_frames {
+            progress.set_fraction(packet.ts() as f64 / total as f64);
+        }
 
-            silence_range.push((silence_start, silence_end));
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // 跳过损坏的数据包，和 ffmpeg 一样继续解码
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let capacity = buffer.as_ref().map(|x| x.capacity()).unwrap_or(0);
+        if capacity < decoded.capacity() * channels {
+            buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+        }
+        let buffer = buffer.as_mut().unwrap();
+        buffer.copy_interleaved_ref(decoded);
+
+        for samples in buffer.samples().chunks(channels) {
+            let loudest = samples.iter().fold(0f32, |max, x| max.max(x.abs()));
+            peak = peak.max(loudest);
+            count += 1;
+            decoded_frames += 1;
+            if count == frame_len {
+                peaks.push(peak);
+                peak = 0.0;
+                count = 0;
+            }
         }
     }
 
-    Ok(silence_range)
+    if count > 0 {
+        peaks.push(peak);
+    }
+
+    Ok(Peaks {
+        frame,
+        peaks,
+        duration: Duration::from_secs_f64(decoded_frames as f64 / sample_rate as f64),
+    })
 }
 
-/// 裁剪音频，实际调用如下命令：
+/// 裁剪出的片段使用无损的 WAV，只在合成时编码一次
+const PIECE_CODEC: &str = "pcm_f32le";
+const PIECE_EXTENSION: &str = "wav";
+
+/// 音频文件选择框的过滤条件，解码器不支持的格式会交给 ffmpeg
+pub const AUDIO_FILTER: &str =
+    "*.{mp3,wav,flac,ogg,oga,opus,m4a,mp4,aac,wma,webm,mka,aiff,aif,caf}";
+
+/// 裁剪音频并解码为无损的片段，「on_time」收到已处理的时长，实际调用如下命令：
 /// ```bash
-/// ffmpeg -ss 起始时间点 -i 输入文件 -c copy -t 时间间隔 输出文件
+/// ffmpeg -ss 起始时间点 -i 输入文件 -t 时间间隔 -c:a pcm_f32le 输出文件.wav
 /// ```
-pub fn cut_audio<P>(audio: P, start: Duration, duration: Duration, output: P) -> Result<()>
+pub fn cut_audio<P>(
+    audio: P,
+    start: Duration,
+    duration: Duration,
+    output: P,
+    progress: &Progress,
+    on_time: impl FnMut(Duration),
+) -> Result<()>
 where
     P: AsRef<OsStr>,
 {
-    let _ff = Command::new("ffmpeg")
-        .arg("-y")
-        .arg("-ss")
-        .arg(format!("{}", start.as_secs_f64()))
-        .arg("-i")
-        .arg(audio)
-        .args(["-c", "copy"])
-        .arg("-t")
-        .arg(format!("{}", duration.as_secs_f64()))
-        .arg(output)
-        .output()?;
+    let start = format!("{}", start.as_secs_f64());
+    let duration = format!("{}", duration.as_secs_f64());
+    let args: [&OsStr; 10] = [
+        "-y".as_ref(),
+        "-ss".as_ref(),
+        start.as_ref(),
+        "-i".as_ref(),
+        audio.as_ref(),
+        "-t".as_ref(),
+        duration.as_ref(),
+        "-c:a".as_ref(),
+        PIECE_CODEC.as_ref(),
+        output.as_ref(),
+    ];
+    run_ffmpeg(args, progress, on_time)
+}
+
+/// 合成音频的输出设置，为空的项使用 ffmpeg 对该格式的默认值
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OutputOptions {
+    /// 输出文件的扩展名，决定封装格式，如 `mp3`、`m4a`、`flac`
+    pub container: String,
+    /// 编码器，如 `libmp3lame`、`aac`、`libopus`
+    pub codec: Option<String>,
+    /// 码率，如 `192k`
+    pub bitrate: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// 声道布局，如 `mono`、`stereo`
+    pub channel_layout: Option<String>,
+}
 
-    Ok(())
+impl OutputOptions {
+    /// 导出对话框中可选的封装格式
+    pub const CONTAINERS: [&'static str; 6] = ["mp3", "m4a", "ogg", "opus", "flac", "wav"];
+
+    /// 导出对话框中可选的声道布局，第一项保持原样
+    pub const CHANNEL_LAYOUTS: [Option<&'static str>; 3] = [None, Some("mono"), Some("stereo")];
+
+    /// 编码相关的 ffmpeg 参数
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(codec) = &self.codec {
+            args.extend(["-c:a".to_owned(), codec.to_owned()]);
+        }
+        if let Some(bitrate) = &self.bitrate {
+            args.extend(["-b:a".to_owned(), bitrate.to_owned()]);
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            args.extend(["-ar".to_owned(), sample_rate.to_string()]);
+        }
+        args
+    }
+
+    /// 加在混音之后的滤镜
+    fn filter(&self) -> String {
+        match &self.channel_layout {
+            Some(layout) => format!(",aformat=channel_layouts={}", layout),
+            None => String::new(),
+        }
+    }
 }
 
 /// 按照时间点合成音频，实际调用如下命令：
@@ -85,11 +298,19 @@ where
 ///    [2]adelay=360000|360000[c];
 ///    [3]adelay=962000|962000[d];
 ///    [0][b][c][d]amix=4"
+///   -c:a 编码器 -b:a 码率 -ar 采样率
 /// 输出文件
 /// ```
-pub fn join_audios(info_list: &[(Duration, PathBuf)], output: &PathBuf) -> Result<()> {
-    let mut ff_command = Command::new("ffmpeg");
-    ff_command.arg("-y");
+/// 「length」是合成后的长度，用来计算进度。失败或取消时删除输出文件。
+pub fn join_audios(
+    info_list: &[(Duration, PathBuf)],
+    output: &PathBuf,
+    options: &OutputOptions,
+    length: Duration,
+    progress: &Progress,
+) -> Result<()> {
+    progress.set_stage(Stage::Joining, info_list.len());
+    let mut ff_command: Vec<OsString> = vec!["-y".into()];
     let mut filter_complex = Vec::new();
     let mut index_vec = Vec::new();
 
@@ -97,8 +318,8 @@ pub fn join_audios(info_list: &[(Duration, PathBuf)], output: &PathBuf) -> Resul
         let (start, audio) = &info_list[i];
         let start_spot = start.as_millis();
 
-        ff_command.arg("-i");
-        ff_command.arg(audio);
+        ff_command.push("-i".into());
+        ff_command.push(audio.into());
 
         filter_complex.push(format!(
             "[{}]adelay={}|{}[{}]",
@@ -111,20 +332,31 @@ pub fn join_audios(info_list: &[(Duration, PathBuf)], output: &PathBuf) -> Resul
         index_vec.push(format!("[a{}]", i));
     }
 
-    let amix = format!("{}amix={}", index_vec.join(""), info_list.len());
+    let amix = format!(
+        "{}amix={}{}",
+        index_vec.join(""),
+        info_list.len(),
+        options.filter()
+    );
     filter_complex.push(amix);
     let filter_complex = filter_complex.join(";");
 
-    ff_command.arg("-filter_complex");
-    ff_command.arg(filter_complex);
-
-    ff_command.arg(output);
+    ff_command.push("-filter_complex".into());
+    ff_command.push(filter_complex.into());
+    ff_command.extend(options.args().into_iter().map(OsString::from));
 
-    let _ff = ff_command.output()?;
+    ff_command.push(output.into());
 
-    Ok(())
+    let result = run_ffmpeg(ff_command, progress, |time| {
+        progress.set_fraction(time.as_secs_f64() / length.as_secs_f64())
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(output);
+    }
+    result
 }
 
+#[cfg(test)]
 fn get_duration_from_output(output: &str) -> Option<String> {
     let regex = regex::Regex::new(r"Duration: ([0-9:\.]*), start:.*bitrate:").unwrap();
     let caps = regex
@@ -134,15 +366,19 @@ fn get_duration_from_output(output: &str) -> Option<String> {
     caps
 }
 
-/// 获取音频的长度
+/// 获取音频的长度，切分音频时改用解码出的长度，只留作测试对照
 /// ```bash
 /// ffmpeg -i 音频名称
 /// ```
+#[cfg(test)]
 pub fn get_audio_duration<P>(audio: P) -> Result<Duration>
 where
     P: AsRef<OsStr>,
 {
-    let output = Command::new("ffmpeg").arg("-i").arg(audio).output()?;
+    let output = std::process::Command::new("ffmpeg")
+        .arg("-i")
+        .arg(audio)
+        .output()?;
     let output = String::from_utf8(output.stderr)?;
 
     let duration = get_duration_from_output(&output)
@@ -170,43 +406,119 @@ where
         .ok_or_else(|| anyhow::anyhow!("failed to get duration"))
 }
 
-pub fn get_audio_pieces<P>(audio: P, duration: Duration) -> Result<Vec<(Duration, Duration)>>
+/// 切分音频的参数
+#[derive(Clone, Copy, Debug)]
+pub struct SplitOptions {
+    /// 静音判断间隔
+    pub silence: Duration,
+    /// 音量低于此分贝即视为静音
+    pub noise_db: f64,
+    /// 片段的最短长度，更短的片段会并入相邻的片段
+    pub min_piece: Duration,
+    /// 片段前后保留的静音长度，不超过与相邻片段之间静音的一半
+    pub padding: Duration,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self {
+            silence: Duration::from_millis(280),
+            noise_db: NOISE_DB,
+            min_piece: Duration::ZERO,
+            padding: Duration::ZERO,
+        }
+    }
+}
+
+pub fn get_audio_pieces<P>(
+    audio: P,
+    options: &SplitOptions,
+    progress: &Progress,
+) -> Result<Vec<(Duration, Duration)>>
 where
     P: AsRef<OsStr>,
 {
-    let silence_pairs = detect_silence(&audio, duration)?;
+    progress.set_stage(Stage::Detecting, 0);
+    let (silence_pairs, end_time) =
+        detect_silence_below(audio, options.silence, options.noise_db, progress)?;
+    Ok(split_pieces(&silence_pairs, end_time, options))
+}
+
+/// 按照静音区间切分出片段，再合并过短的片段，加上前后的留白
+fn split_pieces(
+    silence_pairs: &[(Duration, Duration)],
+    end_time: Duration,
+    options: &SplitOptions,
+) -> Vec<(Duration, Duration)> {
     let mut timeline = Vec::new();
     timeline.push(Duration::from_millis(0));
     for (start, end) in silence_pairs {
-        timeline.push(start);
-        timeline.push(end);
+        timeline.push(*start);
+        timeline.push(*end);
     }
-
-    let end_time = get_audio_duration(&audio)?;
     timeline.push(end_time);
 
+    let mut pieces: Vec<(Duration, Duration)> = Vec::new();
+    for pair in timeline.chunks_exact(2) {
+        let (start, end) = (pair[0], pair[1].min(end_time));
+        // 开头或结尾的静音会留下空的片段
+        if end <= start {
+            continue;
+        }
+        let short = |(start, end): (Duration, Duration)| end - start < options.min_piece;
+        match pieces.last_mut() {
+            Some(last) if short(*last) || short((start, end)) => last.1 = end,
+            _ => pieces.push((start, end)),
+        }
+    }
+
     let mut result = Vec::new();
-    let audio_length = timeline.len() / 2;
-    for i in 0..audio_length {
-        result.push((timeline[i * 2], timeline[i * 2 + 1]));
+    for i in 0..pieces.len() {
+        let (start, end) = pieces[i];
+        let earliest = match i {
+            0 => Duration::ZERO,
+            _ => start - (start - pieces[i - 1].1) / 2,
+        };
+        let latest = match pieces.get(i + 1) {
+            Some(next) => end + (next.0 - end) / 2,
+            None => end_time,
+        };
+        let start = start.saturating_sub(options.padding).max(earliest);
+        let end = (end + options.padding).min(latest);
+        result.push((start, end));
     }
 
-    Ok(result)
+    result
 }
 
+/// 裁剪出所有片段，失败或取消时删除已经裁剪出的片段
 pub fn cut_audio2(
     audio: &PathBuf,
     audio_pieces: &[(Duration, Duration)],
     temp_dir: &PathBuf,
+    progress: &Progress,
 ) -> Result<Vec<(Duration, Duration, PathBuf)>> {
     let file_name = audio.file_name().unwrap();
+    let count = audio_pieces.len();
+    progress.set_stage(Stage::Cutting, count);
     let mut result = Vec::new();
-    for i in 0..audio_pieces.len() {
+    for i in 0..count {
         let (start, end) = audio_pieces[i];
         let dur = end - start;
-        let temp_file_name = format!("{}#{}.mp3", file_name.to_string_lossy(), i);
+        let temp_file_name = format!("{}#{}.{}", file_name.to_string_lossy(), i, PIECE_EXTENSION);
         let temp_output = temp_dir.join(temp_file_name);
-        cut_audio(audio, start, dur, &temp_output)?;
+        progress.set_piece(i + 1);
+        let cut = cut_audio(audio, start, dur, &temp_output, progress, |time| {
+            progress
+                .set_fraction((i as f64 + time.as_secs_f64() / dur.as_secs_f64()) / count as f64)
+        });
+        if let Err(err) = cut {
+            let _ = std::fs::remove_file(&temp_output);
+            for (_, _, piece) in &result {
+                let _ = std::fs::remove_file(piece);
+            }
+            return Err(err);
+        }
         result.push((start, dur, temp_output));
     }
     Ok(result)
@@ -247,6 +559,68 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_decoded_duration() {
+        let peaks = frame_peaks(Path::new("sample.mp3"), &Progress::default()).unwrap();
+        // 不含编码器填充，比 ffmpeg 从文件头估计的 12.86 秒略短
+        assert!((12.818 - peaks.duration.as_secs_f64()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_split_pieces() {
+        let secs = Duration::from_secs_f64;
+        let silence = vec![
+            (secs(0.0), secs(0.5)),
+            (secs(3.0), secs(4.0)),
+            (secs(4.2), secs(5.0)),
+            (secs(8.0), secs(8.4)),
+        ];
+        let end = secs(10.0);
+
+        let pieces = split_pieces(&silence, end, &SplitOptions::default());
+        assert_eq!(
+            pieces,
+            vec![
+                (secs(0.5), secs(3.0)),
+                (secs(4.0), secs(4.2)),
+                (secs(5.0), secs(8.0)),
+                (secs(8.4), secs(10.0)),
+            ]
+        );
+
+        let options = SplitOptions {
+            min_piece: secs(1.0),
+            padding: secs(0.3),
+            ..Default::default()
+        };
+        let pieces = split_pieces(&silence, end, &options);
+        assert_eq!(
+            pieces,
+            vec![
+                (secs(0.2), secs(4.5)),
+                (secs(4.7), secs(8.2)),
+                (secs(8.2), secs(10.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_options() {
+        let options = OutputOptions {
+            container: "m4a".to_owned(),
+            codec: Some("aac".to_owned()),
+            bitrate: Some("128k".to_owned()),
+            sample_rate: Some(44100),
+            channel_layout: Some("mono".to_owned()),
+        };
+        assert_eq!(
+            options.args(),
+            ["-c:a", "aac", "-b:a", "128k", "-ar", "44100"]
+        );
+        assert_eq!(options.filter(), ",aformat=channel_layouts=mono");
+        assert!(OutputOptions::default().args().is_empty());
+    }
+
     #[test]
     fn test_get_duration_from_output() {
         let output = "  Duration: 00:00:12.86, start: 0.046042, bitrate: 32 kb/s";
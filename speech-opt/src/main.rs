@@ -1,8 +1,10 @@
-use std::{path::PathBuf, time::Duration};
+use std::{cell::RefCell, path::PathBuf, rc::Rc, time::Duration};
 
+use combine::{CombineOptions, ItemType};
 use fltk::{prelude::*, *};
 use fltk_table::*;
-use srtlib::{Subtitle, Timestamp};
+use progress::Stage;
+use subtitle::{Cue, Subtitles};
 
 mod ui {
     pub mod mainform {
@@ -12,37 +14,37 @@ mod ui {
     pub mod combine_dialog {
         fl2rust_macro::include_ui!("./src/ui/combine_dialog.fl");
     }
+
+    pub mod progress_dialog {
+        fl2rust_macro::include_ui!("./src/ui/progress_dialog.fl");
+    }
 }
 
+mod cli;
+mod combine;
 mod ffopt;
+mod progress;
+mod subtitle;
 
 #[derive(Clone)]
 enum Message {
     LoadAudio,
     LoadSubtitle,
-    DetectSilence(i32),
+    DetectSilence,
     CopyToTTSMaker,
     ShowConbineDialog,
-    StartCombine(ItemType, ItemType),
+    StartCombine(ItemType, ItemType, subtitle::Format, ffopt::OutputOptions),
+    /// 后台检测完静音，带着检测后要开始的合成设置
+    Detected(
+        Result<Vec<(Duration, Duration)>, String>,
+        Option<(ItemType, ItemType, subtitle::Format, ffopt::OutputOptions)>,
+    ),
+    Combined(Result<combine::Summary, String>),
     SetTableRowLength,
 }
 
-#[derive(Clone, Debug)]
-#[repr(i32)]
-enum ItemType {
-    Audio = 0,
-    Subtitle = 1,
-}
-
-impl From<i32> for ItemType {
-    fn from(value: i32) -> Self {
-        match value {
-            0 => ItemType::Subtitle,
-            1 => ItemType::Audio,
-            _ => ItemType::Subtitle,
-        }
-    }
-}
+/// 正在后台运行的任务，以及刷新进度对话框的定时器
+type Task = Rc<RefCell<Option<(progress::Progress, app::TimeoutHandle)>>>;
 
 fn set_table_opt(table: &mut SmartTable, rows: i32, text_col_width: i32) {
     if let Some(input) = table.input() {
@@ -69,7 +71,90 @@ fn set_table_opt(table: &mut SmartTable, rows: i32, text_col_width: i32) {
     table.redraw();
 }
 
+fn non_empty(text: String) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+fn split_options(ui: &ui::mainform::UserInterface) -> ffopt::SplitOptions {
+    ffopt::SplitOptions {
+        silence: Duration::from_millis(ui.input_duration.value() as u64),
+        noise_db: ui.input_noise.value(),
+        min_piece: Duration::from_millis(ui.input_min_piece.value() as u64),
+        padding: Duration::from_millis(ui.input_padding.value() as u64),
+    }
+}
+
+fn stage_label(state: &progress::State) -> String {
+    match state.stage {
+        Stage::Starting => "Starting".to_owned(),
+        Stage::Detecting => "Detecting silence".to_owned(),
+        Stage::Cutting => format!("Cutting piece {} of {}", state.piece, state.pieces),
+        Stage::Joining => format!("Joining {} pieces", state.pieces),
+    }
+}
+
+/// 显示进度对话框，定时刷新到任务结束
+fn start_task(
+    progress_ui: &mut ui::progress_dialog::UserInterface,
+    task: &Task,
+) -> progress::Progress {
+    let progress = progress::Progress::default();
+    progress_ui
+        .box_stage
+        .set_label(&stage_label(&progress.state()));
+    progress_ui.progress_bar.set_value(0.0);
+    progress_ui.btn_cancel.activate();
+    progress_ui.window.show();
+
+    let handle = app::add_timeout3(0.1, {
+        let progress = progress.clone();
+        let mut box_stage = progress_ui.box_stage.clone();
+        let mut progress_bar = progress_ui.progress_bar.clone();
+        move |handle| {
+            let state = progress.state();
+            box_stage.set_label(&stage_label(&state));
+            progress_bar.set_value(state.fraction * 100.0);
+            app::repeat_timeout3(0.1, handle);
+        }
+    });
+    task.replace(Some((progress.clone(), handle)));
+    progress
+}
+
+/// 隐藏进度对话框，返回任务是否被取消
+fn finish_task(progress_ui: &mut ui::progress_dialog::UserInterface, task: &Task) -> bool {
+    progress_ui.window.hide();
+    match task.take() {
+        Some((progress, handle)) => {
+            app::remove_timeout3(handle);
+            progress.is_cancelled()
+        }
+        None => true,
+    }
+}
+
+/// 在后台检测静音，完成后发送 `Message::Detected`
+fn spawn_detect(
+    progress: progress::Progress,
+    audio: PathBuf,
+    options: ffopt::SplitOptions,
+    then: Option<(ItemType, ItemType, subtitle::Format, ffopt::OutputOptions)>,
+    sender: app::Sender<Message>,
+) {
+    std::thread::spawn(move || {
+        let pieces = ffopt::get_audio_pieces(&audio, &options, &progress)
+            .map_err(|err| format!("{:#}", err));
+        sender.send(Message::Detected(pieces, then));
+    });
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let app = app::App::default();
     let (sender, receiver) = fltk::app::channel();
 
@@ -85,18 +170,73 @@ fn main() {
         let mut window = dialog.window.clone();
         let choice_start = dialog.choice_start.clone();
         let choice_duration = dialog.choice_duration.clone();
+        let choice_format = dialog.choice_format.clone();
+        let choice_container = dialog.choice_container.clone();
+        let input_codec = dialog.input_codec.clone();
+        let input_bitrate = dialog.input_bitrate.clone();
+        let input_sample_rate = dialog.input_sample_rate.clone();
+        let choice_channels = dialog.choice_channels.clone();
         let sender = sender.clone();
         move |_| {
             let choice_start = choice_start.value();
             let choice_duration = choice_duration.value();
+            let choice_format = choice_format.value();
+            // empty keeps the sample rate of the input, a bad one keeps the dialog open
+            let sample_rate = input_sample_rate.value();
+            let sample_rate = match sample_rate.trim() {
+                "" => None,
+                value => match value.parse::<u32>() {
+                    Ok(rate) if rate > 0 => Some(rate),
+                    _ => {
+                        dialog::message_default(&format!(
+                            "Invalid sample rate {:?}, use a number like 44100",
+                            value
+                        ));
+                        return;
+                    }
+                },
+            };
+            let output = ffopt::OutputOptions {
+                container: ffopt::OutputOptions::CONTAINERS
+                    [choice_container.value().max(0) as usize]
+                    .to_owned(),
+                codec: non_empty(input_codec.value()),
+                bitrate: non_empty(input_bitrate.value()),
+                sample_rate,
+                channel_layout: ffopt::OutputOptions::CHANNEL_LAYOUTS
+                    [choice_channels.value().max(0) as usize]
+                    .map(|x| x.to_owned()),
+            };
             sender.send(Message::StartCombine(
                 choice_start.into(),
                 choice_duration.into(),
+                choice_format.into(),
+                output,
             ));
             window.hide();
         }
     });
 
+    // progress dialog, its Cancel button and close button both cancel the task
+    let mut progress_ui = ui::progress_dialog::UserInterface::make_window();
+    let task: Task = Rc::default();
+
+    let mut cancel = {
+        let task = task.clone();
+        let mut btn_cancel = progress_ui.btn_cancel.clone();
+        move || {
+            if let Some((progress, _)) = &*task.borrow() {
+                progress.cancel();
+                btn_cancel.deactivate();
+            }
+        }
+    };
+    progress_ui.btn_cancel.set_callback({
+        let mut cancel = cancel.clone();
+        move |_| cancel()
+    });
+    progress_ui.window.set_callback(move |_| cancel());
+
     let mut ui = ui::mainform::UserInterface::make_window();
 
     // Load smart table
@@ -106,7 +246,11 @@ fn main() {
     set_table_opt(&mut table, 1, 110);
     ui.table_parent.end();
 
+    // The last loaded subtitle, whose ASS styles are kept at export
+    let mut loaded = Subtitles::default();
+
     ui.btn_load_audio.emit(sender.clone(), Message::LoadAudio);
+    ui.btn_detect.emit(sender.clone(), Message::DetectSilence);
     ui.btn_load_subtitle
         .emit(sender.clone(), Message::LoadSubtitle);
     ui.btn_copy.emit(sender.clone(), Message::CopyToTTSMaker);
@@ -119,54 +263,49 @@ fn main() {
         if let Some(message) = receiver.recv() {
             match message {
                 Message::LoadAudio => {
-                    let filename = dialog::file_chooser("Choose Audio File", "*.mp3", ".", false);
+                    let filename =
+                        dialog::file_chooser("Choose Audio File", ffopt::AUDIO_FILTER, ".", false);
                     ui.input_file.set_value(&filename.unwrap_or("".to_owned()));
 
-                    let duration = ui.input_duration.value() as i32;
-                    sender.send(Message::DetectSilence(duration));
+                    sender.send(Message::DetectSilence);
                 }
 
-                Message::DetectSilence(duration) => {
+                Message::DetectSilence => {
                     let audio = PathBuf::from(ui.input_file.value());
-                    let duration = Duration::from_millis(duration as u64);
-
-                    match ffopt::detect_silence(audio, duration) {
-                        Ok(silence) => {
-                            let silence_count = silence.len();
-                            let audio_count = (silence_count + 1) as i32;
-                            ui.box_count.set_label(audio_count.to_string().as_str());
-                        }
-                        Err(err) => {
-                            println!("{}", err)
-                        }
-                    }
+                    let progress = start_task(&mut progress_ui, &task);
+                    spawn_detect(progress, audio, split_options(&ui), None, sender.clone());
                 }
 
                 Message::LoadSubtitle => {
-                    let filename =
-                        dialog::file_chooser("Choose Subtitle File", "*.srt", ".", false);
+                    let filename = dialog::file_chooser(
+                        "Choose Subtitle File",
+                        subtitle::Format::FILTER,
+                        ".",
+                        false,
+                    );
                     if filename.is_none() {
                         continue;
                     }
-                    match srtlib::Subtitles::parse_from_file(filename.unwrap(), None) {
+                    match subtitle::load(&PathBuf::from(filename.unwrap())) {
                         Ok(subtitles) => {
-                            let rows = subtitles.len() as i32;
+                            let rows = subtitles.cues.len() as i32;
                             set_table_opt(&mut table, rows, 400);
 
                             for row in 0..rows {
-                                let subtitle = &subtitles[row as usize];
+                                let cue = &subtitles.cues[row as usize];
                                 table.set_cell_value(
                                     row,
                                     0,
-                                    subtitle.start_time.to_string().as_str(),
+                                    subtitle::srt_timestamp(cue.start).as_str(),
                                 );
                                 table.set_cell_value(
                                     row,
                                     1,
-                                    subtitle.end_time.to_string().as_str(),
+                                    subtitle::srt_timestamp(cue.end).as_str(),
                                 );
-                                table.set_cell_value(row, 2, subtitle.text.to_string().as_str());
+                                table.set_cell_value(row, 2, cue.text.as_str());
                             }
+                            loaded = subtitles;
                         }
                         Err(error) => {
                             dialog::message_default(error.to_string().as_str());
@@ -190,22 +329,41 @@ fn main() {
                     dialog.window.show();
                 }
 
-                Message::StartCombine(combine_start, combine_duration) => {
-                    // Main work here
-                    let audio = ui.input_file.value();
-                    let audio = PathBuf::from(audio);
-                    let duration = ui.input_duration.value();
-                    let duration = Duration::from_millis(duration as u64);
+                Message::StartCombine(start, duration, subtitle_format, output) => {
+                    let audio = PathBuf::from(ui.input_file.value());
+                    let progress = start_task(&mut progress_ui, &task);
+                    spawn_detect(
+                        progress,
+                        audio,
+                        split_options(&ui),
+                        Some((start, duration, subtitle_format, output)),
+                        sender.clone(),
+                    );
+                }
 
-                    let temp_dir = PathBuf::from("./temp");
-                    let audio_pieces = ffopt::get_audio_pieces(&audio, duration).unwrap();
+                Message::Detected(pieces, then) => {
+                    if finish_task(&mut progress_ui, &task) {
+                        continue;
+                    }
+                    let audio_pieces = match (pieces, &then) {
+                        (Ok(pieces), _) => pieces,
+                        (Err(err), None) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                        (Err(err), Some(_)) => {
+                            dialog::message_default(&err);
+                            continue;
+                        }
+                    };
+                    ui.box_count
+                        .set_label(audio_pieces.len().to_string().as_str());
+                    let Some((start, duration, subtitle_format, output)) = then else {
+                        continue;
+                    };
 
                     let sub_data = table.data();
-
-                    let audio_len = audio_pieces.len();
-                    let sub_len = sub_data.len();
-
-                    if audio_len != sub_len {
+                    if audio_pieces.len() != sub_data.len() {
                         let choice = dialog::choice2_default(
                             "Audio and text length does not match. Are you sure to continue?",
                             "Yes",
@@ -219,55 +377,60 @@ fn main() {
                         }
                     }
 
-                    let result_count = usize::min(audio_len, sub_len);
-                    let audio_info =
-                        ffopt::cut_audio2(&audio, audio_pieces.as_slice(), &temp_dir).unwrap();
-                    let sub_info = {
-                        let mut sub_info = Vec::new();
-                        for item in sub_data {
-                            let (start, end, text) = (&item[0], &item[1], &item[2]);
-                            let start = ffopt::parse_timestamp(&start).unwrap();
-                            let end = ffopt::parse_timestamp(&end).unwrap();
-                            sub_info.push((start, end - start, text.to_owned()))
-                        }
-                        sub_info
+                    let mut subtitles = Subtitles {
+                        cues: Vec::new(),
+                        ass_header: loaded.ass_header.clone(),
                     };
-
-                    let mut audio_joininfo = Vec::new();
-                    let mut subs = srtlib::Subtitles::new();
-                    for i in 0..result_count {
-                        let (audio_start, audio_duration, audio) = &audio_info[i];
-                        let (sub_start, sub_duration, text) = &sub_info[i];
-
-                        let start = match combine_start {
-                            ItemType::Audio => audio_start.to_owned(),
-                            ItemType::Subtitle => sub_start.to_owned(),
-                        };
-
-                        let duration = match combine_duration {
-                            ItemType::Audio => audio_duration.to_owned(),
-                            ItemType::Subtitle => sub_duration.to_owned(),
+                    for (i, item) in sub_data.iter().enumerate() {
+                        let times = ffopt::parse_timestamp(&item[0])
+                            .and_then(|start| Ok((start, ffopt::parse_timestamp(&item[1])?)));
+                        let Ok((start, end)) = times else {
+                            dialog::message_default(&format!("Invalid time in row {}", i + 1));
+                            break;
                         };
+                        subtitles.cues.push(Cue {
+                            start,
+                            end,
+                            text: item[2].to_owned(),
+                            style: loaded.cues.get(i).and_then(|x| x.style.clone()),
+                        });
+                    }
+                    if subtitles.cues.len() != sub_data.len() {
+                        continue;
+                    }
 
-                        audio_joininfo.push((start, audio.to_owned()));
-
-                        let mut start_time = Timestamp::new(0, 0, 0, 0);
-                        start_time.add_milliseconds(start.as_millis() as i32);
-
-                        let mut end_time = Timestamp::new(0, 0, 0, 0);
-                        end_time.add_milliseconds((start + duration).as_millis() as i32);
+                    let audio = PathBuf::from(ui.input_file.value());
+                    let options = CombineOptions {
+                        start,
+                        duration,
+                        subtitle_format,
+                        output,
+                        out_dir: PathBuf::from("."),
+                        name: "result".to_owned(),
+                        temp_dir: PathBuf::from("./temp"),
+                    };
+                    let progress = start_task(&mut progress_ui, &task);
+                    let sender = sender.clone();
+                    std::thread::spawn(move || {
+                        let summary = combine::combine(
+                            &audio,
+                            &audio_pieces,
+                            &subtitles,
+                            &options,
+                            &progress,
+                        )
+                        .map_err(|err| format!("{:#}", err));
+                        sender.send(Message::Combined(summary));
+                    });
+                }
 
-                        subs.push(Subtitle {
-                            num: i,
-                            start_time,
-                            end_time,
-                            text: text.to_owned(),
-                        })
+                Message::Combined(summary) => {
+                    if finish_task(&mut progress_ui, &task) {
+                        continue;
+                    }
+                    if let Err(err) = summary {
+                        dialog::message_default(&err);
                     }
-
-                    ffopt::join_audios(audio_joininfo.as_slice(), &PathBuf::from("./result.mp3"))
-                        .unwrap();
-                    subs.write_to_file("./result.srt", None).unwrap();
                 }
 
                 Message::SetTableRowLength => {
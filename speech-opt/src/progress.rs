@@ -0,0 +1,189 @@
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// 后台任务当前在做的事
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stage {
+    #[default]
+    Starting,
+    Detecting,
+    Cutting,
+    Joining,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct State {
+    pub stage: Stage,
+    /// 正在处理第几个片段，从 1 开始
+    pub piece: usize,
+    pub pieces: usize,
+    /// 当前阶段完成的比例，0 到 1
+    pub fraction: f64,
+}
+
+/// 后台任务的进度，界面线程读取它来显示进度，也通过它取消任务
+#[derive(Clone, Default)]
+pub struct Progress {
+    cancelled: Arc<AtomicBool>,
+    state: Arc<Mutex<State>>,
+}
+
+/// 任务被取消时返回的错误
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl Progress {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// 已取消时返回 `Cancelled` 错误
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(Cancelled.into()),
+            false => Ok(()),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        *self.state.lock().unwrap()
+    }
+
+    pub fn set_stage(&self, stage: Stage, pieces: usize) {
+        *self.state.lock().unwrap() = State {
+            stage,
+            piece: 0,
+            pieces,
+            fraction: 0.0,
+        };
+    }
+
+    pub fn set_piece(&self, piece: usize) {
+        self.state.lock().unwrap().piece = piece;
+    }
+
+    pub fn set_fraction(&self, fraction: f64) {
+        self.state.lock().unwrap().fraction = fraction.clamp(0.0, 1.0);
+    }
+}
+
+/// 运行 ffmpeg，每读到一次 `-progress` 输出的处理时间就调用「on_time」。
+/// 取消时结束 ffmpeg 并返回 `Cancelled`，失败时返回它输出的最后一行错误。
+/// 调用时不要再加 `-progress`，这里会加上：
+/// ```bash
+/// ffmpeg -progress pipe:1 -nostats 其他参数
+/// ```
+pub fn run_ffmpeg<I, S>(
+    args: I,
+    progress: &Progress,
+    mut on_time: impl FnMut(Duration),
+) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut child = Command::new("ffmpeg")
+        .args(["-progress", "pipe:1", "-nostats"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(|x| x.ok()) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output);
+        output
+    });
+
+    loop {
+        if progress.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Cancelled.into());
+        }
+        match receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(line) => {
+                if let Some(time) = parse_progress_time(&line) {
+                    on_time(time);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    let status = child.wait()?;
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let message = stderr.lines().last().unwrap_or_default();
+        return Err(anyhow::anyhow!("ffmpeg failed: {}", message));
+    }
+    Ok(())
+}
+
+/// `-progress` 输出的 `out_time_us`，旧版本的 `out_time_ms` 其实也是微秒
+fn parse_progress_time(line: &str) -> Option<Duration> {
+    let (key, value) = line.split_once('=')?;
+    match key {
+        "out_time_us" | "out_time_ms" => value.trim().parse().ok().map(Duration::from_micros),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress_time() {
+        assert_eq!(
+            parse_progress_time("out_time_us=1500000"),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            parse_progress_time("out_time_ms=20000"),
+            Some(Duration::from_millis(20))
+        );
+        // 开始时可能还没有时间
+        assert_eq!(parse_progress_time("out_time_us=N/A"), None);
+        assert_eq!(parse_progress_time("progress=continue"), None);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let progress = Progress::default();
+        assert!(progress.check().is_ok());
+        progress.cancel();
+        let err = progress.check().unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+    }
+}
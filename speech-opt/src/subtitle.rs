@@ -0,0 +1,511 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// LRC 的最后一句没有结束时间时，按这个长度算
+const LRC_LAST_CUE: Duration = Duration::from_secs(3);
+
+/// 没有 ASS 文件头可以沿用时，导出 ASS 使用的文件头
+const ASS_HEADER: &str = "[Script Info]
+ScriptType: v4.00+
+WrapStyle: 0
+ScaledBorderAndShadow: yes
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Srt,
+    WebVtt,
+    /// ASS 和 SSA
+    Ass,
+    Lrc,
+    /// 纯文本，一行一句，没有时间
+    Txt,
+}
+
+impl Format {
+    /// 与导出对话框中的选项顺序相同
+    pub const ALL: [Format; 5] = [
+        Format::Srt,
+        Format::WebVtt,
+        Format::Ass,
+        Format::Lrc,
+        Format::Txt,
+    ];
+
+    /// 文件选择框的过滤条件
+    pub const FILTER: &'static str = "*.{srt,vtt,ass,ssa,lrc,txt}";
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        Format::from_extension(path.extension()?.to_str()?)
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        match extension.to_lowercase().as_str() {
+            "srt" => Some(Format::Srt),
+            "vtt" => Some(Format::WebVtt),
+            "ass" | "ssa" => Some(Format::Ass),
+            "lrc" => Some(Format::Lrc),
+            "txt" => Some(Format::Txt),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Srt => "srt",
+            Format::WebVtt => "vtt",
+            Format::Ass => "ass",
+            Format::Lrc => "lrc",
+            Format::Txt => "txt",
+        }
+    }
+}
+
+impl From<i32> for Format {
+    fn from(value: i32) -> Self {
+        Format::ALL
+            .get(value as usize)
+            .copied()
+            .unwrap_or(Format::Srt)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+    /// ASS 的样式名，其他格式为空
+    pub style: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Subtitles {
+    pub cues: Vec<Cue>,
+    /// ASS 文件中 `[Events]` 之前的部分，包括脚本信息和样式，导出 ASS 时沿用
+    pub ass_header: Option<String>,
+}
+
+/// 读取字幕文件，格式由扩展名决定
+pub fn load(path: &Path) -> Result<Subtitles> {
+    let format = Format::from_path(path)
+        .ok_or_else(|| anyhow::anyhow!("unknown subtitle format: {}", path.display()))?;
+    let text = fs::read_to_string(path)?;
+    parse(&text, format)
+}
+
+/// 写入字幕文件
+pub fn save(subtitles: &Subtitles, path: &Path, format: Format) -> Result<()> {
+    fs::write(path, write(subtitles, format))?;
+    Ok(())
+}
+
+pub fn parse(text: &str, format: Format) -> Result<Subtitles> {
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    match format {
+        Format::Srt | Format::WebVtt => parse_blocks(&text),
+        Format::Ass => parse_ass(&text),
+        Format::Lrc => parse_lrc(&text),
+        Format::Txt => Ok(Subtitles {
+            cues: text
+                .lines()
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(|x| Cue {
+                    text: x.to_owned(),
+                    ..Default::default()
+                })
+                .collect(),
+            ass_header: None,
+        }),
+    }
+}
+
+pub fn write(subtitles: &Subtitles, format: Format) -> String {
+    let mut output = String::new();
+    match format {
+        Format::Srt => {
+            for (i, cue) in subtitles.cues.iter().enumerate() {
+                let _ = write!(
+                    output,
+                    "{}\n{} --> {}\n{}\n\n",
+                    i + 1,
+                    format_time(cue.start, ','),
+                    format_time(cue.end, ','),
+                    cue.text
+                );
+            }
+        }
+        Format::WebVtt => {
+            output.push_str("WEBVTT\n\n");
+            for cue in &subtitles.cues {
+                let _ = write!(
+                    output,
+                    "{} --> {}\n{}\n\n",
+                    format_time(cue.start, '.'),
+                    format_time(cue.end, '.'),
+                    cue.text
+                );
+            }
+        }
+        Format::Ass => write_ass(subtitles, &mut output),
+        Format::Lrc => {
+            for (i, cue) in subtitles.cues.iter().enumerate() {
+                let _ = writeln!(
+                    output,
+                    "[{}]{}",
+                    format_lrc_time(cue.start),
+                    cue.text.replace('\n', " ")
+                );
+                // 与下一句之间有间隔时，用一个空行标出结束时间
+                let gap = match subtitles.cues.get(i + 1) {
+                    Some(next) => next.start > cue.end,
+                    None => true,
+                };
+                if gap {
+                    let _ = writeln!(output, "[{}]", format_lrc_time(cue.end));
+                }
+            }
+        }
+        Format::Txt => {
+            for cue in &subtitles.cues {
+                let _ = writeln!(output, "{}", cue.text.replace('\n', " "));
+            }
+        }
+    }
+    output
+}
+
+/// 以表格中使用的 SRT 格式显示时间，如 `00:00:04,960`
+pub fn srt_timestamp(time: Duration) -> String {
+    format_time(time, ',')
+}
+
+fn format_time(time: Duration, separator: char) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+fn format_lrc_time(time: Duration) -> String {
+    let centis = time.as_millis() / 10;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centis / 6000,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+fn format_ass_time(time: Duration) -> String {
+    let centis = time.as_millis() / 10;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+/// 解析 `时:分:秒,毫秒`、`分:秒.毫秒` 等格式的时间，小数部分按小数计算，
+/// 所以 ASS 的 `0:00:01.50` 是 1.5 秒
+fn parse_time(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let (clock, fraction) = match text.find([',', '.']) {
+        Some(i) => (&text[..i], &text[i + 1..]),
+        None => (text, ""),
+    };
+
+    let mut seconds = 0u64;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<u64>().ok()?;
+    }
+
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // 补齐或截断到纳秒
+    let nanos = format!("{:0<9}", fraction)[..9].parse::<u32>().ok()?;
+
+    Some(Duration::new(seconds, nanos))
+}
+
+/// SRT 和 WebVTT 都由空行分隔的块组成，每块一个时间行和若干文本行
+fn parse_blocks(text: &str) -> Result<Subtitles> {
+    let mut cues = Vec::new();
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|x| x.trim().is_empty());
+        // 序号、WebVTT 的标识符，或是 WEBVTT、NOTE、STYLE 这些没有时间的块
+        let Some(timing) = lines.by_ref().find(|x| x.contains("-->")) else {
+            continue;
+        };
+        let (start, end) = timing
+            .split_once("-->")
+            .ok_or_else(|| anyhow::anyhow!("invalid timing: {}", timing))?;
+        // WebVTT 的时间后面可能跟着位置等设置
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (start, end) = parse_time(start)
+            .zip(parse_time(end))
+            .ok_or_else(|| anyhow::anyhow!("invalid timing: {}", timing))?;
+        cues.push(Cue {
+            start,
+            end,
+            text: lines.collect::<Vec<_>>().join("\n").trim().to_owned(),
+            style: None,
+        });
+    }
+    Ok(Subtitles {
+        cues,
+        ass_header: None,
+    })
+}
+
+/// 只读取 `[Events]` 中的 Dialogue，文本中的 `{\...}` 特效标签会被去掉
+fn parse_ass(text: &str) -> Result<Subtitles> {
+    let events = text
+        .find("[Events]")
+        .ok_or_else(|| anyhow::anyhow!("no [Events] section"))?;
+    let header = text[..events].trim_end().to_owned() + "\n";
+
+    let mut fields: Vec<String> = Vec::new();
+    let mut cues = Vec::new();
+    for line in text[events..].lines() {
+        if line.starts_with('[') && !line.starts_with("[Events]") {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format.split(',').map(|x| x.trim().to_lowercase()).collect();
+            continue;
+        }
+        let Some(dialogue) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+        if fields.is_empty() {
+            anyhow::bail!("Dialogue before Format in [Events]");
+        }
+
+        // 文本是最后一个字段，其中可以有逗号
+        let values: Vec<&str> = dialogue.splitn(fields.len(), ',').collect();
+        let field = |name: &str| {
+            fields
+                .iter()
+                .position(|x| x == name)
+                .and_then(|i| values.get(i))
+                .map(|x| x.trim())
+        };
+        let time = |name: &str| {
+            field(name)
+                .and_then(parse_time)
+                .ok_or_else(|| anyhow::anyhow!("invalid {}: {}", name, line))
+        };
+
+        cues.push(Cue {
+            start: time("start")?,
+            end: time("end")?,
+            text: strip_ass_tags(field("text").unwrap_or_default()),
+            style: field("style").map(|x| x.to_owned()),
+        });
+    }
+
+    Ok(Subtitles {
+        cues,
+        ass_header: Some(header),
+    })
+}
+
+fn strip_ass_tags(text: &str) -> String {
+    let mut result = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '{' => in_tag = true,
+            '}' if in_tag => in_tag = false,
+            c if !in_tag => result.push(c),
+            _ => {}
+        }
+    }
+    result
+        .replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ")
+}
+
+fn write_ass(subtitles: &Subtitles, output: &mut String) {
+    let header = subtitles.ass_header.as_deref().unwrap_or(ASS_HEADER);
+    // SSA 的第一个字段是 Marked，ASS 是 Layer
+    let ssa = header.contains("[V4 Styles]");
+    output.push_str(header);
+    output.push_str("\n[Events]\n");
+    output.push_str(match ssa {
+        true => {
+            "Format: Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n"
+        }
+        false => {
+            "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n"
+        }
+    });
+    for cue in &subtitles.cues {
+        let _ = writeln!(
+            output,
+            "Dialogue: {},{},{},{},,0,0,0,,{}",
+            if ssa { "Marked=0" } else { "0" },
+            format_ass_time(cue.start),
+            format_ass_time(cue.end),
+            cue.style.as_deref().unwrap_or("Default"),
+            cue.text.replace('\n', "\\N")
+        );
+    }
+}
+
+/// 每行可以有多个时间标签，空文本的时间标签是上一句的结束时间，
+/// 其他的如 `[ar:歌手]` 会被忽略，`[offset:毫秒]` 会提前所有时间
+fn parse_lrc(text: &str) -> Result<Subtitles> {
+    let mut offset = 0i64;
+    let mut stamps: Vec<(Duration, String)> = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|x| x.split_once(']')) {
+            if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(0);
+            } else if let Some(time) = parse_time(tag) {
+                times.push(time);
+            }
+            rest = after;
+        }
+        for time in times {
+            stamps.push((time, rest.trim().to_owned()));
+        }
+    }
+    stamps.sort_by_key(|(time, _)| *time);
+
+    let shift = |time: Duration| match offset {
+        0.. => time.saturating_sub(Duration::from_millis(offset as u64)),
+        _ => time + Duration::from_millis(offset.unsigned_abs()),
+    };
+
+    let mut cues = Vec::new();
+    for (i, (start, text)) in stamps.iter().enumerate() {
+        if text.is_empty() {
+            continue;
+        }
+        let end = match stamps.get(i + 1) {
+            Some((next, _)) => *next,
+            None => *start + LRC_LAST_CUE,
+        };
+        cues.push(Cue {
+            start: shift(*start),
+            end: shift(end),
+            text: text.to_owned(),
+            style: None,
+        });
+    }
+
+    Ok(Subtitles {
+        cues,
+        ass_header: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: u64, end: u64, text: &str) -> Cue {
+        Cue {
+            start: Duration::from_millis(start),
+            end: Duration::from_millis(end),
+            text: text.to_owned(),
+            style: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("00:00:1,960"), Some(Duration::from_millis(1960)));
+        assert_eq!(parse_time("0:01:02.50"), Some(Duration::from_millis(62500)));
+        assert_eq!(parse_time("01:05.123"), Some(Duration::from_millis(65123)));
+        assert_eq!(parse_time("ar:someone"), None);
+    }
+
+    #[test]
+    fn test_load_sample() {
+        let subtitles = load(Path::new("sample.srt")).unwrap();
+        assert_eq!(subtitles.cues.len(), 3);
+        assert_eq!(subtitles.cues[0].start, Duration::from_millis(1960));
+        assert_eq!(subtitles.cues[2].end, Duration::from_millis(15290));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let subtitles = Subtitles {
+            cues: vec![cue(1000, 2500, "第一句"), cue(3000, 4000, "second, line")],
+            ass_header: None,
+        };
+        for format in [Format::Srt, Format::WebVtt, Format::Ass, Format::Lrc] {
+            let parsed = parse(&write(&subtitles, format), format).unwrap();
+            let expected: Vec<Cue> = subtitles
+                .cues
+                .iter()
+                .map(|x| Cue {
+                    style: (format == Format::Ass).then(|| "Default".to_owned()),
+                    ..x.clone()
+                })
+                .collect();
+            assert_eq!(parsed.cues, expected, "{:?}", format);
+        }
+
+        let parsed = parse(&write(&subtitles, Format::Txt), Format::Txt).unwrap();
+        assert_eq!(parsed.cues[1].text, "second, line");
+    }
+
+    #[test]
+    fn test_parse_webvtt() {
+        let text = "WEBVTT\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:02.000 align:start\nHello\nworld\n";
+        let subtitles = parse(text, Format::WebVtt).unwrap();
+        assert_eq!(subtitles.cues, vec![cue(1000, 2000, "Hello\nworld")]);
+    }
+
+    #[test]
+    fn test_parse_ass() {
+        let text = "[Script Info]\nScriptType: v4.00+\n\n[V4+ Styles]\nStyle: Big,Arial,40\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.50,0:00:03.00,Big,,0,0,0,,{\\i1}Hi{\\i0}, there\\Nnext\n";
+        let subtitles = parse(text, Format::Ass).unwrap();
+        assert_eq!(subtitles.cues[0].text, "Hi, there\nnext");
+        assert_eq!(subtitles.cues[0].start, Duration::from_millis(1500));
+        assert_eq!(subtitles.cues[0].style.as_deref(), Some("Big"));
+
+        let written = write(&subtitles, Format::Ass);
+        assert!(written.contains("Style: Big,Arial,40"));
+        assert!(written.contains("Dialogue: 0,0:00:01.50,0:00:03.00,Big,,0,0,0,,Hi, there\\Nnext"));
+    }
+
+    #[test]
+    fn test_parse_lrc() {
+        let text =
+            "[ar:someone]\n[offset:500]\n[00:01.00][00:05.00]again\n[00:02.00]once\n[00:03.00]\n";
+        let subtitles = parse(text, Format::Lrc).unwrap();
+        assert_eq!(
+            subtitles.cues,
+            vec![
+                cue(500, 1500, "again"),
+                cue(1500, 2500, "once"),
+                cue(4500, 7500, "again")
+            ]
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Sessions saved into one tar archive instead of a file per screenshot.
+//! Captures are appended as they are taken and the archive always ends with
+//! the end-of-archive marker, so it can be read by any tar tool at any time.
+//! A capture cut short by a crash is dropped when the archive is read or
+//! opened again.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use chrono::{DateTime, Local};
+
+pub const EXTENSION: &str = ".tar";
+
+const BLOCK: u64 = 512;
+
+/// Splits a manifest path like `SESSION.tar/NAME.png` into the archive and
+/// the name of the capture in it.
+pub fn split(file: &str) -> Option<(&str, &str)> {
+    let end = file.find(&format!("{EXTENSION}/"))? + EXTENSION.len();
+    Some((&file[..end], &file[end + 1..]))
+}
+
+/// A file in an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    /// Modification time, in seconds since the Unix epoch.
+    pub mtime: u64,
+    pub size: u64,
+    /// Where the data starts in the archive.
+    offset: u64,
+}
+
+/// Lists the complete members of the archive at `path`, and returns where the
+/// last of them ends.
+pub fn members(path: &Path) -> Result<(Vec<Member>, u64), String> {
+    let mut file =
+        File::open(path).map_err(|error| format!("Failed to open {}: {error}", path.display()))?;
+    let length = file
+        .metadata()
+        .map_err(|error| format!("Failed to read {}: {error}", path.display()))?
+        .len();
+    scan(&mut file, length).map_err(|error| format!("Failed to read {}: {error}", path.display()))
+}
+
+fn scan(file: &mut File, length: u64) -> std::io::Result<(Vec<Member>, u64)> {
+    let mut members = Vec::new();
+    let mut position = 0;
+    let mut header = [0; BLOCK as usize];
+    while position + BLOCK <= length {
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut header)?;
+        let Some((name, size, mtime)) = parse_header(&header) else {
+            // The end marker, or a header cut short.
+            break;
+        };
+        let end = position + BLOCK + padded(size);
+        if end > length {
+            break;
+        }
+        members.push(Member {
+            name,
+            mtime,
+            size,
+            offset: position + BLOCK,
+        });
+        position = end;
+    }
+    Ok((members, position))
+}
+
+/// Reads the data of `member` from the archive at `path`.
+pub fn read(path: &Path, member: &Member) -> Result<Vec<u8>, String> {
+    let error = |error: std::io::Error| format!("Failed to read {}: {error}", path.display());
+    let mut file = File::open(path).map_err(error)?;
+    file.seek(SeekFrom::Start(member.offset)).map_err(error)?;
+    let mut data = vec![0; member.size as usize];
+    file.read_exact(&mut data).map_err(error)?;
+    Ok(data)
+}
+
+/// Reads the file at `path`, which can also be a capture in an archive, like
+/// `FOLDER/SESSION.tar/NAME.png`.
+pub fn read_path(path: &Path) -> Result<Vec<u8>, String> {
+    let text = path.to_string_lossy();
+    let Some((archive, name)) = split(&text).filter(|(archive, _)| Path::new(archive).is_file())
+    else {
+        return std::fs::read(path)
+            .map_err(|error| format!("Failed to read {}: {error}", path.display()));
+    };
+
+    let archive = Path::new(archive);
+    let (members, _) = members(archive)?;
+    let member = members
+        .iter()
+        .rev()
+        .find(|member| member.name == name)
+        .ok_or_else(|| format!("{} is not in {}", name, archive.display()))?;
+    read(archive, member)
+}
+
+/// Appends captures to an archive.
+pub struct Writer {
+    file: File,
+    /// Where the next member goes, which is where the end marker starts.
+    end: u64,
+}
+
+impl Writer {
+    /// Opens the archive at `path`, creating it if needed. A member cut short
+    /// by a crash is cut off.
+    pub fn open(path: &Path) -> Result<Writer, String> {
+        let error = |error: std::io::Error| format!("Failed to open {}: {error}", path.display());
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(error)?;
+        let length = file.metadata().map_err(error)?.len();
+        let (_, end) = scan(&mut file, length).map_err(error)?;
+
+        let mut writer = Writer { file, end };
+        writer.finish().map_err(error)?;
+        Ok(writer)
+    }
+
+    /// Adds a file. It is on disk when this returns. The data goes first and
+    /// the header last, so a crash leaves either the whole file or none of it.
+    pub fn append(&mut self, name: &str, time: DateTime<Local>, data: &[u8]) -> Result<(), String> {
+        let header = header(name, data.len() as u64, time.timestamp().max(0) as u64)?;
+        let error = |error: std::io::Error| format!("Failed to write to the archive: {error}");
+
+        let start = self.end;
+        self.file
+            .seek(SeekFrom::Start(start + BLOCK))
+            .map_err(error)?;
+        self.file.write_all(data).map_err(error)?;
+        let padding = padded(data.len() as u64) - data.len() as u64;
+        self.file
+            .write_all(&vec![0; padding as usize])
+            .map_err(error)?;
+        self.end = start + BLOCK + padded(data.len() as u64);
+        self.finish().map_err(error)?;
+
+        self.file.seek(SeekFrom::Start(start)).map_err(error)?;
+        self.file.write_all(&header).map_err(error)?;
+        self.file.sync_data().map_err(error)
+    }
+
+    /// Writes the end marker after the last member and syncs the file.
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&[0; 2 * BLOCK as usize])?;
+        self.file.set_len(self.end + 2 * BLOCK)?;
+        self.file.sync_data()
+    }
+}
+
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK) * BLOCK
+}
+
+/// A ustar header for a regular file.
+fn header(name: &str, size: u64, mtime: u64) -> Result<[u8; BLOCK as usize], String> {
+    let mut header = [0; BLOCK as usize];
+
+    // Names longer than 100 bytes are split at a slash into a prefix.
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name),
+        _ => name
+            .char_indices()
+            .filter(|(index, c)| *c == '/' && *index <= 155 && name.len() - index - 1 <= 100)
+            .map(|(index, _)| (&name[..index], &name[index + 1..]))
+            .next()
+            .ok_or_else(|| format!("The file name \"{name}\" is too long for the archive"))?,
+    };
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|&byte| byte as u64).sum();
+    octal(&mut header[148..155], checksum);
+    Ok(header)
+}
+
+/// Writes `value` as zero padded octal digits followed by a NUL.
+fn octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{value:0width$o}");
+    field[..width].copy_from_slice(&digits.as_bytes()[digits.len() - width..]);
+    field[width] = 0;
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Name, size and modification time of the file a header describes, if the
+/// header is intact.
+fn parse_header(header: &[u8; BLOCK as usize]) -> Option<(String, u64, u64)> {
+    if header.iter().all(|&byte| byte == 0) {
+        return None;
+    }
+
+    let stored = parse_octal(&header[148..156])?;
+    let checksum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(index, &byte)| match index {
+            148..=155 => b' ' as u64,
+            _ => byte as u64,
+        })
+        .sum();
+    if stored != checksum {
+        return None;
+    }
+
+    let text = |field: &[u8]| {
+        let end = field
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..end]).into_owned()
+    };
+    let name = match text(&header[345..500]) {
+        prefix if prefix.is_empty() => text(&header[..100]),
+        prefix => format!("{prefix}/{}", text(&header[..100])),
+    };
+    Some((
+        name,
+        parse_octal(&header[124..136])?,
+        parse_octal(&header[136..148])?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split("20230301-093000-Desktop.tar/ts_09_30.png"),
+            Some(("20230301-093000-Desktop.tar", "ts_09_30.png"))
+        );
+        assert_eq!(
+            split("a.tar/2023/03/01.png"),
+            Some(("a.tar", "2023/03/01.png"))
+        );
+        assert_eq!(split("ts_09_30.png"), None);
+    }
+
+    #[test]
+    fn test_header() {
+        let header = header("screens/ts.png", 1000, 1_677_659_400).unwrap();
+        assert_eq!(
+            parse_header(&header),
+            Some(("screens/ts.png".to_owned(), 1000, 1_677_659_400))
+        );
+
+        let long = format!("{}/{}.png", "d".repeat(60), "f".repeat(80));
+        let header = super::header(&long, 1, 0).unwrap();
+        assert_eq!(parse_header(&header).unwrap().0, long);
+        assert!(super::header(&"f".repeat(120), 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_recover() {
+        let path = std::env::temp_dir().join(format!(
+            "take-screenshots-archive-{}.tar",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let time = Local.with_ymd_and_hms(2023, 3, 1, 9, 30, 0).unwrap();
+
+        let mut writer = Writer::open(&path).unwrap();
+        writer.append("a.png", time, b"first").unwrap();
+        writer.append("b.png", time, &[7; 700]).unwrap();
+        drop(writer);
+
+        let (members, end) = members(&path).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[1].name, "b.png");
+        assert_eq!(members[1].mtime, time.timestamp() as u64);
+        assert_eq!(read(&path, &members[0]).unwrap(), b"first");
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), end + 1024);
+
+        // Killed while writing the third capture.
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(end)).unwrap();
+        file.write_all(&header("c.png", 2000, 0).unwrap()).unwrap();
+        file.write_all(&[1; 300]).unwrap();
+        drop(file);
+        assert_eq!(super::members(&path).unwrap().0.len(), 2);
+
+        let mut writer = Writer::open(&path).unwrap();
+        writer.append("c.png", time, b"third").unwrap();
+        drop(writer);
+        let (members, _) = super::members(&path).unwrap();
+        assert_eq!(members.len(), 3);
+        assert_eq!(read(&path, &members[2]).unwrap(), b"third");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+//! Starting the daemon with the desktop session, through an XDG autostart
+//! entry or a systemd user service. Both run
+//! `take-screenshots daemon PROFILE`; the entry logs to a file, the service
+//! to the journal.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use directories::BaseDirs;
+
+use crate::daemon;
+
+/// Choices of the window, in the order of `Method::from_choice`.
+pub const CHOICES: &str = "Not at login|At login (autostart)|At login (systemd)";
+
+const ENTRY_NAME: &str = "take-screenshots.desktop";
+const SERVICE_NAME: &str = "take-screenshots.service";
+
+/// Both files carry this comment, so the profile can be read back.
+const PROFILE_COMMENT: &str = "# Profile: ";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// `~/.config/autostart/take-screenshots.desktop`, started by the desktop
+    /// environment.
+    Entry,
+    /// `~/.config/systemd/user/take-screenshots.service`, started with
+    /// `graphical-session.target` and restarted if it crashes.
+    Service,
+}
+
+impl Method {
+    pub fn from_choice(choice: i32) -> Option<Method> {
+        match choice {
+            1 => Some(Method::Entry),
+            2 => Some(Method::Service),
+            _ => None,
+        }
+    }
+
+    pub fn choice(method: Option<Method>) -> i32 {
+        match method {
+            None => 0,
+            Some(Method::Entry) => 1,
+            Some(Method::Service) => 2,
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Option<Method>, String> {
+        match value {
+            "entry" => Ok(Some(Method::Entry)),
+            "service" => Ok(Some(Method::Service)),
+            "off" => Ok(None),
+            _ => Err(format!(
+                "Unknown autostart method \"{value}\", use entry, service or off"
+            )),
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        let config = BaseDirs::new()?.config_dir().to_owned();
+        Some(match self {
+            Method::Entry => config.join("autostart").join(ENTRY_NAME),
+            Method::Service => config.join("systemd").join("user").join(SERVICE_NAME),
+        })
+    }
+}
+
+/// How the daemon starts with the session now, and with which profile.
+pub fn current() -> Option<(Method, String)> {
+    [Method::Entry, Method::Service]
+        .into_iter()
+        .find_map(|method| {
+            let contents = fs::read_to_string(method.path()?).ok()?;
+            Some((method, profile_of(&contents)?))
+        })
+}
+
+/// Makes the daemon start `profile` with the session, in place of whatever
+/// started before. Returns the file written.
+pub fn enable(method: Method, profile: &str) -> Result<PathBuf, String> {
+    if !cfg!(target_os = "linux") {
+        return Err("Starting with the session is only supported on Linux.".to_owned());
+    }
+
+    let executable =
+        std::env::current_exe().map_err(|error| format!("Failed to find this program: {error}"))?;
+    let executable = executable.to_string_lossy();
+    disable()?;
+
+    let path = method
+        .path()
+        .ok_or("Failed to find the configuration folder.")?;
+    let contents = match method {
+        Method::Entry => {
+            let log = daemon::log_path().ok_or("Failed to find the data folder.")?;
+            entry(&executable, profile, &log.to_string_lossy())
+        }
+        Method::Service => service(&executable, profile),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|error| format!("Failed to create {}: {error}", parent.display()))?;
+    }
+    fs::write(&path, contents)
+        .map_err(|error| format!("Failed to write {}: {error}", path.display()))?;
+
+    if method == Method::Service {
+        let enabled =
+            systemctl(&["daemon-reload"]).and_then(|_| systemctl(&["enable", SERVICE_NAME]));
+        if let Err(error) = enabled {
+            remove(&path)?;
+            return Err(error);
+        }
+    }
+    Ok(path)
+}
+
+/// Stops starting the daemon with the session. A running daemon is left
+/// alone.
+pub fn disable() -> Result<(), String> {
+    if let Some(path) = Method::Entry.path() {
+        remove(&path)?;
+    }
+    // The unit goes even when systemd can't be reached, it wasn't enabled
+    // then.
+    if let Some(path) = Method::Service.path().filter(|path| path.exists()) {
+        if let Err(error) = systemctl(&["disable", SERVICE_NAME]) {
+            println!("{error}");
+        }
+        remove(&path)?;
+        if let Err(error) = systemctl(&["daemon-reload"]) {
+            println!("{error}");
+        }
+    }
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to remove {}: {error}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn systemctl(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|error| format!("Failed to run systemctl: {error}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn entry(executable: &str, profile: &str, log: &str) -> String {
+    let exec: Vec<String> = [executable, "daemon", profile, "--log", log]
+        .iter()
+        .map(|arg| entry_quote(arg))
+        .collect();
+    format!(
+        "{PROFILE_COMMENT}{profile}\n\
+         [Desktop Entry]\n\
+         Type=Application\n\
+         Name=Take Screenshots\n\
+         Comment=Takes screenshots with the jobs of the \"{profile}\" profile\n\
+         Exec={}\n\
+         NoDisplay=true\n\
+         X-GNOME-Autostart-enabled=true\n",
+        exec.join(" ")
+    )
+}
+
+fn service(executable: &str, profile: &str) -> String {
+    let exec: Vec<String> = [executable, "daemon", profile]
+        .iter()
+        .map(|arg| service_quote(arg))
+        .collect();
+    format!(
+        "{PROFILE_COMMENT}{profile}\n\
+         [Unit]\n\
+         Description=Take Screenshots with the \"{profile}\" profile\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        exec.join(" ")
+    )
+}
+
+/// Quotes an argument of an `Exec` key. The quoting of the command line is
+/// escaped again as a string value, then "%" as a field code.
+fn entry_quote(arg: &str) -> String {
+    let mut quoted = String::new();
+    for c in arg.chars() {
+        match c {
+            '"' | '`' | '$' | '\\' => quoted.push_str(&format!("\\{c}")),
+            c => quoted.push(c),
+        }
+    }
+    format!("\"{}\"", quoted.replace('\\', "\\\\").replace('%', "%%"))
+}
+
+/// Quotes an argument of `ExecStart`, where "%" starts a specifier and "$"
+/// a variable.
+fn service_quote(arg: &str) -> String {
+    let quoted = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{quoted}\"")
+}
+
+fn profile_of(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(PROFILE_COMMENT))
+        .map(|profile| profile.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let entry = entry("/opt/my apps/take-screenshots", "Work day", "/home/a/log");
+        assert!(entry.contains(
+            "Exec=\"/opt/my apps/take-screenshots\" \"daemon\" \"Work day\" \"--log\" \"/home/a/log\"\n"
+        ));
+        assert_eq!(profile_of(&entry).as_deref(), Some("Work day"));
+
+        assert_eq!(entry_quote("a\"b$c"), "\"a\\\\\"b\\\\$c\"");
+        assert_eq!(entry_quote("100%"), "\"100%%\"");
+    }
+
+    #[test]
+    fn test_service() {
+        let service = service("/usr/bin/take-screenshots", "default");
+        assert!(
+            service.contains("ExecStart=\"/usr/bin/take-screenshots\" \"daemon\" \"default\"\n")
+        );
+        assert!(service.contains("Restart=on-failure"));
+        assert_eq!(profile_of(&service).as_deref(), Some("default"));
+
+        assert_eq!(service_quote("a\"b$c%"), "\"a\\\"b$$c%%\"");
+    }
+}
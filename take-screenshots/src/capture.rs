@@ -0,0 +1,732 @@
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    io::Cursor,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, Utc};
+use image::{imageops, DynamicImage, ImageOutputFormat, RgbaImage};
+use screenshots::Screen;
+use tokio::sync::Mutex;
+
+use crate::archive;
+use crate::cursor::{self, CursorImage, Pointer};
+use crate::i18n::trf;
+use crate::metadata::{self, Container, Metadata};
+use crate::resize::Resize;
+#[cfg(target_os = "linux")]
+use crate::wayland;
+
+pub struct Capture {
+    /// Where the capture is, `FOLDER/SESSION.tar/NAME` when it is in an
+    /// archive.
+    pub path: PathBuf,
+    pub time: DateTime<Local>,
+    pub screen_id: u32,
+    /// Pointer position on the saved image, when it is on it.
+    pub pointer: Option<Pointer>,
+    pub archived: Option<Archived>,
+}
+
+/// A capture saved into an archive, where no other program can open it.
+pub struct Archived {
+    pub archive: PathBuf,
+    /// Name of the capture in the archive.
+    pub member: String,
+    /// The saved file, for hooks that need one of their own.
+    pub data: Vec<u8>,
+}
+
+/// Where screenshots come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// screenshots-rs, for X11, Windows and macOS.
+    Screenshots,
+    /// wlr-screencopy, on wlroots based Wayland compositors.
+    #[cfg(target_os = "linux")]
+    WlrScreencopy,
+    /// The xdg-desktop-portal Screenshot interface, on other Wayland
+    /// compositors.
+    #[cfg(target_os = "linux")]
+    Portal,
+}
+
+impl Backend {
+    /// Picks the backend for the current desktop session.
+    pub fn detect() -> Backend {
+        #[cfg(target_os = "linux")]
+        if wayland::is_wayland_session() {
+            return if wayland::screencopy::is_supported() {
+                Backend::WlrScreencopy
+            } else {
+                Backend::Portal
+            };
+        }
+
+        Backend::Screenshots
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Screenshots => "screenshots-rs",
+            #[cfg(target_os = "linux")]
+            Backend::WlrScreencopy => "wlr-screencopy",
+            #[cfg(target_os = "linux")]
+            Backend::Portal => "xdg-desktop-portal",
+        }
+    }
+
+    /// Grabs the 1-based `screen`, or every screen the backend can see
+    /// without it. The portal always sees the whole desktop as one screen.
+    async fn grab(&self, screen: Option<usize>) -> Result<Vec<Frame>, String> {
+        match self {
+            Backend::Screenshots => tokio::task::spawn_blocking(move || grab_screens(screen))
+                .await
+                .map_err(|error| error.to_string())?,
+            #[cfg(target_os = "linux")]
+            Backend::WlrScreencopy => {
+                blocking(move || {
+                    wayland::screencopy::grab(screen).map(|outputs| {
+                        outputs
+                            .into_iter()
+                            .map(|output| Frame {
+                                image: output.image,
+                                screen_id: output.name,
+                                origin: output.position,
+                            })
+                            .collect()
+                    })
+                })
+                .await
+            }
+            #[cfg(target_os = "linux")]
+            Backend::Portal => {
+                blocking(|| {
+                    wayland::portal::grab().map(|image| {
+                        vec![Frame {
+                            image,
+                            screen_id: 0,
+                            origin: (0, 0),
+                        }]
+                    })
+                })
+                .await
+            }
+        }
+    }
+
+    /// The shortest time between motion samples the backend keeps up with, or
+    /// `None` when it can't sample the screen for motion. The portal goes
+    /// through a file for every screenshot, and some flash the screen.
+    pub fn min_sample_rate(&self) -> Option<Duration> {
+        match self {
+            Backend::Screenshots => Some(Duration::from_millis(100)),
+            #[cfg(target_os = "linux")]
+            Backend::WlrScreencopy => Some(Duration::from_millis(100)),
+            #[cfg(target_os = "linux")]
+            Backend::Portal => None,
+        }
+    }
+
+    /// Whether this is an X11 session, where `cursor::query` can read the
+    /// pointer and `metadata::active_window` the focused window.
+    pub fn is_x11(&self) -> bool {
+        cfg!(target_os = "linux") && *self == Backend::Screenshots
+    }
+}
+
+/// One grabbed screen.
+struct Frame {
+    image: RgbaImage,
+    screen_id: u32,
+    /// Position of the screen on the desktop.
+    origin: (i32, i32),
+}
+
+fn grab_screens(screen: Option<usize>) -> Result<Vec<Frame>, String> {
+    let mut screens = Screen::all().map_err(|error| error.to_string())?;
+    if let Some(screen) = screen {
+        let count = screens.len();
+        screens = vec![screen
+            .checked_sub(1)
+            .filter(|index| *index < count)
+            .map(|index| screens.swap_remove(index))
+            .ok_or_else(|| format!("Screen {screen} not found, there are {count}"))?];
+    }
+    screens
+        .iter()
+        .map(|screen| {
+            let image = screen.capture().map_err(|error| error.to_string())?;
+            let image = RgbaImage::from_raw(image.width(), image.height(), image.rgba().clone())
+                .ok_or("The captured image has an unexpected size")?;
+            Ok(Frame {
+                image,
+                screen_id: screen.display_info.id,
+                origin: (screen.display_info.x, screen.display_info.y),
+            })
+        })
+        .collect()
+}
+
+/// What one grab saw, shared by the jobs capturing at that moment.
+struct Grab {
+    frames: Vec<Frame>,
+    pointer: Option<(Pointer, CursorImage)>,
+    /// Title of the focused window.
+    window: Option<String>,
+}
+
+/// Jobs asking for a screenshot this soon after a grab get its frames instead
+/// of grabbing again.
+const REUSE: Duration = Duration::from_millis(250);
+
+/// The capture backend, shared by every job. Grabs are serialized so jobs
+/// never grab the display at the same time.
+pub struct Grabber {
+    backend: Backend,
+    last: Mutex<Option<(Instant, Arc<Grab>)>>,
+}
+
+impl Grabber {
+    pub fn new(backend: Backend) -> Self {
+        Grabber {
+            backend,
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Grabs the display once, to find whether it can be captured.
+    pub async fn check(&self) -> Result<(), String> {
+        self.grab().await.map(|_| ())
+    }
+
+    async fn grab(&self) -> Result<Arc<Grab>, String> {
+        let mut last = self.last.lock().await;
+        if let Some((grabbed, grab)) = &*last {
+            if grabbed.elapsed() < REUSE {
+                return Ok(grab.clone());
+            }
+        }
+
+        let frames = self.backend.grab(None).await?;
+        let (pointer, window) = match self.backend.is_x11() {
+            true => (
+                cursor::query()
+                    .map_err(|error| println!("Failed to read the pointer: {error}"))
+                    .ok(),
+                metadata::active_window()
+                    .map_err(|error| println!("Failed to read the focused window: {error}"))
+                    .ok()
+                    .flatten(),
+            ),
+            false => (None, None),
+        };
+
+        let grab = Arc::new(Grab {
+            frames,
+            pointer,
+            window,
+        });
+        *last = Some((Instant::now(), grab.clone()));
+        Ok(grab)
+    }
+    /// Grabs only the 1-based `screen`, or every screen without it, for
+    /// motion sampling. Samples come too often to share the last grab or to
+    /// read the pointer and the focused window along with them.
+    async fn sample(&self, screen: Option<usize>) -> Result<Grab, String> {
+        let _serialized = self.last.lock().await;
+        Ok(Grab {
+            frames: self.backend.grab(screen).await?,
+            pointer: None,
+            window: None,
+        })
+    }
+}
+
+/// Part of a screen, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Parses X11 style geometry, `WIDTHxHEIGHT+X+Y`.
+    fn parse(text: &str) -> Option<Region> {
+        let (size, offset) = text.split_once('+')?;
+        let (width, height) = size.split_once('x')?;
+        let (x, y) = offset.split_once('+')?;
+        let region = Region {
+            x: x.trim().parse().ok()?,
+            y: y.trim().parse().ok()?,
+            width: width.trim().parse().ok()?,
+            height: height.trim().parse().ok()?,
+        };
+        (region.width > 0 && region.height > 0).then_some(region)
+    }
+}
+
+/// What a job captures: a whole screen or a region of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Target {
+    /// 1-based index of the screen. `None` works when there is one screen.
+    pub screen: Option<usize>,
+    pub region: Option<Region>,
+}
+
+impl Target {
+    /// Parses `""` (the screen), `2` (the second screen), `800x600+0+0` (a
+    /// region of the screen) or `2:800x600+0+0` (a region of the second
+    /// screen).
+    pub fn parse(text: &str) -> Result<Target, String> {
+        let text = text.trim();
+        let error = || {
+            trf("Failed to parse the target \"{text}\".\nUse a screen number like \"2\", a region like \"800x600+0+0\" or both like \"2:800x600+0+0\".", &[("text", &text)])
+        };
+
+        let (screen, region) = match text.split_once(':') {
+            Some((screen, region)) => (Some(screen), Some(region)),
+            None if text.is_empty() => (None, None),
+            None if text.contains('x') => (None, Some(text)),
+            None => (Some(text), None),
+        };
+
+        let screen = match screen {
+            Some(screen) => match screen.trim().parse::<usize>() {
+                Ok(screen) if screen > 0 => Some(screen),
+                _ => return Err(error()),
+            },
+            None => None,
+        };
+        let region = match region {
+            Some(region) => Some(Region::parse(region).ok_or_else(error)?),
+            None => None,
+        };
+
+        Ok(Target { screen, region })
+    }
+
+    /// Cuts the target out of `grab`, with the cursor drawn in when
+    /// `cursor` is set.
+    fn select(&self, grab: &Grab, cursor: bool) -> Result<Selection, String> {
+        let frames = &grab.frames;
+        let frame = match self.screen {
+            Some(screen) => frames
+                .get(screen - 1)
+                .ok_or_else(|| format!("Screen {screen} not found, there are {}", frames.len()))?,
+            None if frames.len() == 1 => &frames[0],
+            None => {
+                return Err(format!(
+                    "Expected one screen, found {}. Pick one in the target of the job.",
+                    frames.len()
+                ))
+            }
+        };
+
+        let image = &frame.image;
+        let mut origin = frame.origin;
+        let mut image = match self.region {
+            Some(region) => {
+                if region.x.saturating_add(region.width) > image.width()
+                    || region.y.saturating_add(region.height) > image.height()
+                {
+                    return Err(format!(
+                        "The region {}x{}+{}+{} doesn't fit on the {}x{} screen",
+                        region.width,
+                        region.height,
+                        region.x,
+                        region.y,
+                        image.width(),
+                        image.height()
+                    ));
+                }
+                origin = (origin.0 + region.x as i32, origin.1 + region.y as i32);
+                imageops::crop_imm(image, region.x, region.y, region.width, region.height)
+                    .to_image()
+            }
+            None => image.clone(),
+        };
+
+        let mut pointer = None;
+        if let Some((on_desktop, cursor_image)) = &grab.pointer {
+            let x = on_desktop.x - origin.0;
+            let y = on_desktop.y - origin.1;
+            if (0..image.width() as i32).contains(&x) && (0..image.height() as i32).contains(&y) {
+                pointer = Some(Pointer {
+                    x,
+                    y,
+                    ..*on_desktop
+                });
+            }
+            if cursor {
+                cursor_image.draw(&mut image, origin);
+            }
+        }
+
+        Ok(Selection {
+            image,
+            screen_id: frame.screen_id,
+            origin,
+            pointer,
+        })
+    }
+}
+
+/// The part of a grab a job saves.
+struct Selection {
+    image: RgbaImage,
+    screen_id: u32,
+    /// Position of the image on the desktop.
+    origin: (i32, i32),
+    /// Where the pointer is on the image, if it is on it.
+    pointer: Option<Pointer>,
+}
+
+impl Selection {
+    /// The captured area on the desktop as `WxH+X+Y`.
+    fn geometry(&self) -> String {
+        format!(
+            "{}x{}{:+}{:+}",
+            self.image.width(),
+            self.image.height(),
+            self.origin.0,
+            self.origin.1
+        )
+    }
+}
+
+/// Set while a blocking backend runs on a worker thread.
+#[cfg(target_os = "linux")]
+static BLOCKING: AtomicBool = AtomicBool::new(false);
+
+/// Runs a blocking backend on a worker thread. Gives up after a while, as a
+/// portal waiting for a permission prompt nobody answers would otherwise stall
+/// the session. The thread can't be stopped, so no new grab starts until it
+/// returns, instead of piling up stuck threads.
+#[cfg(target_os = "linux")]
+async fn blocking<F>(grab: F) -> Result<Vec<Frame>, String>
+where
+    F: FnOnce() -> Result<Vec<Frame>, String> + Send + 'static,
+{
+    struct Done;
+    impl Drop for Done {
+        fn drop(&mut self) {
+            BLOCKING.store(false, Ordering::SeqCst);
+        }
+    }
+
+    if BLOCKING.swap(true, Ordering::SeqCst) {
+        return Err("The last screenshot is still waiting for the compositor".to_owned());
+    }
+    let grab = move || {
+        let _done = Done;
+        grab()
+    };
+    match tokio::time::timeout(Duration::from_secs(30), tokio::task::spawn_blocking(grab)).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(error)) => Err(error.to_string()),
+        Err(_) => Err("Timed out waiting for the screenshot".to_owned()),
+    }
+}
+
+/// Encodes `image` for `container`. JPEG has no alpha channel, so colour
+/// images lose it.
+fn encode(image: DynamicImage, container: Container) -> Result<Vec<u8>, String> {
+    let (image, format) = match container {
+        Container::Png => (image, ImageOutputFormat::Png),
+        Container::Jpeg => match image {
+            DynamicImage::ImageLuma8(_) => (image, ImageOutputFormat::Jpeg(90)),
+            image => (
+                DynamicImage::ImageRgb8(image.to_rgb8()),
+                ImageOutputFormat::Jpeg(90),
+            ),
+        },
+        Container::WebP => (image, ImageOutputFormat::WebP),
+    };
+
+    let mut buffer = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut buffer), format)
+        .map_err(|error| error.to_string())?;
+    Ok(buffer)
+}
+
+/// A screenshot that is taken but not saved yet.
+pub struct Sample {
+    pub time: DateTime<Local>,
+    /// Resized already, so samples held in memory are as small as they get.
+    pub image: DynamicImage,
+    pub pointer: Option<Pointer>,
+    metadata: Metadata,
+}
+
+/// What a job captures and where it saves it.
+pub struct Shot<'a> {
+    pub grabber: &'a Grabber,
+    pub target: Target,
+    /// Draw the mouse cursor into the screenshots.
+    pub cursor: bool,
+    pub resize: Resize,
+    /// `strftime` format of the file names.
+    pub format: &'a str,
+    pub output_folder: &'a str,
+    /// Name of the job.
+    pub name: &'a str,
+    /// Name of the session in the manifest.
+    pub session_id: &'a str,
+}
+
+/// Takes a screenshot and saves it into `archive` if there is one.
+pub async fn screenshot(
+    shot: &Shot<'_>,
+    archive: Option<&std::sync::Mutex<archive::Writer>>,
+) -> Result<Capture, String> {
+    let now: DateTime<Local> = Utc::now().into();
+    let grab = shot.grabber.grab().await?;
+    let sample = to_sample(shot, &grab, shot.target, now)?;
+    save(shot, sample, false, archive).await
+}
+
+/// Samples the screen of `shot` for motion, without saving it. The sample has
+/// no cursor, pointer or window title.
+pub async fn grab_sample(shot: &Shot<'_>) -> Result<Sample, String> {
+    let now: DateTime<Local> = Utc::now().into();
+    let grab = shot.grabber.sample(shot.target.screen).await?;
+    // Only the screen of the target was grabbed.
+    let target = Target {
+        screen: None,
+        ..shot.target
+    };
+    to_sample(shot, &grab, target, now)
+}
+
+fn to_sample(
+    shot: &Shot<'_>,
+    grab: &Grab,
+    target: Target,
+    now: DateTime<Local>,
+) -> Result<Sample, String> {
+    let selection = target.select(grab, shot.cursor)?;
+    let metadata = Metadata {
+        time: now,
+        screen: selection.screen_id,
+        geometry: selection.geometry(),
+        window: grab.window.clone(),
+        session: shot.session_id.to_owned(),
+        job: shot.name.to_owned(),
+    };
+    let (image, pointer) = shot.resize.apply(selection.image, selection.pointer);
+    Ok(Sample {
+        time: now,
+        image,
+        pointer,
+        metadata,
+    })
+}
+
+/// Saves `sample` under the name the format of `shot` gives its time. Frames
+/// of a burst are less than a second apart, so with `burst` the milliseconds
+/// are added to the name.
+pub async fn save(
+    shot: &Shot<'_>,
+    sample: Sample,
+    burst: bool,
+    archive: Option<&std::sync::Mutex<archive::Writer>>,
+) -> Result<Capture, String> {
+    let now = sample.time;
+    let mut filename = now.format(shot.format).to_string();
+    if burst {
+        filename = with_millis(&filename, now);
+    }
+
+    let archive_path = format!(
+        "{}/{}{}",
+        shot.output_folder,
+        shot.session_id,
+        archive::EXTENSION
+    );
+    let path = match archive {
+        // Where the capture would be if the archive were a folder, which is
+        // how the manifest refers to it.
+        Some(_) => PathBuf::from(format!("{archive_path}/{filename}")),
+        None => PathBuf::from(format!("{}/{filename}", shot.output_folder)),
+    };
+    let buffer = encode(sample.image, Container::from_path(&path))?;
+    let buffer = sample.metadata.embed(&buffer)?;
+    let archived = match archive {
+        Some(archive) => {
+            tokio::task::block_in_place(|| {
+                archive.lock().unwrap().append(&filename, now, &buffer)
+            })?;
+            Some(Archived {
+                archive: PathBuf::from(archive_path),
+                member: filename,
+                data: buffer,
+            })
+        }
+        None => {
+            tokio::fs::write(&path, &buffer)
+                .await
+                .map_err(|error| format!("Failed to write {}: {error}", path.display()))?;
+            None
+        }
+    };
+
+    Ok(Capture {
+        path,
+        time: now,
+        screen_id: sample.metadata.screen,
+        pointer: sample.pointer,
+        archived,
+    })
+}
+
+/// Adds the milliseconds of `time` to `filename`, before its extension.
+fn with_millis(filename: &str, time: DateTime<Local>) -> String {
+    let millis = time.timestamp_subsec_millis();
+    match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.contains('/') => {
+            format!("{stem}-{millis:03}.{extension}")
+        }
+        _ => format!("{filename}-{millis:03}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_with_millis() {
+        let time = Local.timestamp_millis_opt(1_677_663_005_042).unwrap();
+        assert_eq!(with_millis("ts_09_30_05.png", time), "ts_09_30_05-042.png");
+        assert_eq!(with_millis("a.b/ts", time), "a.b/ts-042");
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(Target::parse("").unwrap(), Target::default());
+        assert_eq!(
+            Target::parse("2").unwrap(),
+            Target {
+                screen: Some(2),
+                region: None
+            }
+        );
+        let region = Some(Region {
+            x: 10,
+            y: 20,
+            width: 800,
+            height: 600,
+        });
+        assert_eq!(
+            Target::parse("800x600+10+20").unwrap(),
+            Target {
+                screen: None,
+                region
+            }
+        );
+        assert_eq!(
+            Target::parse("1:800x600+10+20").unwrap(),
+            Target {
+                screen: Some(1),
+                region
+            }
+        );
+        assert!(Target::parse("0").is_err());
+        assert!(Target::parse("800x600").is_err());
+        assert!(Target::parse("0x600+0+0").is_err());
+    }
+
+    fn frame(width: u32, height: u32, screen_id: u32, origin: (i32, i32)) -> Frame {
+        Frame {
+            image: RgbaImage::new(width, height),
+            screen_id,
+            origin,
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let grab = Grab {
+            frames: vec![frame(100, 50, 7, (0, 0)), frame(40, 30, 9, (100, 0))],
+            pointer: None,
+            window: None,
+        };
+
+        assert!(Target::default().select(&grab, false).is_err());
+
+        let selection = Target::parse("2:20x10+20+20")
+            .unwrap()
+            .select(&grab, false)
+            .unwrap();
+        assert_eq!(
+            (selection.image.dimensions(), selection.screen_id),
+            ((20, 10), 9)
+        );
+        assert_eq!(selection.geometry(), "20x10+120+20");
+
+        assert!(Target::parse("2:20x10+21+20")
+            .unwrap()
+            .select(&grab, false)
+            .is_err());
+        assert!(Target::parse("3").unwrap().select(&grab, false).is_err());
+
+        let single = Grab {
+            frames: vec![frame(100, 50, 7, (0, 0))],
+            pointer: None,
+            window: None,
+        };
+        assert!(Target::default().select(&single, false).is_ok());
+    }
+
+    #[test]
+    fn test_select_pointer() {
+        let pointer = Pointer {
+            x: 125,
+            y: 22,
+            buttons: 1,
+        };
+        let cursor = CursorImage {
+            x: 125,
+            y: 22,
+            pixels: vec![0xffffffff],
+            width: 1,
+            height: 1,
+        };
+        let grab = Grab {
+            frames: vec![frame(100, 50, 7, (0, 0)), frame(40, 30, 9, (100, 0))],
+            pointer: Some((pointer, cursor)),
+            window: None,
+        };
+
+        let selection = Target::parse("2:20x10+20+20")
+            .unwrap()
+            .select(&grab, true)
+            .unwrap();
+        assert_eq!(
+            selection.pointer,
+            Some(Pointer {
+                x: 5,
+                y: 2,
+                buttons: 1
+            })
+        );
+        assert_eq!(selection.image.get_pixel(5, 2).0, [255, 255, 255, 255]);
+
+        let selection = Target::parse("1").unwrap().select(&grab, false).unwrap();
+        assert_eq!(selection.pointer, None);
+        assert_eq!(selection.image.get_pixel(25, 22).0, [0, 0, 0, 0]);
+    }
+}
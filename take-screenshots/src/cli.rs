@@ -0,0 +1,244 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use chrono::Local;
+
+use crate::autostart::{self, Method};
+use crate::capture::{Backend, Grabber};
+use crate::jobs::Jobs;
+#[cfg(feature = "ocr")]
+use crate::ocr;
+use crate::{daemon, metadata, profile, report, resume};
+
+const USAGE: &str = "Usage:
+  take-screenshots                  open the window
+  take-screenshots report FOLDER    write an HTML activity report for FOLDER
+  take-screenshots inspect FILE     print the capture details embedded in FILE
+  take-screenshots search FOLDER TEXT
+                                    list the screenshots in FOLDER showing TEXT,
+                                    from the text index of the jobs with OCR,
+                                    in builds with the ocr feature
+  take-screenshots resume           carry on the sessions that were interrupted,
+                                    without the window, until they end
+  take-screenshots daemon [PROFILE] [--log FILE]
+                                    run the jobs of PROFILE without the window,
+                                    logging to FILE if given
+  take-screenshots autostart entry|service|off [PROFILE]
+                                    start the daemon with PROFILE at login, from
+                                    an autostart entry or a systemd user service
+  take-screenshots ctl COMMAND [JOB]
+                                    control the running instance, COMMAND is one of
+                                    start, stop, pause, status or capture-now, and
+                                    applies to JOB or to every job";
+
+/// Runs a subcommand and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["report", folder] => match report::generate(Path::new(folder)) {
+            Ok(path) => {
+                println!("{}", path.display());
+                0
+            }
+            Err(error) => {
+                eprintln!("{error}");
+                1
+            }
+        },
+        ["inspect", file] => inspect(Path::new(file)),
+        #[cfg(feature = "ocr")]
+        ["search", folder, words @ ..] if !words.is_empty() => search(Path::new(folder), words),
+        ["resume"] => tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(resume_sessions())
+        }),
+        ["daemon", options @ ..] => match daemon_options(options) {
+            Some((profile, log)) => tokio::task::block_in_place(|| {
+                tokio::runtime::Handle::current().block_on(daemon::run(profile, log))
+            }),
+            None => {
+                eprintln!("{USAGE}");
+                2
+            }
+        },
+        ["autostart", method] => set_autostart(method, profile::DEFAULT),
+        ["autostart", method, profile] => set_autostart(method, profile),
+        ["ctl", command] => ctl(command, None),
+        ["ctl", command, job] => ctl(command, Some(job)),
+        ["help" | "--help" | "-h"] => {
+            println!("{USAGE}");
+            0
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            2
+        }
+    }
+}
+
+/// Prints the matches like `grep`, and exits with 1 when there are none.
+#[cfg(feature = "ocr")]
+fn search(folder: &Path, words: &[&str]) -> i32 {
+    match ocr::search(folder, &words.join(" ")) {
+        Ok(matches) if matches.is_empty() => 1,
+        Ok(matches) => {
+            for found in matches {
+                println!(
+                    "{}\t{}\t{}",
+                    found.time.format("%Y-%m-%d %H:%M:%S"),
+                    found.path.display(),
+                    found.snippet
+                );
+            }
+            0
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            2
+        }
+    }
+}
+
+/// Reads `[PROFILE] [--log FILE]`.
+fn daemon_options<'a>(options: &[&'a str]) -> Option<(&'a str, Option<&'a Path>)> {
+    let mut profile = None;
+    let mut log = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match *option {
+            "--log" => log = Some(Path::new(*options.next()?)),
+            option if option.starts_with('-') || profile.is_some() => return None,
+            option => profile = Some(option),
+        }
+    }
+    Some((profile.unwrap_or(profile::DEFAULT), log))
+}
+
+fn set_autostart(method: &str, profile: &str) -> i32 {
+    let result = Method::parse(method).and_then(|method| match method {
+        Some(method) => {
+            let profile = profile::check_name(profile)?;
+            if let Some(folder) = profile::folder() {
+                profile::load(&folder, profile)?;
+            }
+            autostart::enable(method, profile).map(|path| path.display().to_string())
+        }
+        None => autostart::disable().map(|_| "Not starting with the session".to_owned()),
+    });
+    match result {
+        Ok(message) => {
+            println!("{message}");
+            0
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            1
+        }
+    }
+}
+
+fn inspect(file: &Path) -> i32 {
+    let fields = std::fs::read(file)
+        .map_err(|error| format!("Failed to read {}: {error}", file.display()))
+        .and_then(|data| metadata::read(&data));
+    match fields {
+        Ok(fields) if fields.is_empty() => {
+            eprintln!("{} has no metadata", file.display());
+            1
+        }
+        Ok(fields) => {
+            for (key, value) in fields {
+                println!("{key}: {value}");
+            }
+            0
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            1
+        }
+    }
+}
+
+/// Resumes the interrupted sessions and waits for them to end. They can be
+/// controlled with `ctl` meanwhile.
+async fn resume_sessions() -> i32 {
+    #[cfg(unix)]
+    let listener = match crate::control::acquire() {
+        Ok(Some(listener)) => Some(listener),
+        Ok(None) => {
+            eprintln!("Take Screenshots is already running.");
+            return 1;
+        }
+        Err(error) => {
+            println!("{error}");
+            None
+        }
+    };
+
+    let states = resume::interrupted(Local::now());
+    if states.is_empty() {
+        println!("No interrupted sessions");
+    }
+
+    let jobs = Arc::new(Jobs::new(Arc::new(Grabber::new(Backend::detect()))));
+    let mut code = 0;
+    for state in states {
+        let session = state.session();
+        match jobs.resume(state) {
+            Ok(_) => println!("Resumed {session}"),
+            Err(error) => {
+                eprintln!("Failed to resume {session}: {error}");
+                code = 1;
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    if let Some(listener) = listener {
+        tokio::spawn(crate::control::serve(listener, jobs.clone(), None));
+    }
+    while jobs.any_running() {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    #[cfg(unix)]
+    crate::control::release();
+    code
+}
+
+#[cfg(unix)]
+fn ctl(command: &str, job: Option<&str>) -> i32 {
+    use crate::control;
+
+    if !control::COMMANDS.contains(&command) {
+        eprintln!("{USAGE}");
+        return 2;
+    }
+
+    let request = match job {
+        Some(job) => format!("{command} {job}"),
+        None => command.to_owned(),
+    };
+    match control::request(&request) {
+        Ok(reply) => {
+            println!("{reply}");
+            let ok = serde_json::from_str::<serde_json::Value>(&reply)
+                .ok()
+                .and_then(|reply| reply["ok"].as_bool())
+                .unwrap_or(false);
+            if ok {
+                0
+            } else {
+                1
+            }
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            1
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn ctl(_command: &str, _job: Option<&str>) -> i32 {
+    eprintln!("The control socket is only available on Unix");
+    1
+}
@@ -0,0 +1,315 @@
+//! Single-instance lock and control socket.
+//!
+//! The first instance locks a file and binds a Unix domain socket next to it,
+//! in a folder only the user can open. Later instances find the file locked
+//! and refuse to start a second capture loop, and
+//! `take-screenshots ctl COMMAND [JOB]` talks to it: one command per
+//! connection, answered by one line of JSON.
+
+use std::{
+    env,
+    fs::{DirBuilder, File, OpenOptions, Permissions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use directories::ProjectDirs;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::jobs::{Job, Jobs};
+use crate::session::{SessionCommand, Status};
+
+pub const COMMANDS: &[&str] = &["start", "stop", "pause", "status", "capture-now"];
+
+#[derive(Serialize)]
+struct Reply {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The profile when the instance is the daemon.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daemon: Option<String>,
+    jobs: Vec<JobStatus>,
+}
+
+#[derive(Serialize)]
+struct JobStatus {
+    name: String,
+    #[serde(flatten)]
+    status: Status,
+}
+
+const SOCKET: &str = "control.sock";
+
+/// The lock file of the running instance, held until it exits.
+static LOCK: OnceLock<File> = OnceLock::new();
+
+/// The folder of the socket and the lock: in the runtime folder of the user,
+/// or else in the local data folder.
+fn folder() -> Option<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => Some(PathBuf::from(runtime_dir).join("take-screenshots")),
+        None => ProjectDirs::from("", "", "take-screenshots")
+            .map(|dirs| dirs.data_local_dir().to_owned()),
+    }
+}
+
+fn socket_path() -> Option<PathBuf> {
+    folder().map(|folder| folder.join(SOCKET))
+}
+
+/// Takes the single-instance lock and binds the control socket. Returns
+/// `Ok(None)` when another instance already holds the lock.
+pub fn acquire() -> Result<Option<UnixListener>, String> {
+    let folder = folder().ok_or("Failed to find a folder for the control socket.")?;
+    let error = |error: std::io::Error| format!("Failed to create {}: {error}", folder.display());
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&folder)
+        .map_err(error)?;
+    // Other users mustn't reach the socket, or take the lock first.
+    std::fs::set_permissions(&folder, Permissions::from_mode(0o700)).map_err(error)?;
+
+    let lock_path = folder.join("instance.lock");
+    let lock = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|error| format!("Failed to open {}: {error}", lock_path.display()))?;
+    // SAFETY: flock only uses the descriptor, which stays open with `lock`.
+    if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == -1 {
+        let error = std::io::Error::last_os_error();
+        return match error.kind() {
+            ErrorKind::WouldBlock => Ok(None),
+            _ => Err(format!("Failed to lock {}: {error}", lock_path.display())),
+        };
+    }
+
+    // With the lock held, a socket left there is from an instance that
+    // didn't exit cleanly.
+    let path = folder.join(SOCKET);
+    match std::fs::remove_file(&path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => {
+            return Err(format!("Failed to remove {}: {error}", path.display()));
+        }
+        _ => {}
+    }
+    let listener = UnixListener::bind(&path)
+        .map_err(|error| format!("Failed to bind {}: {error}", path.display()))?;
+    let _ = LOCK.set(lock);
+    Ok(Some(listener))
+}
+
+/// Removes the socket. The lock goes with the process.
+pub fn release() {
+    if let Some(path) = socket_path() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Answers control connections. `daemon` is the profile when this instance
+/// is the daemon, which the window attaches to.
+pub async fn serve(listener: UnixListener, jobs: Arc<Jobs>, daemon: Option<String>) {
+    let listener = match listener
+        .set_nonblocking(true)
+        .and_then(|_| tokio::net::UnixListener::from_std(listener))
+    {
+        Ok(listener) => listener,
+        Err(error) => {
+            println!("Failed to listen for control commands: {error}");
+            return;
+        }
+    };
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                println!("Failed to accept control connection: {error}");
+                continue;
+            }
+        };
+
+        let jobs = jobs.clone();
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut line = String::new();
+            if tokio::io::BufReader::new(reader)
+                .read_line(&mut line)
+                .await
+                .is_err()
+            {
+                return;
+            }
+
+            let (command, job) = match line.trim().split_once(' ') {
+                Some((command, job)) => (command, Some(job.trim())),
+                None => (line.trim(), None),
+            };
+            let reply = Reply {
+                daemon,
+                ..execute(command, job, &jobs).await
+            };
+            let reply = serde_json::to_string(&reply).unwrap_or_default();
+            let _ = writer.write_all(format!("{reply}\n").as_bytes()).await;
+        });
+    }
+}
+
+/// Runs `command` on the job called `job`, or on every job it applies to.
+async fn execute(command: &str, job: Option<&str>, jobs: &Jobs) -> Reply {
+    let result = match COMMANDS.contains(&command) {
+        true => select(command, job, jobs).and_then(|selected| {
+            let errors: Vec<String> = selected
+                .iter()
+                .filter_map(|(index, job)| {
+                    apply(command, *index, job, jobs)
+                        .err()
+                        .map(|error| format!("{}: {error}", job.settings.name))
+                })
+                .collect();
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors.join("\n"))
+            }
+        }),
+        false => Err(format!("Unknown command \"{command}\"")),
+    };
+
+    // Commands are handled asynchronously by the sessions.
+    if command != "status" && result.is_ok() {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    Reply {
+        ok: result.is_ok(),
+        error: result.err(),
+        daemon: None,
+        jobs: jobs
+            .all()
+            .into_iter()
+            .map(|job| JobStatus {
+                name: job.settings.name,
+                status: job.controller.status(),
+            })
+            .collect(),
+    }
+}
+
+/// The jobs `command` should run on. Without a job name, that is every job
+/// where it makes sense, such as the stopped or paused ones for `start`.
+fn select(command: &str, name: Option<&str>, jobs: &Jobs) -> Result<Vec<(usize, Job)>, String> {
+    let all = jobs.all().into_iter().enumerate();
+    if let Some(name) = name {
+        return match all
+            .clone()
+            .find(|(_, job)| job.settings.name.trim() == name)
+        {
+            Some(job) => Ok(vec![job]),
+            None => Err(format!("No job is called \"{name}\"")),
+        };
+    }
+
+    let selected: Vec<(usize, Job)> = all
+        .filter(|(_, job)| {
+            let running = job.controller.is_running();
+            match command {
+                "start" => !running || job.controller.status().paused,
+                "status" => false,
+                _ => running,
+            }
+        })
+        .collect();
+
+    match command {
+        "status" => Ok(selected),
+        "start" if selected.is_empty() => Err("Every job is already running".to_owned()),
+        _ if selected.is_empty() => Err("No session is running".to_owned()),
+        _ => Ok(selected),
+    }
+}
+
+fn apply(command: &str, index: usize, job: &Job, jobs: &Jobs) -> Result<(), String> {
+    let paused = job.controller.status().paused;
+    match command {
+        "start" if paused => send(job, SessionCommand::Resume),
+        "start" => jobs.start(index),
+        "stop" => send(job, SessionCommand::Stop),
+        "pause" if paused => send(job, SessionCommand::Resume),
+        "pause" => send(job, SessionCommand::Pause),
+        "capture-now" => send(job, SessionCommand::CaptureNow { done: None }),
+        _ => Ok(()),
+    }
+}
+
+fn send(job: &Job, command: SessionCommand) -> Result<(), String> {
+    if job.controller.send(command) {
+        Ok(())
+    } else {
+        Err("No session is running".to_owned())
+    }
+}
+
+/// Sends `command`, optionally followed by a job name, to the running
+/// instance and returns its JSON reply.
+pub fn request(command: &str) -> Result<String, String> {
+    let mut stream = socket_path()
+        .and_then(|path| UnixStream::connect(path).ok())
+        .ok_or_else(|| "take-screenshots is not running".to_owned())?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .map_err(|error| error.to_string())?;
+    writeln!(stream, "{command}").map_err(|error| error.to_string())?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|error| error.to_string())?;
+    Ok(reply.trim_end().to_owned())
+}
+
+/// The profile of the running instance if it is the daemon.
+pub fn daemon_profile() -> Option<String> {
+    let reply = request("status").ok()?;
+    let reply: serde_json::Value = serde_json::from_str(&reply).ok()?;
+    reply["daemon"].as_str().map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let runtime_dir =
+            env::temp_dir().join(format!("take-screenshots-control-{}", std::process::id()));
+        env::set_var("XDG_RUNTIME_DIR", &runtime_dir);
+        let folder = runtime_dir.join("take-screenshots");
+        std::fs::create_dir_all(&folder).unwrap();
+        // Left behind by a crash.
+        std::fs::write(folder.join(SOCKET), b"").unwrap();
+
+        let listener = acquire().unwrap();
+        assert!(listener.is_some());
+        let mode = std::fs::metadata(&folder).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert!(UnixStream::connect(folder.join(SOCKET)).is_ok());
+        // The lock is taken, whatever happens to the socket.
+        release();
+        assert!(acquire().unwrap().is_none());
+
+        std::fs::remove_dir_all(&runtime_dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! The mouse pointer: where it is, which buttons are down and what the cursor
+//! looks like. Screen grabs leave the cursor out, so it is read through XFixes
+//! and drawn into the image afterwards.
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// Pointer position at the time of a capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pointer {
+    pub x: i32,
+    pub y: i32,
+    /// Mouse buttons held down, bit 0 for the first button.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub buttons: u8,
+}
+
+fn is_zero(buttons: &u8) -> bool {
+    *buttons == 0
+}
+
+/// The cursor image with its position on the desktop.
+#[derive(Clone, Debug)]
+pub struct CursorImage {
+    /// Where the top left corner of the cursor goes, which is the pointer
+    /// position minus the hotspot.
+    pub x: i32,
+    pub y: i32,
+    /// Premultiplied ARGB, as XFixes sends it.
+    pub pixels: Vec<u32>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CursorImage {
+    /// Draws the cursor into `image`, whose top left corner is at
+    /// `origin` on the desktop.
+    pub fn draw(&self, image: &mut RgbaImage, origin: (i32, i32)) {
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let x = self.x + column as i32 - origin.0;
+                let y = self.y + row as i32 - origin.1;
+                if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+                    continue;
+                }
+
+                let Some(&argb) = self.pixels.get((row * self.width + column) as usize) else {
+                    continue;
+                };
+                let alpha = (argb >> 24) & 0xff;
+                if alpha == 0 {
+                    continue;
+                }
+                let source = [(argb >> 16) & 0xff, (argb >> 8) & 0xff, argb & 0xff];
+
+                let pixel = image.get_pixel_mut(x as u32, y as u32);
+                for (channel, source) in pixel.0.iter_mut().zip(source) {
+                    let blended = source + (*channel as u32 * (255 - alpha) + 127) / 255;
+                    *channel = blended.min(255) as u8;
+                }
+                pixel.0[3] = 255;
+            }
+        }
+    }
+}
+
+/// Reads the pointer and the cursor image from the X server.
+#[cfg(target_os = "linux")]
+pub fn query() -> Result<(Pointer, CursorImage), String> {
+    use x11rb::{
+        connection::Connection,
+        protocol::{xfixes::ConnectionExt as _, xproto::ConnectionExt as _},
+    };
+
+    let (connection, screen) = x11rb::connect(None).map_err(|error| error.to_string())?;
+    let root = connection.setup().roots[screen].root;
+
+    connection
+        .xfixes_query_version(4, 0)
+        .map_err(|error| error.to_string())?
+        .reply()
+        .map_err(|error| format!("XFixes is not available: {error}"))?;
+    let cursor = connection
+        .xfixes_get_cursor_image()
+        .map_err(|error| error.to_string())?
+        .reply()
+        .map_err(|error| error.to_string())?;
+    let pointer = connection
+        .query_pointer(root)
+        .map_err(|error| error.to_string())?
+        .reply()
+        .map_err(|error| error.to_string())?;
+
+    Ok((
+        Pointer {
+            x: pointer.root_x.into(),
+            y: pointer.root_y.into(),
+            buttons: ((u16::from(pointer.mask) >> 8) & 0x1f) as u8,
+        },
+        CursorImage {
+            x: i32::from(cursor.x) - i32::from(cursor.xhot),
+            y: i32::from(cursor.y) - i32::from(cursor.yhot),
+            pixels: cursor.cursor_image,
+            width: cursor.width.into(),
+            height: cursor.height.into(),
+        },
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn query() -> Result<(Pointer, CursorImage), String> {
+    Err("Reading the cursor is only supported on X11".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_draw() {
+        // 2x1 cursor: opaque red, then half transparent white.
+        let cursor = CursorImage {
+            x: 9,
+            y: 4,
+            pixels: vec![0xffff0000, 0x80808080],
+            width: 2,
+            height: 1,
+        };
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        cursor.draw(&mut image, (8, 2));
+
+        assert_eq!(image.get_pixel(1, 2), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(2, 2), &Rgba([128, 128, 255, 255]));
+        assert_eq!(image.get_pixel(0, 2), &Rgba([0, 0, 255, 255]));
+
+        // Only the red pixel is inside the image.
+        cursor.draw(&mut image, (6, 1));
+        assert_eq!(image.get_pixel(3, 3), &Rgba([255, 0, 0, 255]));
+    }
+}
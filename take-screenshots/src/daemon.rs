@@ -0,0 +1,215 @@
+//! Running without the window, as started with the session. The daemon
+//! carries on the interrupted sessions, starts the jobs of a profile and
+//! answers the control socket until it is stopped.
+//!
+//! At login it may start before the display can be captured, or before the
+//! output folder is mounted, so it waits for the display and tries the jobs
+//! that failed to start again, a little later each time. Failed captures
+//! don't stop a session, and when the daemon itself crashes, the systemd
+//! service restarts it and the sessions resume.
+//!
+//! The variables naming the display are read once, when the daemon starts,
+//! so a display that never works means the daemon started too early. It then
+//! exits with an error, and the systemd service starts it again with the
+//! variables the session has imported since.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Local;
+use directories::ProjectDirs;
+
+use crate::capture::{Backend, Grabber};
+use crate::jobs::Jobs;
+use crate::{profile, resume};
+
+/// How many times the display is tried before giving up, waiting twice as
+/// long each time: about half a minute in all.
+const DISPLAY_ATTEMPTS: u32 = 6;
+
+/// Longest wait before trying to start the jobs that failed again.
+const MAX_START_WAIT: Duration = Duration::from_secs(600);
+
+/// Where the autostart entry logs to.
+pub fn log_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "take-screenshots")
+        .map(|dirs| dirs.data_local_dir().join("daemon.log"))
+}
+
+/// Runs the jobs of `profile_name`. Only returns if the daemon can't start.
+pub async fn run(profile_name: &str, log: Option<&Path>) -> i32 {
+    if let Some(log) = log {
+        if let Err(error) = redirect(log) {
+            eprintln!("{error}");
+            return 1;
+        }
+    }
+    #[cfg(unix)]
+    let listener = match crate::control::acquire() {
+        Ok(Some(listener)) => Some(listener),
+        Ok(None) => {
+            eprintln!("Take Screenshots is already running.");
+            return 1;
+        }
+        Err(error) => {
+            println!("{error}");
+            None
+        }
+    };
+
+    println!(
+        "{} Starting with the \"{profile_name}\" profile",
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+
+    let settings = match profile::folder() {
+        Some(folder) => profile::load(&folder, profile_name),
+        None => Err("Failed to find the configuration folder.".to_owned()),
+    };
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("{error}");
+            #[cfg(unix)]
+            crate::control::release();
+            return 1;
+        }
+    };
+
+    let Some(grabber) = wait_for_display().await else {
+        eprintln!("Giving up, the display can't be captured.");
+        #[cfg(unix)]
+        crate::control::release();
+        return 1;
+    };
+    let jobs = Arc::new(Jobs::new(grabber));
+    for settings in settings {
+        jobs.add(settings);
+    }
+
+    // A resumed session takes the job of the same name.
+    for state in resume::interrupted(Local::now()) {
+        let session = state.session();
+        match jobs.resume(state) {
+            Ok(_) => println!("Resumed {session}"),
+            Err(error) => println!("Failed to resume {session}: {error}"),
+        }
+    }
+
+    #[cfg(unix)]
+    if let Some(listener) = listener {
+        tokio::spawn(crate::control::serve(
+            listener,
+            jobs.clone(),
+            Some(profile_name.to_owned()),
+        ));
+    }
+
+    start_jobs(&jobs).await;
+
+    // From here on the jobs are controlled with `ctl` or the window.
+    std::future::pending().await
+}
+
+/// Waits until the display can be captured, such as when the compositor is
+/// still starting. The backend is detected once, from the variables the
+/// daemon was started with, which don't change while it runs.
+async fn wait_for_display() -> Option<Arc<Grabber>> {
+    let grabber = Grabber::new(Backend::detect());
+    let mut wait = Duration::from_secs(1);
+    for attempt in 1..=DISPLAY_ATTEMPTS {
+        match grabber.check().await {
+            Ok(()) => {
+                println!("Taking screenshots with {}", grabber.backend().name());
+                return Some(Arc::new(grabber));
+            }
+            Err(error) => println!(
+                "The display can't be captured yet (attempt {attempt} of {DISPLAY_ATTEMPTS}): {error}"
+            ),
+        }
+        if attempt < DISPLAY_ATTEMPTS {
+            tokio::time::sleep(wait).await;
+            wait *= 2;
+        }
+    }
+    None
+}
+
+/// Starts every job that isn't running, until each has started once. Jobs
+/// started or stopped with `ctl` meanwhile are left alone.
+async fn start_jobs(jobs: &Jobs) {
+    let mut pending: Vec<usize> = jobs
+        .all()
+        .iter()
+        .enumerate()
+        .filter(|(_, job)| !job.controller.is_running())
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut wait = Duration::from_secs(5);
+    loop {
+        pending.retain(|&index| {
+            let Some(job) = jobs.get(index) else {
+                return false;
+            };
+            if job.controller.is_running() || job.controller.status().session.is_some() {
+                return false;
+            }
+            match jobs.start(index) {
+                Ok(()) => {
+                    println!("Started \"{}\"", job.settings.name);
+                    false
+                }
+                Err(error) => {
+                    println!("Failed to start \"{}\": {error}", job.settings.name);
+                    true
+                }
+            }
+        });
+        if pending.is_empty() {
+            return;
+        }
+
+        println!(
+            "Trying to start them again in {}",
+            humantime::format_duration(wait)
+        );
+        tokio::time::sleep(wait).await;
+        wait = (wait * 2).min(MAX_START_WAIT);
+    }
+}
+
+/// Sends the output, and that of the hooks, to the end of `path`.
+#[cfg(unix)]
+fn redirect(path: &Path) -> Result<(), String> {
+    use std::os::unix::io::AsRawFd;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|error| format!("Failed to create {}: {error}", parent.display()))?;
+    }
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|error| format!("Failed to open {}: {error}", path.display()))?;
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: both descriptors are open, dup2 only replaces `fd`.
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+            return Err(format!(
+                "Failed to log to {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn redirect(_path: &Path) -> Result<(), String> {
+    Err("Logging to a file is only available on Unix".to_owned())
+}
@@ -0,0 +1,375 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use tokio::process::{Child, Command};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookEvent {
+    SessionStart,
+    Capture,
+    SessionEnd,
+}
+
+impl HookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookEvent::SessionStart => "start",
+            HookEvent::Capture => "capture",
+            HookEvent::SessionEnd => "end",
+        }
+    }
+}
+
+/// What a hook knows about the moment it is run for. `path` and `screen_id`
+/// are only set for `HookEvent::Capture`, and `archive` for captures saved
+/// into an archive, whose `path` is then a `TempCopy` of it.
+pub struct HookContext<'a> {
+    pub event: HookEvent,
+    pub folder: &'a str,
+    pub path: Option<&'a Path>,
+    pub time: DateTime<Local>,
+    pub screen_id: Option<u32>,
+    /// The archive and the name of the capture in it.
+    pub archive: Option<(&'a Path, &'a str)>,
+}
+
+impl HookContext<'_> {
+    /// The placeholders of the command line and their values.
+    fn vars(&self) -> Vec<(&'static str, String)> {
+        let path = self
+            .path
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let screen = self.screen_id.map(|id| id.to_string()).unwrap_or_default();
+        let (archive, member) = self
+            .archive
+            .map(|(archive, member)| (archive.to_string_lossy().into_owned(), member.to_owned()))
+            .unwrap_or_default();
+
+        vec![
+            ("event", self.event.as_str().to_owned()),
+            ("folder", self.folder.to_owned()),
+            ("path", path),
+            ("timestamp", self.time.to_rfc3339()),
+            ("screen", screen),
+            ("archive", archive),
+            ("member", member),
+        ]
+    }
+}
+
+/// A capture saved into an archive, written to a temporary file of the same
+/// name for a hook to open. Removed when dropped.
+pub struct TempCopy {
+    folder: PathBuf,
+    path: PathBuf,
+}
+
+impl TempCopy {
+    pub async fn write(member: &str, data: &[u8]) -> Result<TempCopy, String> {
+        static COUNT: AtomicU64 = AtomicU64::new(0);
+        let folder = std::env::temp_dir().join(format!(
+            "take-screenshots-hook-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let name = Path::new(member).file_name().unwrap_or(member.as_ref());
+        let copy = TempCopy {
+            path: folder.join(name),
+            folder,
+        };
+        tokio::fs::create_dir_all(&copy.folder)
+            .await
+            .and(tokio::fs::write(&copy.path, data).await)
+            .map_err(|error| format!("Failed to write {}: {error}", copy.path.display()))?;
+        Ok(copy)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.folder);
+    }
+}
+
+/// A user command run at session start, after each capture and at session end.
+///
+/// The values are exported as `TS_EVENT`, `TS_FOLDER`, `TS_PATH`,
+/// `TS_TIMESTAMP`, `TS_SCREEN_ID`, `TS_ARCHIVE` and `TS_MEMBER`, and `{event}`,
+/// `{folder}`, `{path}`, `{timestamp}`, `{screen}`, `{archive}` and `{member}`
+/// in the command line become quoted references
+/// to them before it is passed to the system shell, so no value is ever
+/// parsed as shell code.
+#[derive(Clone, Debug)]
+pub struct Hook {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl Hook {
+    pub fn is_empty(&self) -> bool {
+        self.command.trim().is_empty()
+    }
+
+    /// Runs the hook and waits for it. Failures are logged, never returned,
+    /// so a broken script can't stop the session.
+    pub async fn run(&self, context: &HookContext<'_>) {
+        if self.is_empty() {
+            return;
+        }
+
+        let vars = context.vars();
+        let command_line = expand(&self.command, &vars);
+
+        let mut command = Command::from(shell_command(&command_line));
+        command.stdin(Stdio::null()).kill_on_drop(true);
+        for (name, value) in &vars {
+            command.env(env_name(name), value);
+        }
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(error) => {
+                println!(
+                    "Failed to run {} hook `{command_line}`: {error}",
+                    context.event.as_str()
+                );
+                return;
+            }
+        };
+
+        match tokio::time::timeout(self.timeout, child.wait()).await {
+            Ok(Ok(status)) if status.success() => {}
+            Ok(Ok(status)) => {
+                println!(
+                    "{} hook `{command_line}` exited with {status}",
+                    context.event.as_str()
+                );
+            }
+            Ok(Err(error)) => {
+                println!(
+                    "Failed to wait for {} hook `{command_line}`: {error}",
+                    context.event.as_str()
+                );
+            }
+            Err(_) => {
+                kill_tree(&mut child).await;
+                println!(
+                    "{} hook `{command_line}` timed out after {}",
+                    context.event.as_str(),
+                    humantime::format_duration(self.timeout)
+                );
+            }
+        }
+    }
+}
+
+/// The environment variable a placeholder is exported as.
+fn env_name(name: &str) -> String {
+    match name {
+        "screen" => "TS_SCREEN_ID".to_owned(),
+        name => format!("TS_{}", name.to_uppercase()),
+    }
+}
+
+/// Replaces the placeholders of `vars` in `template` with quoted references
+/// to their variables, in one pass. Other braces are left alone.
+fn expand(template: &str, vars: &[(&'static str, String)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let name = rest
+            .split_once('}')
+            .map(|(name, _)| name)
+            .filter(|name| vars.iter().any(|(var, _)| var == name));
+        match name {
+            Some(name) => {
+                result.push_str(&reference(&env_name(name)));
+                rest = &rest[name.len() + 1..];
+            }
+            None => result.push('{'),
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(windows)]
+fn reference(var: &str) -> String {
+    format!("\"%{var}%\"")
+}
+
+#[cfg(not(windows))]
+fn reference(var: &str) -> String {
+    format!("\"${var}\"")
+}
+
+/// The shell running `command_line`, in a process group of its own so a hook
+/// that times out can be killed along with what it started.
+#[cfg(windows)]
+fn shell_command(command_line: &str) -> std::process::Command {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x200;
+    let mut command = std::process::Command::new("cmd");
+    command
+        .arg("/C")
+        .raw_arg(command_line)
+        .creation_flags(CREATE_NEW_PROCESS_GROUP);
+    command
+}
+
+#[cfg(not(windows))]
+fn shell_command(command_line: &str) -> std::process::Command {
+    use std::os::unix::process::CommandExt;
+
+    let mut command = std::process::Command::new("sh");
+    command.arg("-c").arg(command_line).process_group(0);
+    command
+}
+
+#[cfg(windows)]
+async fn kill_tree(child: &mut Child) {
+    if let Some(id) = child.id() {
+        let _ = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &id.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+    }
+    let _ = child.kill().await;
+}
+
+#[cfg(not(windows))]
+async fn kill_tree(child: &mut Child) {
+    if let Some(id) = child.id() {
+        // The shell leads the group, so its id is the group id.
+        unsafe { libc::kill(-(id as i32), libc::SIGKILL) };
+    }
+    let _ = child.kill().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(&'static str, String)> {
+        HookContext {
+            event: HookEvent::Capture,
+            folder: "/tmp/a b",
+            path: Some(Path::new("/tmp/a b/{screen}'$(touch x)\";.png")),
+            time: Local::now(),
+            screen_id: Some(7),
+            archive: None,
+        }
+        .vars()
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand("cp {path} {folder}/{nope} {{screen}}", &vars()),
+            "cp \"$TS_PATH\" \"$TS_FOLDER\"/{nope} {\"$TS_SCREEN_ID\"}"
+        );
+        assert_eq!(expand("{path", &vars()), "{path");
+    }
+
+    fn temp_folder(name: &str) -> std::path::PathBuf {
+        let folder = std::env::temp_dir().join(format!(
+            "take-screenshots-hooks-{name}-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[cfg(not(windows))]
+    #[tokio::test]
+    async fn test_run_quotes_values() {
+        let folder = temp_folder("quotes");
+        let output = folder.join("out");
+        let hook = Hook {
+            command: format!("printf %s {{path}} > '{}'", output.display()),
+            timeout: Duration::from_secs(10),
+        };
+        let path = folder.join("{screen}'$(touch x)\"; touch y.png");
+        hook.run(&HookContext {
+            event: HookEvent::Capture,
+            folder: &folder.to_string_lossy(),
+            path: Some(&path),
+            time: Local::now(),
+            screen_id: Some(7),
+            archive: None,
+        })
+        .await;
+
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            path.to_string_lossy()
+        );
+        assert!(!Path::new("x").exists() && !Path::new("y.png").exists());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_temp_copy() {
+        let copy = TempCopy::write("shots/ts_09_30.png", b"png").await.unwrap();
+        let path = copy.path().to_owned();
+        assert_eq!(path.file_name().unwrap(), "ts_09_30.png");
+        assert_eq!(std::fs::read(&path).unwrap(), b"png");
+        drop(copy);
+        assert!(!path.exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_timeout_kills_children() {
+        let folder = temp_folder("timeout");
+        let pid_file = folder.join("pid");
+        let hook = Hook {
+            command: format!("sleep 30 & echo $! > '{}'; wait", pid_file.display()),
+            timeout: Duration::from_millis(300),
+        };
+        let started = std::time::Instant::now();
+        hook.run(&HookContext {
+            event: HookEvent::SessionStart,
+            folder: "",
+            path: None,
+            time: Local::now(),
+            screen_id: None,
+            archive: None,
+        })
+        .await;
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        // Killed, so gone or a zombie waiting for its new parent.
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        std::fs::remove_dir_all(&folder).unwrap();
+        let stat = format!("/proc/{}/stat", pid.trim());
+        let alive = || {
+            std::fs::read_to_string(&stat)
+                .map(|stat| !stat.contains(") Z "))
+                .unwrap_or(false)
+        };
+        for _ in 0..50 {
+            if !alive() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!alive());
+    }
+}
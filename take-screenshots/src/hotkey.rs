@@ -0,0 +1,273 @@
+//! Global hotkey for out-of-schedule captures, grabbed on the X11 root window.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::i18n::{tr, trf};
+
+/// A key combination such as `Ctrl+Alt+S`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hotkey {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub super_key: bool,
+    pub keysym: u32,
+}
+
+impl Hotkey {
+    /// Parses modifiers and one key joined by `+`. Keys are letters, digits,
+    /// `F1` to `F12`, `Print`, `Pause`, `Insert`, `Delete`, `Home`, `End`,
+    /// `PageUp`, `PageDown` and `Space`.
+    pub fn parse(text: &str) -> Result<Hotkey, String> {
+        let mut hotkey = Hotkey {
+            shift: false,
+            ctrl: false,
+            alt: false,
+            super_key: false,
+            keysym: 0,
+        };
+
+        let parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let (key, modifiers) = parts
+            .split_last()
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| trf("\"{text}\" has no key.", &[("text", &text)]))?;
+
+        for modifier in modifiers {
+            match modifier.to_lowercase().as_str() {
+                "shift" => hotkey.shift = true,
+                "ctrl" | "control" => hotkey.ctrl = true,
+                "alt" => hotkey.alt = true,
+                "super" | "win" | "meta" => hotkey.super_key = true,
+                _ => {
+                    return Err(trf(
+                        "Unknown modifier \"{modifier}\" in \"{text}\".",
+                        &[("modifier", modifier), ("text", &text)],
+                    ))
+                }
+            }
+        }
+
+        hotkey.keysym = keysym(key).ok_or_else(|| {
+            trf(
+                "Unknown key \"{key}\" in \"{text}\".",
+                &[("key", key), ("text", &text)],
+            )
+        })?;
+        Ok(hotkey)
+    }
+}
+
+fn keysym(key: &str) -> Option<u32> {
+    let lower = key.to_lowercase();
+    let mut chars = lower.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            return Some(c as u32);
+        }
+    }
+
+    if let Some(number) = lower.strip_prefix('f').and_then(|n| n.parse::<u32>().ok()) {
+        return (1..=12).contains(&number).then_some(0xffbd + number);
+    }
+
+    Some(match lower.as_str() {
+        "print" | "printscreen" => 0xff61,
+        "pause" => 0xff13,
+        "insert" => 0xff63,
+        "delete" => 0xffff,
+        "home" => 0xff50,
+        "end" => 0xff57,
+        "pageup" => 0xff55,
+        "pagedown" => 0xff56,
+        "space" => 0x20,
+        _ => return None,
+    })
+}
+
+/// Keeps the hotkey grabbed until dropped.
+pub struct Registration {
+    stop: Arc<AtomicBool>,
+    /// Wakes the listening thread up, so it sees `stop`.
+    wake: Box<dyn Fn() + Send>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        (self.wake)();
+    }
+}
+
+/// Grabs `hotkey` and calls `pressed` from a background thread each time it is
+/// pressed. Key repeat is ignored.
+#[cfg(target_os = "linux")]
+pub fn register<F>(hotkey: Hotkey, pressed: F) -> Result<Registration, String>
+where
+    F: Fn() + Send + 'static,
+{
+    use x11rb::{
+        connection::Connection,
+        protocol::{
+            xproto::{ConnectionExt, GrabMode, ModMask},
+            Event,
+        },
+    };
+
+    let (connection, screen) = x11rb::connect(None).map_err(|error| {
+        trf(
+            "Global hotkeys need an X11 display: {error}",
+            &[("error", &error)],
+        )
+    })?;
+    let connection = Arc::new(connection);
+    let root = connection.setup().roots[screen].root;
+
+    let min_keycode = connection.setup().min_keycode;
+    let max_keycode = connection.setup().max_keycode;
+    let mapping = connection
+        .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)
+        .map_err(|error| error.to_string())?
+        .reply()
+        .map_err(|error| error.to_string())?;
+    let keycode = mapping
+        .keysyms
+        .chunks(mapping.keysyms_per_keycode.max(1) as usize)
+        .position(|keysyms| keysyms.contains(&hotkey.keysym))
+        .map(|index| min_keycode + index as u8)
+        .ok_or(tr("The key of the hotkey is not on this keyboard."))?;
+
+    let mut modifiers = ModMask::from(0u16);
+    for (enabled, modifier) in [
+        (hotkey.shift, ModMask::SHIFT),
+        (hotkey.ctrl, ModMask::CONTROL),
+        (hotkey.alt, ModMask::M1),
+        (hotkey.super_key, ModMask::M4),
+    ] {
+        if enabled {
+            modifiers |= modifier;
+        }
+    }
+
+    // Grab the combination with and without Caps Lock and Num Lock, or it
+    // would stop working whenever one of them is on.
+    for locks in [
+        ModMask::from(0u16),
+        ModMask::LOCK,
+        ModMask::M2,
+        ModMask::LOCK | ModMask::M2,
+    ] {
+        connection
+            .grab_key(
+                false,
+                root,
+                modifiers | locks,
+                keycode,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )
+            .map_err(|error| error.to_string())?
+            .check()
+            .map_err(|_| tr("The hotkey is already taken by another program.").to_owned())?;
+    }
+
+    let wake = waker(connection.clone(), root)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    std::thread::spawn({
+        let stop = stop.clone();
+        let connection = connection.clone();
+        move || {
+            let mut released_at = None;
+            while !stop.load(Ordering::Relaxed) {
+                match connection.wait_for_event() {
+                    // A held key repeats as a release and a press at the
+                    // same time.
+                    Ok(Event::KeyPress(event)) if released_at == Some(event.time) => {}
+                    Ok(Event::KeyPress(_)) => pressed(),
+                    Ok(Event::KeyRelease(event)) => released_at = Some(event.time),
+                    Ok(_) => {}
+                    Err(error) => {
+                        println!("Lost the X11 connection of the hotkey: {error}");
+                        return;
+                    }
+                }
+            }
+            // The grabs go away with the connection.
+        }
+    });
+
+    Ok(Registration { stop, wake })
+}
+
+/// Returns a function that makes `wait_for_event` return on `connection`, so
+/// a thread waiting for events can be stopped. The event goes to a window of
+/// its own, which gets it since a window without an event mask passes sent
+/// events to the client that made it.
+#[cfg(target_os = "linux")]
+pub fn waker(
+    connection: Arc<x11rb::rust_connection::RustConnection>,
+    root: u32,
+) -> Result<Box<dyn Fn() + Send>, String> {
+    use x11rb::{
+        connection::Connection,
+        protocol::xproto::{
+            AtomEnum, ClientMessageEvent, ConnectionExt, CreateWindowAux, EventMask, WindowClass,
+        },
+    };
+
+    let window = connection
+        .generate_id()
+        .map_err(|error| error.to_string())?;
+    connection
+        .create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )
+        .map_err(|error| error.to_string())?
+        .check()
+        .map_err(|error| error.to_string())?;
+
+    Ok(Box::new(move || {
+        let event = ClientMessageEvent::new(32, window, AtomEnum::NONE, [0; 5]);
+        let _ = connection.send_event(false, window, EventMask::NO_EVENT, event);
+        let _ = connection.flush();
+    }))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn register<F>(_hotkey: Hotkey, _pressed: F) -> Result<Registration, String>
+where
+    F: Fn() + Send + 'static,
+{
+    Err(tr("Global hotkeys are only supported on X11.").to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let hotkey = Hotkey::parse("Ctrl+Alt+S").unwrap();
+        assert!(hotkey.ctrl && hotkey.alt && !hotkey.shift && !hotkey.super_key);
+        assert_eq!(hotkey.keysym, 's' as u32);
+
+        assert_eq!(Hotkey::parse("super + F12").unwrap().keysym, 0xffc9);
+        assert_eq!(Hotkey::parse("Print").unwrap().keysym, 0xff61);
+        assert!(Hotkey::parse("Ctrl+").is_err());
+        assert!(Hotkey::parse("Hyper+S").is_err());
+        assert!(Hotkey::parse("Ctrl+F13").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Translations of the window and of the messages it shows. Strings are
+//! looked up by their English text, which is also what is shown when the
+//! language has no translation for them. Placeholders like `{name}` are
+//! filled after the lookup, so translations can move them around.
+//!
+//! Durations are typed and shown with the unit names of the language, and
+//! the English ones are always accepted, so saved settings work in either.
+
+use std::{collections::HashMap, fmt::Display, sync::OnceLock, time::Duration};
+
+mod zh;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    English,
+    Chinese,
+}
+
+impl Language {
+    /// The first language of the usual locale variables that has a catalog,
+    /// or English.
+    fn detect(var: impl Fn(&str) -> Option<String>) -> Language {
+        let locales = ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| var(name))
+            .filter(|value| !value.is_empty());
+        for value in locales {
+            // LANGUAGE is a list like "zh_CN:en".
+            for locale in value.split(':') {
+                match locale.split(['_', '.', '@', '-']).next() {
+                    Some("zh") => return Language::Chinese,
+                    Some("en") => return Language::English,
+                    _ => {}
+                }
+            }
+        }
+        Language::English
+    }
+
+    fn catalog(&self) -> Option<&'static [(&'static str, &'static str)]> {
+        match self {
+            Language::English => None,
+            Language::Chinese => Some(zh::CATALOG),
+        }
+    }
+}
+
+pub fn language() -> Language {
+    static LANGUAGE: OnceLock<Language> = OnceLock::new();
+    *LANGUAGE.get_or_init(|| Language::detect(|name| std::env::var(name).ok()))
+}
+
+fn lookup(language: Language, text: &'static str) -> &'static str {
+    static CATALOG: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    let Some(catalog) = language.catalog() else {
+        return text;
+    };
+    CATALOG
+        .get_or_init(|| catalog.iter().copied().collect())
+        .get(text)
+        .copied()
+        .unwrap_or(text)
+}
+
+/// The translation of `text`.
+pub fn tr(text: &'static str) -> &'static str {
+    lookup(language(), text)
+}
+
+/// The translation of `text`, with each `{name}` of `args` filled in.
+pub fn trf(text: &'static str, args: &[(&str, &dyn Display)]) -> String {
+    fill(tr(text), args)
+}
+
+fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter()
+        .fold(template.to_owned(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), &value.to_string())
+        })
+}
+
+/// Unit names of each language, longest first so "分钟" isn't read as "分".
+const UNITS: &[(&str, &str)] = &[
+    ("毫秒", "ms"),
+    ("秒钟", "s"),
+    ("秒", "s"),
+    ("分钟", "min"),
+    ("分", "min"),
+    ("个小时", "h"),
+    ("小时", "h"),
+    ("钟头", "h"),
+    ("天", "d"),
+    ("星期", "w"),
+    ("周", "w"),
+];
+
+/// Parses a duration like "5 minutes", "1h 30min" or "1 小时 30 分钟".
+/// Full-width digits are accepted too.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let mut normalized: String = text
+        .chars()
+        .map(|c| match c {
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+            c => c,
+        })
+        .collect();
+    for (unit, english) in UNITS {
+        normalized = normalized.replace(unit, &format!("{english} "));
+    }
+    humantime::parse_duration(normalized.trim()).map_err(|error| error.to_string())
+}
+
+/// Formats a duration with the unit names of the language.
+pub fn format_duration(duration: Duration) -> String {
+    match language() {
+        Language::English => humantime::format_duration(duration).to_string(),
+        Language::Chinese => format_chinese(duration),
+    }
+}
+
+fn format_chinese(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let parts = [
+        (seconds / 86400, "天"),
+        (seconds / 3600 % 24, "小时"),
+        (seconds / 60 % 60, "分钟"),
+        (seconds % 60, "秒"),
+        (duration.subsec_millis() as u64, "毫秒"),
+    ];
+    let text: Vec<String> = parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{value} {unit}"))
+        .collect();
+    match text.is_empty() {
+        true => "0 秒".to_owned(),
+        false => text.join(" "),
+    }
+}
+
+/// A duration typed in any language, as it is shown in this one. Text that
+/// isn't a duration is left as it is.
+pub fn localize_duration(text: &str) -> String {
+    match (language(), parse_duration(text)) {
+        (Language::Chinese, Ok(duration)) => format_chinese(duration),
+        _ => text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let detect = |vars: &[(&str, &str)]| {
+            Language::detect(|name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+        assert_eq!(detect(&[]), Language::English);
+        assert_eq!(detect(&[("LANG", "zh_CN.UTF-8")]), Language::Chinese);
+        assert_eq!(detect(&[("LANG", "zh_TW")]), Language::Chinese);
+        assert_eq!(
+            detect(&[("LANGUAGE", "en_US:zh"), ("LANG", "zh_CN.UTF-8")]),
+            Language::English
+        );
+        assert_eq!(
+            detect(&[("LANGUAGE", ""), ("LC_ALL", "fr_FR:zh_CN")]),
+            Language::Chinese
+        );
+        assert_eq!(detect(&[("LANG", "C.UTF-8")]), Language::English);
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup(Language::English, "Add job"), "Add job");
+        assert_eq!(lookup(Language::Chinese, "Add job"), "添加任务");
+        assert_eq!(
+            lookup(Language::Chinese, "not translated"),
+            "not translated"
+        );
+        assert_eq!(
+            fill("{a} and {b}, {a}", &[("a", &1), ("b", &"two")]),
+            "1 and two, 1"
+        );
+    }
+
+    #[test]
+    fn test_catalog() {
+        let placeholders = |text: &str| {
+            let mut names: Vec<String> = text
+                .split('{')
+                .skip(1)
+                .filter_map(|part| part.split_once('}'))
+                .map(|(name, _)| name.to_owned())
+                .filter(|name| name.chars().all(|c| c.is_ascii_lowercase() || c == '_'))
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+        };
+
+        let mut seen = std::collections::HashSet::new();
+        for (english, chinese) in zh::CATALOG {
+            assert!(seen.insert(english), "{english} is translated twice");
+            // Choices must keep their number of items.
+            assert_eq!(english.matches('|').count(), chinese.matches('|').count());
+            // A translation can drop a placeholder, but not make one up.
+            for name in placeholders(chinese) {
+                assert!(
+                    placeholders(english).contains(&name),
+                    "{chinese} has {{{name}}}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        assert_eq!(parse_duration("5 minutes"), Ok(minutes(5)));
+        assert_eq!(parse_duration("1h 30min"), Ok(minutes(90)));
+        assert_eq!(parse_duration("5 分钟"), Ok(minutes(5)));
+        assert_eq!(parse_duration("1小时30分"), Ok(minutes(90)));
+        assert_eq!(parse_duration("2个小时"), Ok(minutes(120)));
+        assert_eq!(parse_duration("２００毫秒"), Ok(Duration::from_millis(200)));
+        assert!(parse_duration("5 光年").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn test_format_chinese() {
+        assert_eq!(format_chinese(Duration::from_secs(5400)), "1 小时 30 分钟");
+        assert_eq!(format_chinese(Duration::from_millis(200)), "200 毫秒");
+        assert_eq!(
+            format_chinese(Duration::from_secs(90061)),
+            "1 天 1 小时 1 分钟 1 秒"
+        );
+        assert_eq!(format_chinese(Duration::ZERO), "0 秒");
+        assert_eq!(
+            parse_duration(&format_chinese(Duration::from_secs(90061))),
+            Ok(Duration::from_secs(90061))
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! The Chinese (Simplified) catalog.
+
+pub const CATALOG: &[(&str, &str)] = &[
+    ("Add job", "添加任务"),
+    ("Remove job", "删除任务"),
+    ("Start screenshot", "开始截图"),
+    ("Stop screenshot", "停止截图"),
+    ("Take Screenshots", "定时截图"),
+    ("Use Strategy", "截图方式"),
+    ("screenshots-rs(Cross platform, default)|ksnip(Cross platform)|NirCmd(Only Windows)|Python MSS(Cross platform)", "screenshots-rs（跨平台，默认）|ksnip（跨平台）|NirCmd（仅 Windows）|Python MSS（跨平台）"),
+    ("Stop in|Stop at|Stop after|Never stop", "持续|停止于|截取张数|不停止"),
+    ("Not at login|At login (autostart)|At login (systemd)", "登录时不启动|登录时启动（autostart）|登录时启动（systemd）"),
+    ("Desktop", "桌面"),
+    ("Job", "任务"),
+    ("Job {number}", "任务 {number}"),
+    (", capture", "，截取"),
+    ("Empty for the screen, a screen number like \"2\",\na region like \"800x600+0+0\" or both like \"2:800x600+0+0\"", "留空截取整个屏幕，也可以填屏幕编号如 \"2\"，\n区域如 \"800x600+0+0\"，或两者如 \"2:800x600+0+0\""),
+    ("Cursor", "光标"),
+    ("Draw the mouse cursor into the screenshots (X11 only)", "在截图中画出鼠标光标（仅 X11）"),
+    ("Save at", "保存到"),
+    ("Select", "选择"),
+    ("with name", "文件名"),
+    ("In an archive", "存为归档"),
+    ("Save the screenshots of each session into one SESSION.tar file in the folder", "把每次会话的截图存进文件夹里的一个 SESSION.tar 文件"),
+    ("OCR", "文字识别"),
+    ("OCR needs a build with the ocr feature.", "文字识别需要启用 ocr 功能编译。"),
+    ("Recognize the text of each screenshot with tesseract,\nto find it with \"Search\"", "用 tesseract 识别每张截图中的文字，\n以便用“搜索”查找"),
+    ("The next screenshots of the job if it started now", "任务现在开始的话接下来的截图"),
+    ("Resize to", "缩放到"),
+    ("Empty to keep the size, a percentage like \"50%\"\nor a maximum size like \"1920x1080\", \"1920x\" or \"x1080\"", "留空保持原尺寸，也可以填百分比如 \"50%\"\n或最大尺寸如 \"1920x1080\"、\"1920x\" 或 \"x1080\""),
+    ("Resampling filter", "重采样滤镜"),
+    ("Grayscale", "灰度"),
+    (", with duration", "，间隔"),
+    ("Run hook", "运行钩子"),
+    ("Command run at start, after each capture and at end.\nUse {event}, {path}, {timestamp}, {screen} or $TS_PATH...", "开始时、每次截图后和结束时运行的命令。\n可以使用 {event}、{path}、{timestamp}、{screen} 或 $TS_PATH 等"),
+    (", timeout", "，超时"),
+    ("Also on", "也在"),
+    ("window switch", "切换窗口时"),
+    ("Take a screenshot when the focused window changes (X11 only)", "焦点窗口变化时截图（仅 X11）"),
+    ("clipboard", "复制时"),
+    ("Take a screenshot when something is copied (X11 only)", "复制内容时截图（仅 X11）"),
+    (", after", "，等待"),
+    ("Events until then are folded into one screenshot", "这段时间内的事件合并为一张截图"),
+    ("and writes to", "以及写入"),
+    ("A file or folder; a screenshot is taken when it is written.\nLeave empty to disable.", "文件或文件夹，写入时截图。\n留空则不启用。"),
+    ("Motion", "画面变化"),
+    ("Sample the screen and save a burst of frames when it changes", "采样屏幕，画面变化时连续保存多帧"),
+    ("every", "每隔"),
+    ("Time between samples, and between the frames of a burst", "采样之间以及连拍各帧之间的时间"),
+    ("over", "超过"),
+    ("How much of the picture must change, like \"2%\"", "画面需要变化多少，如 \"2%\""),
+    ("frames", "帧"),
+    ("Frames saved from before the change, kept in memory until then", "保存变化之前的帧数，在此之前保存在内存中"),
+    ("+", "+"),
+    ("Frames saved after the change", "保存变化之后的帧数"),
+    ("Hotkey", "快捷键"),
+    ("Takes an extra screenshot in every running job (X11 only).\nLeave empty to disable.", "在每个运行中的任务里额外截一张图（仅 X11）。\n留空则不启用。"),
+    ("Ask for a note", "询问备注"),
+    ("Serve at", "服务地址"),
+    ("A port, served to this computer only, or an address and port.\nOpen it in a browser to see the latest screenshot.", "端口（仅本机可访问），或地址和端口。\n在浏览器中打开即可查看最新截图。"),
+    ("token", "令牌"),
+    ("Optional, then requests need ?token=TOKEN.\nRequired to serve other computers.", "可选，设置后请求需要带上 ?token=TOKEN。\n为其他电脑提供服务时必须设置。"),
+    ("On", "开启"),
+    ("Profile", "配置"),
+    ("The jobs are saved under this name.\nThe daemon started at login runs them.", "任务以此名称保存。\n登录时启动的后台进程会运行它们。"),
+    ("Save", "保存"),
+    ("Load", "载入"),
+    ("Run the jobs of the profile without the window when you log in.\nThe window attaches to it when opened.", "登录时在后台运行此配置的任务，不显示窗口。\n打开窗口时会连接到后台进程。"),
+    ("Minimize the window", "最小化窗口"),
+    ("Capture now", "立即截图"),
+    ("Report", "报告"),
+    ("Write an HTML activity report for the output folder of the job", "为任务的输出文件夹生成 HTML 活动报告"),
+    ("Report written to {path}", "报告已写入 {path}"),
+    ("Search", "搜索"),
+    ("Find screenshots in the output folder of the job by their text", "按文字在任务的输出文件夹中查找截图"),
+    ("Search {folder}", "搜索 {folder}"),
+    ("Words on the screen, each matching the beginning of a word", "屏幕上的文字，每个词匹配词的开头"),
+    ("No screenshot shows that.", "没有截图包含这些文字。"),
+    ("Failed to read {path}: {error}", "无法读取 {path}：{error}"),
+    ("Failed to create {path}: {error}", "无法创建 {path}：{error}"),
+    ("Failed to write {path}: {error}", "无法写入 {path}：{error}"),
+    ("Give the profile a name.", "请给配置起个名字。"),
+    ("The profile name \"{name}\" may only have letters, digits, spaces, \"-\" and \"_\".", "配置名称 \"{name}\" 只能包含字母、数字、空格、\"-\" 和 \"_\"。"),
+    ("There is no profile called \"{name}\".", "没有名为 \"{name}\" 的配置。"),
+    ("The profile \"{name}\" has no jobs.", "配置 \"{name}\" 没有任务。"),
+    ("No such job.", "没有这个任务。"),
+    ("\"{name}\" is already running.", "“{name}”已经在运行。"),
+    ("Another job is also called \"{name}\", rename one of them.", "另一个任务也叫“{name}”，请重命名其中一个。"),
+    ("\"{text}\" has no key.", "\"{text}\" 没有按键。"),
+    ("Unknown modifier \"{modifier}\" in \"{text}\".", "\"{text}\" 中的修饰键 \"{modifier}\" 无法识别。"),
+    ("Unknown key \"{key}\" in \"{text}\".", "\"{text}\" 中的按键 \"{key}\" 无法识别。"),
+    ("Global hotkeys need an X11 display: {error}", "全局快捷键需要 X11 显示：{error}"),
+    ("The key of the hotkey is not on this keyboard.", "这个键盘上没有快捷键的按键。"),
+    ("The hotkey is already taken by another program.", "快捷键已被其他程序占用。"),
+    ("Global hotkeys are only supported on X11.", "全局快捷键仅支持 X11。"),
+    ("Window and clipboard triggers need an X11 display: {error}", "窗口和剪贴板触发需要 X11 显示：{error}"),
+    ("Window and clipboard triggers are only supported on X11.", "窗口和剪贴板触发仅支持 X11。"),
+    ("\"{value}\" is not an address to serve at.\nUse a port like \"8765\" or an address like \"{example}\".", "\"{value}\" 不是可用的服务地址。\n请使用端口如 \"8765\" 或地址如 \"{example}\"。"),
+    ("Set a token to serve at {address}, other computers could see the screenshots.", "在 {address} 提供服务需要设置令牌，否则其他电脑也能看到截图。"),
+    ("Warning: {warning}", "警告：{warning}"),
+    ("paused", "已暂停"),
+    ("running", "运行中"),
+    ("stopped", "已停止"),
+    ("{captures} screenshots", "{captures} 张截图"),
+    (", {failed} failed", "，{failed} 张失败"),
+    ("Take Screenshots, daemon with the \"{profile}\" profile", "定时截图，运行“{profile}”配置的后台进程"),
+    ("Start", "开始"),
+    ("Stop", "停止"),
+    ("Pause", "暂停"),
+    ("Resume", "继续"),
+    ("Cancel", "取消"),
+    ("Discard", "丢弃"),
+    ("The daemon stopped.", "后台进程已停止。"),
+    ("The job hasn't taken any screenshots yet.", "任务还没有截过图。"),
+    ("Take Screenshots is already running.\nUse \"take-screenshots ctl\" to control it.", "定时截图已经在运行。\n请使用 \"take-screenshots ctl\" 控制它。"),
+    ("{job} in {folder}, started {started}, {captures} screenshots", "{job}，保存在 {folder}，开始于 {started}，{captures} 张截图"),
+    ("These sessions were interrupted:\n\n{sessions}\n\nResume them?", "这些会话被中断了：\n\n{sessions}\n\n要继续吗？"),
+    ("Failed to resume \"{name}\": {error}", "无法继续“{name}”：{error}"),
+    ("The last job can't be removed.", "不能删除最后一个任务。"),
+    ("{warnings}\n\nStart anyway?", "{warnings}\n\n仍然开始吗？"),
+    ("Failed to serve at {address}: {error}", "无法在 {address} 提供服务：{error}"),
+    ("Failed to find the configuration folder.", "找不到配置文件夹。"),
+    ("Stop the jobs before loading a profile.", "载入配置前请先停止任务。"),
+    ("Note for this screenshot:", "这张截图的备注："),
+    ("How long to keep taking screenshots", "持续截图多长时间"),
+    ("Time of day (HH:MM) or date and time (YYYY-MM-DD HH:MM)", "时刻（HH:MM）或日期和时间（YYYY-MM-DD HH:MM）"),
+    ("Number of screenshots to take", "要截取的张数"),
+    ("Runs until \"Stop screenshot\" is pressed", "一直运行到按下“停止截图”"),
+    ("Failed to parse the target \"{text}\".\nUse a screen number like \"2\", a region like \"800x600+0+0\" or both like \"2:800x600+0+0\".", "无法解析截取范围 \"{text}\"。\n请使用屏幕编号如 \"2\"、区域如 \"800x600+0+0\" 或两者如 \"2:800x600+0+0\"。"),
+    ("\"{format}\" is not a valid file name format.", "\"{format}\" 不是有效的文件名格式。"),
+    ("Failed to parse the duration: {error}.\nTry something like \"5 minutes\".", "无法解析间隔：{error}。\n请试试 \"5 分钟\" 这样的写法。"),
+    ("Give the job a name.", "请给任务起个名字。"),
+    ("Failed to parse the hook timeout: {error}.\nTry something like \"30s\".", "无法解析钩子超时：{error}。\n请试试 \"30 秒\" 这样的写法。"),
+    ("Failed to parse the debounce time: {error}.\nTry something like \"2s\".", "无法解析等待时间：{error}。\n请试试 \"2 秒\" 这样的写法。"),
+    ("Failed to parse the motion sampling rate: {error}.\nTry something like \"200ms\".", "无法解析采样间隔：{error}。\n请试试 \"200 毫秒\" 这样的写法。"),
+    ("Sample the screen for motion at most every 10ms.", "屏幕采样间隔不能短于 10 毫秒。"),
+    ("{backend} can sample the screen for motion at most every {rate}.", "{backend} 的屏幕采样间隔不能短于 {rate}。"),
+    ("{backend} can't sample the screen for motion.", "{backend} 无法采样屏幕检测变化。"),
+    ("The motion threshold \"{threshold}\" should be a percentage like \"2%\".", "变化阈值 \"{threshold}\" 应该是百分比，如 \"2%\"。"),
+    ("The frames before the motion should be a number from 0 to {max}.", "变化之前的帧数应该是 0 到 {max} 之间的数字。"),
+    ("The frames after the motion should be a number from 0 to {max}.", "变化之后的帧数应该是 0 到 {max} 之间的数字。"),
+    ("Screenshots {first} and {second} would both be saved as \"{name}\", the name repeats after {every}. Add finer fields like %S, or the date, to the name.", "第 {first} 张和第 {second} 张截图都会保存为 \"{name}\"，文件名每隔 {every} 重复一次。请在文件名中加入更细的字段如 %S，或加入日期。"),
+    ("The folder \"{folder}\" doesn't exist.", "文件夹 \"{folder}\" 不存在。"),
+    ("{existing} of the next {count} screenshots would overwrite existing files, starting with \"{path}\".", "接下来的 {count} 张截图中有 {existing} 张会覆盖已有文件，第一个是 \"{path}\"。"),
+    ("Can't write to \"{folder}\": {error}", "无法写入 \"{folder}\"：{error}"),
+    ("Failed to parse the size \"{text}\".\nUse a percentage like \"50%\" or a maximum size like \"1920x1080\", \"1920x\" or \"x1080\".", "无法解析尺寸 \"{text}\"。\n请使用百分比如 \"50%\" 或最大尺寸如 \"1920x1080\"、\"1920x\" 或 \"x1080\"。"),
+    ("Failed to parse \"Stop in\": {error}.\nTry something like \"2 hours\".", "无法解析“持续”：{error}。\n请试试 \"2 小时\" 这样的写法。"),
+    ("Failed to parse \"Stop at\".\nUse a time like \"18:30\" or a date and time like \"2023-02-20 18:30\".", "无法解析“停止于”。\n请使用时间如 \"18:30\" 或日期和时间如 \"2023-02-20 18:30\"。"),
+    ("\"Stop after\" expects a number of screenshots, like \"100\".", "“截取张数”需要填截图张数，如 \"100\"。"),
+    ("The duration between screenshots must be longer than zero.", "截图间隔必须大于零。"),
+    ("The duration between screenshots can be at most {max}.", "截图间隔最长为 {max}。"),
+    ("\"Stop in\" can be at most {max}.", "“持续”最长为 {max}。"),
+    ("\"Stop in\" ({stop}) is shorter than the duration ({interval}), so only one screenshot would be taken.\nMake \"Stop in\" longer or the duration shorter.", "“持续”（{stop}）比间隔（{interval}）短，只会截取一张图。\n请把“持续”调长或把间隔调短。"),
+    ("\"Stop at\" ({time}) is already in the past.", "“停止于”（{time}）已经过去了。"),
+    ("\"Stop at\" ({time}) comes before the second screenshot would be taken ({interval} from now), so only one screenshot would be taken.\nPick a later time or a shorter duration.", "“停止于”（{time}）早于第二张截图的时间（{interval}后），只会截取一张图。\n请选择更晚的时间或更短的间隔。"),
+    ("\"Stop after\" must be at least 1 screenshot.", "“截取张数”至少为 1。"),
+    ("Window and clipboard triggers need an X11 session.", "窗口和剪贴板触发需要 X11 会话。"),
+    ("Can't watch \"{path}\": {error}", "无法监视 \"{path}\"：{error}"),
+    ("\"{path}\" can't be watched, the screenshots are saved there.", "不能监视 \"{path}\"，截图就保存在那里。"),
+];
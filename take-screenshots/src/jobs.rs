@@ -0,0 +1,357 @@
+//! Capture jobs. Each job has its own target, folder, file name and interval,
+//! and runs its own session. All jobs share one capture backend.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{
+    format::{strftime, Item},
+    DateTime, Local,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::capture::{Grabber, Target};
+use crate::hooks::Hook;
+use crate::i18n::{parse_duration, tr, trf};
+use crate::motion::Motion;
+#[cfg(feature = "ocr")]
+use crate::ocr;
+use crate::resize::Resize;
+use crate::resume::{self, State};
+use crate::session::{Controller, Session, StopMode};
+use crate::triggers::Triggers;
+
+/// The settings of a job, as typed in the window.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobSettings {
+    pub name: String,
+    pub target: String,
+    pub cursor: bool,
+    /// Empty, a percentage or a maximum size, see `Resize::parse`.
+    pub resize: String,
+    /// Index into `FILTER_CHOICES`.
+    pub filter: i32,
+    pub grayscale: bool,
+    pub output_folder: String,
+    /// Save each session into one archive.
+    pub archive: bool,
+    pub format: String,
+    pub interval: String,
+    /// Index into `STOP_MODE_CHOICES`.
+    pub stop_mode: i32,
+    pub stop_value: String,
+    pub hook_command: String,
+    pub hook_timeout: String,
+    pub window_trigger: bool,
+    pub clipboard_trigger: bool,
+    /// File or folder whose writes trigger captures, empty for none.
+    pub watch_path: String,
+    pub debounce: String,
+    /// Sample the screen and save bursts around motion.
+    pub motion: bool,
+    pub motion_rate: String,
+    pub motion_threshold: String,
+    pub burst_before: String,
+    pub burst_after: String,
+    /// Recognize the text of each screenshot into the index of the folder.
+    #[serde(default)]
+    pub ocr: bool,
+}
+
+impl JobSettings {
+    pub fn new(name: &str, output_folder: &str) -> Self {
+        JobSettings {
+            name: name.to_owned(),
+            target: String::new(),
+            cursor: false,
+            resize: String::new(),
+            filter: 0,
+            grayscale: false,
+            output_folder: output_folder.to_owned(),
+            archive: false,
+            format: "ts_%Y_%m_%d-%H_%M_%S.png".to_owned(),
+            interval: "5 minutes".to_owned(),
+            stop_mode: 0,
+            stop_value: "2 hours".to_owned(),
+            hook_command: String::new(),
+            hook_timeout: "30s".to_owned(),
+            window_trigger: false,
+            clipboard_trigger: false,
+            watch_path: String::new(),
+            debounce: "2s".to_owned(),
+            motion: false,
+            motion_rate: "200ms".to_owned(),
+            motion_threshold: "2%".to_owned(),
+            burst_before: "5".to_owned(),
+            burst_after: "10".to_owned(),
+            ocr: false,
+        }
+    }
+
+    /// Checks the file name format, the interval and the stop mode, and
+    /// returns the interval and the stop mode.
+    pub fn schedule(&self, now: DateTime<Local>) -> Result<(Duration, StopMode), String> {
+        let interval = self.interval()?;
+
+        if strftime::StrftimeItems::new(&self.format).any(|item| matches!(item, Item::Error)) {
+            return Err(trf(
+                "\"{format}\" is not a valid file name format.",
+                &[("format", &self.format)],
+            ));
+        }
+
+        let stop = StopMode::parse(self.stop_mode, &self.stop_value, now)?;
+        stop.validate(interval, now)?;
+        Ok((interval, stop))
+    }
+
+    fn interval(&self) -> Result<Duration, String> {
+        parse_duration(&self.interval).map_err(|error| {
+            trf(
+                "Failed to parse the duration: {error}.\nTry something like \"5 minutes\".",
+                &[("error", &error)],
+            )
+        })
+    }
+
+    /// Checks the settings and turns them into a session.
+    pub fn session(&self, grabber: Arc<Grabber>, now: DateTime<Local>) -> Result<Session, String> {
+        let (interval, stop) = self.schedule(now)?;
+        self.build(
+            grabber,
+            interval,
+            State::new(self.clone(), now, stop),
+            false,
+        )
+    }
+
+    /// Turns the saved state of an interrupted session back into a session
+    /// that carries on from there. Only the stop mode is not checked again,
+    /// it was when the session started.
+    pub fn resume(&self, grabber: Arc<Grabber>, state: State) -> Result<Session, String> {
+        let interval = self.interval()?;
+        self.build(grabber, interval, state, true)
+    }
+
+    fn build(
+        &self,
+        grabber: Arc<Grabber>,
+        interval: Duration,
+        state: State,
+        resumed: bool,
+    ) -> Result<Session, String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(tr("Give the job a name.").to_owned());
+        }
+
+        let target = Target::parse(&self.target)?;
+        let resize = Resize::parse(&self.resize, self.filter, self.grayscale)?;
+
+        let hook_timeout = parse_duration(&self.hook_timeout).map_err(|error| {
+            trf(
+                "Failed to parse the hook timeout: {error}.\nTry something like \"30s\".",
+                &[("error", &error)],
+            )
+        })?;
+
+        let triggers = Triggers {
+            window: self.window_trigger,
+            clipboard: self.clipboard_trigger,
+            watch: match self.watch_path.trim() {
+                "" => None,
+                path => Some(PathBuf::from(path)),
+            },
+            debounce: parse_duration(&self.debounce).map_err(|error| {
+                trf(
+                    "Failed to parse the debounce time: {error}.\nTry something like \"2s\".",
+                    &[("error", &error)],
+                )
+            })?,
+        };
+        triggers.validate(Path::new(&self.output_folder), grabber.backend().is_x11())?;
+
+        let motion = match self.motion {
+            true => {
+                let motion = Motion::parse(
+                    &self.motion_rate,
+                    &self.motion_threshold,
+                    &self.burst_before,
+                    &self.burst_after,
+                )?;
+                let backend = grabber.backend();
+                motion.check_rate(backend.name(), backend.min_sample_rate())?;
+                Some(motion)
+            }
+            false => None,
+        };
+
+        if self.ocr {
+            #[cfg(feature = "ocr")]
+            ocr::check()?;
+            #[cfg(not(feature = "ocr"))]
+            return Err(tr("OCR needs a build with the ocr feature.").to_owned());
+        }
+
+        Ok(Session {
+            name: name.to_owned(),
+            target,
+            cursor: self.cursor,
+            resize,
+            grabber,
+            format: self.format.clone(),
+            output_folder: self.output_folder.clone(),
+            archive: self.archive,
+            interval,
+            stop: state.stop,
+            hook: Hook {
+                command: self.hook_command.clone(),
+                timeout: hook_timeout,
+            },
+            triggers,
+            motion,
+            ocr: self.ocr,
+            state,
+            state_folder: resume::folder(),
+            resumed,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Job {
+    pub settings: JobSettings,
+    pub controller: Arc<Controller>,
+}
+
+/// The jobs of this process, shared by the window and the control socket.
+pub struct Jobs {
+    grabber: Arc<Grabber>,
+    jobs: Mutex<Vec<Job>>,
+}
+
+impl Jobs {
+    pub fn new(grabber: Arc<Grabber>) -> Self {
+        Jobs {
+            grabber,
+            jobs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Adds a job and returns its index.
+    pub fn add(&self, settings: JobSettings) -> usize {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push(Job {
+            settings,
+            controller: Arc::new(Controller::default()),
+        });
+        jobs.len() - 1
+    }
+
+    /// Removes a stopped job.
+    pub fn remove(&self, index: usize) -> Result<(), String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        match jobs.get(index) {
+            Some(job) if job.controller.is_running() => Err(format!(
+                "Stop \"{}\" before removing it.",
+                job.settings.name
+            )),
+            Some(_) => {
+                jobs.remove(index);
+                Ok(())
+            }
+            None => Err("No such job.".to_owned()),
+        }
+    }
+
+    pub fn all(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Job> {
+        self.jobs.lock().unwrap().get(index).cloned()
+    }
+
+    /// Replaces the settings of a job. A running session keeps the settings
+    /// it was started with.
+    pub fn set_settings(&self, index: usize, settings: JobSettings) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(index) {
+            job.settings = settings;
+        }
+    }
+
+    pub fn any_running(&self) -> bool {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|job| job.controller.is_running())
+    }
+
+    /// Starts a session for the job at `index`.
+    pub fn start(&self, index: usize) -> Result<(), String> {
+        let job = {
+            let jobs = self.jobs.lock().unwrap();
+            let job = jobs.get(index).ok_or(tr("No such job."))?;
+            if job.controller.is_running() {
+                return Err(already_running(&job.settings.name));
+            }
+
+            let name = job.settings.name.trim();
+            if jobs
+                .iter()
+                .enumerate()
+                .any(|(other, job)| other != index && job.settings.name.trim() == name)
+            {
+                return Err(trf(
+                    "Another job is also called \"{name}\", rename one of them.",
+                    &[("name", &name)],
+                ));
+            }
+            job.clone()
+        };
+
+        // Making the session touches the disk, the jobs stay unlocked
+        // meanwhile. Another start in between makes attaching fail.
+        let session = job.settings.session(self.grabber.clone(), Local::now())?;
+        let commands = job
+            .controller
+            .attach()
+            .ok_or_else(|| already_running(&job.settings.name))?;
+        tokio::spawn(session.run(commands, job.controller));
+        Ok(())
+    }
+
+    /// Carries on an interrupted session, in the stopped job of the same name
+    /// or in a new job. Returns the index of the job.
+    pub fn resume(&self, state: State) -> Result<usize, String> {
+        let name = state.settings.name.trim().to_owned();
+        let jobs = self.all();
+        let index = match jobs.iter().position(|job| job.settings.name.trim() == name) {
+            Some(index) if jobs[index].controller.is_running() => {
+                return Err(already_running(&name));
+            }
+            Some(index) => {
+                self.set_settings(index, state.settings.clone());
+                index
+            }
+            None => self.add(state.settings.clone()),
+        };
+
+        let job = self.get(index).ok_or(tr("No such job."))?;
+        let session = job.settings.resume(self.grabber.clone(), state)?;
+        let commands = job
+            .controller
+            .attach()
+            .ok_or_else(|| already_running(&name))?;
+        tokio::spawn(session.run(commands, job.controller));
+        Ok(index)
+    }
+}
+
+fn already_running(name: &str) -> String {
+    trf("\"{name}\" is already running.", &[("name", &name)])
+}
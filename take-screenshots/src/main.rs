@@ -1,14 +1,472 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::Local;
 use directories::UserDirs;
 use fltk::{prelude::*, *};
-use chrono::{format::{strftime, Item}, Utc, DateTime, Local};
-use screenshots::Screen;
+
+mod archive;
+mod autostart;
+mod capture;
+mod cli;
+#[cfg(unix)]
+mod control;
+mod cursor;
+mod daemon;
+mod hooks;
+mod hotkey;
+mod i18n;
+mod jobs;
+mod manifest;
+mod metadata;
+mod motion;
+#[cfg(feature = "ocr")]
+mod ocr;
+mod preview;
+mod profile;
+mod report;
+mod resize;
+mod resume;
+mod server;
+mod session;
+mod triggers;
+#[cfg(target_os = "linux")]
+mod wayland;
+
+use autostart::Method;
+use capture::{Backend, Grabber};
+use i18n::{tr, trf};
+use jobs::{Job, JobSettings, Jobs};
+use resize::FILTER_CHOICES;
+use session::{SessionCommand, STOP_MODE_CHOICES};
+
+/// The job editor of the window.
+struct JobForm {
+    name: input::Input,
+    target: input::Input,
+    cursor: button::CheckButton,
+    resize: input::Input,
+    filter: menu::Choice,
+    grayscale: button::CheckButton,
+    output_folder: input::Input,
+    format: input::Input,
+    archive: button::CheckButton,
+    stop_mode: menu::Choice,
+    stop_value: input::Input,
+    interval: input::Input,
+    hook_command: input::Input,
+    hook_timeout: input::Input,
+    window_trigger: button::CheckButton,
+    clipboard_trigger: button::CheckButton,
+    watch_path: input::Input,
+    debounce: input::Input,
+    motion: button::CheckButton,
+    motion_rate: input::Input,
+    motion_threshold: input::Input,
+    burst_before: input::Input,
+    burst_after: input::Input,
+    #[cfg(feature = "ocr")]
+    ocr: button::CheckButton,
+    preview: text::TextDisplay,
+}
+
+impl JobForm {
+    fn read(&self) -> JobSettings {
+        JobSettings {
+            name: self.name.value(),
+            target: self.target.value(),
+            cursor: self.cursor.is_checked(),
+            resize: self.resize.value(),
+            filter: self.filter.value(),
+            grayscale: self.grayscale.is_checked(),
+            output_folder: self.output_folder.value(),
+            format: self.format.value(),
+            archive: self.archive.is_checked(),
+            interval: self.interval.value(),
+            stop_mode: self.stop_mode.value(),
+            stop_value: self.stop_value.value(),
+            hook_command: self.hook_command.value(),
+            hook_timeout: self.hook_timeout.value(),
+            window_trigger: self.window_trigger.is_checked(),
+            clipboard_trigger: self.clipboard_trigger.is_checked(),
+            watch_path: self.watch_path.value(),
+            debounce: self.debounce.value(),
+            motion: self.motion.is_checked(),
+            motion_rate: self.motion_rate.value(),
+            motion_threshold: self.motion_threshold.value(),
+            burst_before: self.burst_before.value(),
+            burst_after: self.burst_after.value(),
+            #[cfg(feature = "ocr")]
+            ocr: self.ocr.is_checked(),
+            #[cfg(not(feature = "ocr"))]
+            ocr: false,
+        }
+    }
+
+    fn show(&mut self, settings: &JobSettings) {
+        self.name.set_value(&settings.name);
+        self.target.set_value(&settings.target);
+        self.cursor.set_checked(settings.cursor);
+        self.resize.set_value(&settings.resize);
+        self.filter.set_value(settings.filter);
+        self.grayscale.set_checked(settings.grayscale);
+        self.output_folder.set_value(&settings.output_folder);
+        self.format.set_value(&settings.format);
+        self.archive.set_checked(settings.archive);
+        self.interval.set_value(&i18n::localize_duration(&settings.interval));
+        self.stop_mode.set_value(settings.stop_mode);
+        self.stop_value.set_value(&match settings.stop_mode {
+            0 => i18n::localize_duration(&settings.stop_value),
+            _ => settings.stop_value.clone(),
+        });
+        self.hook_command.set_value(&settings.hook_command);
+        self.hook_timeout.set_value(&i18n::localize_duration(&settings.hook_timeout));
+        self.window_trigger.set_checked(settings.window_trigger);
+        self.clipboard_trigger.set_checked(settings.clipboard_trigger);
+        self.watch_path.set_value(&settings.watch_path);
+        self.debounce.set_value(&i18n::localize_duration(&settings.debounce));
+        self.motion.set_checked(settings.motion);
+        self.motion_rate.set_value(&i18n::localize_duration(&settings.motion_rate));
+        self.motion_threshold.set_value(&settings.motion_threshold);
+        self.burst_before.set_value(&settings.burst_before);
+        self.burst_after.set_value(&settings.burst_after);
+        #[cfg(feature = "ocr")]
+        self.ocr.set_checked(settings.ocr);
+        if settings.stop_mode == 3 {
+            self.stop_value.deactivate();
+        } else {
+            self.stop_value.activate();
+        }
+        self.show_preview();
+    }
+
+    /// Lists the next files the job would write if started now, and what may
+    /// go wrong with them.
+    fn show_preview(&mut self) {
+        let text = match preview::preview(&self.read(), Local::now()) {
+            Ok(preview) => {
+                let mut lines: Vec<String> = preview.paths.iter().map(|path| path.display().to_string()).collect();
+                lines.extend(preview.warnings.iter().map(|warning| trf("Warning: {warning}", &[("warning", warning)])));
+                lines.join("\n")
+            }
+            Err(error) => error.replace('\n', " "),
+        };
+        if let Some(mut buffer) = self.preview.buffer() {
+            buffer.set_text(&text);
+        }
+    }
+}
+
+/// One line of the job list: name, state and number of screenshots.
+fn job_line(job: &Job) -> String {
+    let status = job.controller.status();
+    status_line(&job.settings.name, status.running, status.paused, status.captures, status.failed)
+}
+
+fn status_line(name: &str, running: bool, paused: bool, captures: u64, failed: u64) -> String {
+    let state = if paused {
+        tr("paused")
+    } else if running {
+        tr("running")
+    } else {
+        tr("stopped")
+    };
+    let mut line = format!("{name}\t{state}\t{}", trf("{captures} screenshots", &[("captures", &captures)]));
+    if failed > 0 {
+        line.push_str(&trf(", {failed} failed", &[("failed", &failed)]));
+    }
+    line
+}
+
+/// A job of the daemon, as in the reply of the control socket.
+#[cfg(unix)]
+fn remote_line(job: &serde_json::Value) -> String {
+    status_line(
+        job["name"].as_str().unwrap_or_default(),
+        job["running"].as_bool().unwrap_or(false),
+        job["paused"].as_bool().unwrap_or(false),
+        job["captures"].as_u64().unwrap_or(0),
+        job["failed"].as_u64().unwrap_or(0),
+    )
+}
+
+/// The window when the daemon is running: its jobs, controlled through the
+/// control socket. Settings are changed in the profile, which the daemon
+/// reads when it starts.
+#[cfg(unix)]
+fn attach(app: &app::App, profile: &str) {
+    let mut window = window::SingleWindow::default()
+        .with_size(410, 164)
+        .with_label(&trf("Take Screenshots, daemon with the \"{profile}\" profile", &[("profile", &profile)]));
+
+    let mut vpack = group::Pack::default()
+        .with_size(390, 140)
+        .center_of_parent();
+
+    vpack.set_spacing(12);
+
+    let mut job_browser = browser::HoldBrowser::default()
+        .with_size(390, 90);
+
+    job_browser.set_column_widths(&[150, 70, 170]);
+    job_browser.set_column_char('\t');
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let mut button_start = button::Button::default()
+        .with_label(tr("Start"));
+
+    let mut button_pause = button::Button::default()
+        .with_label(tr("Pause"));
+
+    let mut button_capture = button::Button::default()
+        .with_label(tr("Capture now"));
+
+    let mut button_report = button::Button::default()
+        .with_label(tr("Report"));
+
+    button_report.set_tooltip(tr("Write an HTML activity report for the output folder of the job"));
+
+    flex.end();
+    vpack.end();
+
+    window.end();
+    window.show();
+
+    let (s, r) = app::channel();
+    let (report_s, report_r) = app::channel::<Result<PathBuf, String>>();
+    button_start.emit(s, "start");
+    button_pause.emit(s, "pause");
+    button_capture.emit(s, "capture-now");
+    button_report.emit(s, "report");
+    job_browser.emit(s, "refresh");
+
+    app::add_timeout3(1.0, move |handle| {
+        s.send("refresh");
+        app::repeat_timeout3(1.0, handle);
+    });
+    s.send("refresh");
+
+    let mut jobs: Vec<serde_json::Value> = Vec::new();
+    while app.wait() {
+        if let Some(result) = report_r.recv() {
+            button_report.activate();
+            match result {
+                Ok(path) => dialog::message_default(&trf("Report written to {path}", &[("path", &path.display())])),
+                Err(error) => dialog::message_default(&error),
+            }
+        }
+
+        let Some(msg) = r.recv() else {
+            continue;
+        };
+        let job = match job_browser.value() {
+            0 => None,
+            line => jobs.get(line as usize - 1).cloned(),
+        };
+        let name = job.as_ref().and_then(|job| job["name"].as_str()).unwrap_or_default().to_owned();
+        match msg {
+            "refresh" => {
+                let reply = control::request("status").and_then(|reply| {
+                    serde_json::from_str::<serde_json::Value>(&reply).map_err(|error| error.to_string())
+                });
+                let Ok(reply) = reply else {
+                    dialog::message_default(tr("The daemon stopped."));
+                    break;
+                };
+                jobs = reply["jobs"].as_array().cloned().unwrap_or_default();
+
+                let selected = job_browser.value().max(1);
+                job_browser.clear();
+                for job in &jobs {
+                    job_browser.add(&remote_line(job));
+                }
+                if !jobs.is_empty() {
+                    job_browser.select(selected.min(jobs.len() as i32));
+                }
+
+                let job = jobs.get(job_browser.value().max(1) as usize - 1);
+                let running = job.is_some_and(|job| job["running"].as_bool() == Some(true));
+                let paused = job.is_some_and(|job| job["paused"].as_bool() == Some(true));
+                button_start.set_label(tr(if running { "Stop" } else { "Start" }));
+                button_pause.set_label(tr(if paused { "Resume" } else { "Pause" }));
+                if running {
+                    button_pause.activate();
+                    button_capture.activate();
+                } else {
+                    button_pause.deactivate();
+                    button_capture.deactivate();
+                }
+            },
+            "start" | "pause" | "capture-now" => {
+                if name.is_empty() {
+                    continue;
+                }
+                let running = job.is_some_and(|job| job["running"].as_bool() == Some(true));
+                let command = match msg {
+                    "start" if running => "stop",
+                    command => command,
+                };
+                let reply = control::request(&format!("{command} {name}")).and_then(|reply| {
+                    let reply: serde_json::Value = serde_json::from_str(&reply).map_err(|error| error.to_string())?;
+                    match reply["error"].as_str() {
+                        Some(error) => Err(error.to_owned()),
+                        None => Ok(()),
+                    }
+                });
+                if let Err(error) = reply {
+                    dialog::message_default(&error);
+                }
+                s.send("refresh");
+            },
+            "report" => {
+                let Some(folder) = job.as_ref().and_then(|job| job["folder"].as_str()) else {
+                    dialog::message_default(tr("The job hasn't taken any screenshots yet."));
+                    continue;
+                };
+                let folder = PathBuf::from(folder);
+                button_report.deactivate();
+                tokio::task::spawn_blocking(move || {
+                    report_s.send(report::generate(&folder));
+                });
+            },
+            _ => {}
+        }
+    }
+}
+
+/// The screenshots of `folder`, searched by their text. Picking a match
+/// shows the screenshot.
+#[cfg(feature = "ocr")]
+fn search_window(folder: &str) {
+    let mut window = window::SingleWindow::default()
+        .with_size(640, 560)
+        .with_label(&trf("Search {folder}", &[("folder", &folder)]));
+
+    let mut vpack = group::Pack::default()
+        .with_size(620, 536)
+        .center_of_parent();
+
+    vpack.set_spacing(12);
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let mut search_input = input::Input::default();
+
+    search_input.set_trigger(enums::CallbackTrigger::EnterKeyAlways);
+    search_input.set_tooltip(tr("Words on the screen, each matching the beginning of a word"));
+
+    let mut button_search = button::Button::default()
+        .with_label(tr("Search"));
+
+    flex.set_size(&button_search, 70);
+    flex.end();
+
+    let mut match_browser = browser::HoldBrowser::default()
+        .with_size(620, 140);
+
+    match_browser.set_column_widths(&[140, 90, 390]);
+    match_browser.set_column_char('\t');
+
+    let mut image_frame = frame::Frame::default()
+        .with_size(620, 345)
+        .with_align(enums::Align::Center | enums::Align::Inside);
+
+    image_frame.set_frame(enums::FrameType::DownBox);
+    vpack.end();
+
+    window.end();
+    window.show();
+
+    let matches: std::rc::Rc<std::cell::RefCell<Vec<ocr::Match>>> = Default::default();
+
+    let search = {
+        let folder = PathBuf::from(folder);
+        let matches = matches.clone();
+        let search_input = search_input.clone();
+        let mut match_browser = match_browser.clone();
+        let mut image_frame = image_frame.clone();
+        move || {
+            match_browser.clear();
+            image_frame.set_image(None::<image::RgbImage>);
+            image_frame.set_label("");
+            match ocr::search(&folder, &search_input.value()) {
+                Ok(results) => {
+                    for found in &results {
+                        match_browser.add(&format!("{}\t{}\t{}", found.time.format("%Y-%m-%d %H:%M:%S"), found.job, found.snippet));
+                    }
+                    if results.is_empty() {
+                        image_frame.set_label(tr("No screenshot shows that."));
+                    }
+                    *matches.borrow_mut() = results;
+                }
+                Err(error) => image_frame.set_label(&error),
+            }
+            image_frame.redraw();
+        }
+    };
+    let mut search_clicked = search.clone();
+    button_search.set_callback(move |_| search_clicked());
+    let mut search_entered = search;
+    search_input.set_callback(move |_| search_entered());
+
+    match_browser.set_callback(move |browser| {
+        let line = browser.value();
+        let Some(found) = (line > 0).then(|| matches.borrow().get(line as usize - 1).cloned()).flatten() else {
+            return;
+        };
+        // The frame shows the screenshot scaled down to fit.
+        let shown = archive::read_path(&found.path)
+            .and_then(|data| ::image::load_from_memory(&data).map_err(|error| trf("Failed to read {path}: {error}", &[("path", &found.path.display()), ("error", &error)])))
+            .and_then(|picture| {
+                let picture = picture.thumbnail(image_frame.w() as u32 - 4, image_frame.h() as u32 - 4).to_rgba8();
+                image::RgbImage::new(picture.as_raw(), picture.width() as i32, picture.height() as i32, enums::ColorDepth::Rgba8).map_err(|error| error.to_string())
+            });
+        match shown {
+            Ok(picture) => {
+                image_frame.set_label("");
+                image_frame.set_image(Some(picture));
+            }
+            Err(error) => {
+                image_frame.set_image(None::<image::RgbImage>);
+                image_frame.set_label(&error);
+            }
+        }
+        image_frame.set_tooltip(&found.path.display().to_string());
+        image_frame.redraw();
+    });
+}
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let app = app::App::default();
 
+    #[cfg(unix)]
+    let control_listener = match control::acquire() {
+        Ok(Some(listener)) => Some(listener),
+        Ok(None) => {
+            match control::daemon_profile() {
+                Some(profile) => attach(&app, &profile),
+                None => dialog::alert_default(tr("Take Screenshots is already running.\nUse \"take-screenshots ctl\" to control it.")),
+            }
+            return;
+        }
+        Err(error) => {
+            println!("{error}");
+            None
+        }
+    };
+
     let mut picture_folder = "".to_owned();
 
     if let Some(user_folder) = UserDirs::new() {
@@ -19,14 +477,20 @@ async fn main() {
         }
     }
 
+    let backend = Backend::detect();
+    println!("Taking screenshots with {}", backend.name());
+    let jobs = Arc::new(Jobs::new(Arc::new(Grabber::new(backend))));
+    jobs.add(JobSettings::new(tr("Desktop"), &picture_folder));
+    let mut selected = 0;
+
     let mut main_window = window::SingleWindow::default()
-        .with_size(410, 210)
-        .with_label("Take Screenshots");
+        .with_size(410, 781)
+        .with_label(tr("Take Screenshots"));
 
     let mut vpack = group::Pack::default()
-        .with_size(390, 186)
+        .with_size(390, 757)
         .center_of_parent();
-    
+
     vpack.set_spacing(12);
 
     let mut flex = group::Flex::default()
@@ -36,32 +500,86 @@ async fn main() {
     let frame = frame::Frame::default()
         .with_size(330, 25)
         .with_align(enums::Align::Left | enums::Align::Inside)
-        .with_label("Use Strategy");
+        .with_label(tr("Use Strategy"));
 
     let mut screenshot_strategy = menu::Choice::default()
         .with_size(330, 25);
 
-    screenshot_strategy.add_choice("screenshots-rs(Cross platform, default)|ksnip(Cross platform)|NirCmd(Only Windows)|Python MSS(Cross platform)");
+    screenshot_strategy.add_choice(tr("screenshots-rs(Cross platform, default)|ksnip(Cross platform)|NirCmd(Only Windows)|Python MSS(Cross platform)");
     screenshot_strategy.set_value(0);
 
     flex.set_size(&frame, 85);
     flex.end();
 
+    let mut job_browser = browser::HoldBrowser::default()
+        .with_size(390, 90);
+
+    job_browser.set_column_widths(&[150, 70, 170]);
+    job_browser.set_column_char('\t');
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let mut button_add = button::Button::default()
+        .with_label(tr("Add job"));
+
+    let mut button_remove = button::Button::default()
+        .with_label(tr("Remove job"));
+
+    let mut button_start = button::Button::default()
+        .with_label(tr("Start screenshot"));
+
+    flex.set_size(&button_add, 90);
+    flex.set_size(&button_remove, 110);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let frame_name = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("Job"));
+
+    let name_input = input::Input::default()
+        .with_size(330, 25);
+
+    let frame_target = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr(", capture"));
+
+    let mut target_input = input::Input::default()
+        .with_size(330, 25);
+
+    target_input.set_tooltip(tr("Empty for the screen, a screen number like \"2\",\na region like \"800x600+0+0\" or both like \"2:800x600+0+0\""));
+
+    let mut cursor_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("Cursor"));
+
+    cursor_checkbutton.set_tooltip(tr("Draw the mouse cursor into the screenshots (X11 only)"));
+
+    flex.set_size(&frame_name, 30);
+    flex.set_size(&frame_target, 60);
+    flex.set_size(&cursor_checkbutton, 70);
+    flex.end();
+
     let mut flex = group::Flex::default()
         .with_size(280, 25)
         .with_type(group::FlexType::Row);
 
     let frame = frame::Frame::default()
-        .with_label("Save at")
+        .with_label(tr("Save at"))
         .with_align(enums::Align::Left | enums::Align::Inside);
-    
+
     let mut output_folder_input = input::Input::default()
         .with_align(enums::Align::TopLeft);
 
-    output_folder_input.set_value(&picture_folder);
+    output_folder_input.set_trigger(enums::CallbackTrigger::Changed);
 
     let mut button_select = button::Button::default()
-        .with_label("Select");
+        .with_label(tr("Select"));
 
     flex.set_size(&frame, 55);
     flex.set_size(&mut button_select, 60);
@@ -74,140 +592,760 @@ async fn main() {
     let frame = frame::Frame::default()
         .with_size(330, 25)
         .with_align(enums::Align::Left | enums::Align::Inside)
-        .with_label("with name");
- 
+        .with_label(tr("with name"));
+
     let mut filename_format_input = input::Input::default()
         .with_size(330, 25);
 
-    filename_format_input.set_value("ts_%Y_%m_%d-%H_%M_%S.png");  
+    filename_format_input.set_trigger(enums::CallbackTrigger::Changed);
+
+    let mut archive_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("In an archive"));
+
+    archive_checkbutton.set_tooltip(tr("Save the screenshots of each session into one SESSION.tar file in the folder"));
+
+    #[cfg(feature = "ocr")]
+    let ocr_checkbutton = {
+        let mut ocr_checkbutton = button::CheckButton::default()
+            .with_size(0, 25)
+            .with_label(tr("OCR"));
+
+        ocr_checkbutton.set_tooltip(tr("Recognize the text of each screenshot with tesseract,\nto find it with \"Search\""));
+        flex.set_size(&ocr_checkbutton, 55);
+        ocr_checkbutton
+    };
 
     flex.set_size(&frame, 70);
+    flex.set_size(&archive_checkbutton, 110);
     flex.end();
-    
+
+    let mut preview_display = text::TextDisplay::default()
+        .with_size(390, 100);
+
+    preview_display.set_buffer(text::TextBuffer::default());
+    preview_display.wrap_mode(text::WrapMode::AtBounds, 0);
+    preview_display.set_text_size(12);
+    preview_display.set_tooltip(tr("The next screenshots of the job if it started now"));
+
     let mut flex = group::Flex::default()
         .with_size(280, 25)
         .with_type(group::FlexType::Row);
 
-    let frame_stop_at = frame::Frame::default()
+    let frame_resize = frame::Frame::default()
         .with_align(enums::Align::Left | enums::Align::Inside)
-        .with_label("Stop in");
- 
+        .with_label(tr("Resize to"));
+
+    let mut resize_input = input::Input::default()
+        .with_size(330, 25);
+
+    resize_input.set_tooltip(tr("Empty to keep the size, a percentage like \"50%\"\nor a maximum size like \"1920x1080\", \"1920x\" or \"x1080\""));
+
+    let mut filter_choice = menu::Choice::default()
+        .with_size(330, 25);
+
+    filter_choice.add_choice(FILTER_CHOICES);
+    filter_choice.set_tooltip(tr("Resampling filter"));
+
+    let grayscale_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("Grayscale"));
+
+    flex.set_size(&frame_resize, 65);
+    flex.set_size(&filter_choice, 100);
+    flex.set_size(&grayscale_checkbutton, 90);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let mut stop_mode_choice = menu::Choice::default()
+        .with_size(330, 25);
+
+    stop_mode_choice.add_choice(tr(STOP_MODE_CHOICES));
+
     let mut stop_time_input = input::Input::default()
         .with_size(330, 25);
 
-    stop_time_input.set_value("2 hours");
+    stop_time_input.set_trigger(enums::CallbackTrigger::Changed);
 
     frame::Frame::default()
         .with_align(enums::Align::Left | enums::Align::Inside)
-        .with_label(", with duration");
+        .with_label(tr(", with duration"));
 
     let mut duration_input = input::Input::default()
         .with_size(330, 25);
 
-    duration_input.set_value("5 minutes");
+    duration_input.set_trigger(enums::CallbackTrigger::Changed);
 
-    flex.set_size(&frame_stop_at, 50);
+    flex.set_size(&stop_mode_choice, 85);
     flex.end();
-    
+
     let mut flex = group::Flex::default()
-        .with_size(320, 25);
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
 
-    let mut button_start = button::Button::default()
-        .with_label("Start screenshot");
+    let frame_hook = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("Run hook"));
+
+    let mut hook_command_input = input::Input::default()
+        .with_size(330, 25);
+
+    hook_command_input.set_tooltip(tr("Command run at start, after each capture and at end.\nUse {event}, {path}, {timestamp}, {screen} or $TS_PATH..."));
+
+    let frame_timeout = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr(", timeout"));
+
+    let hook_timeout_input = input::Input::default()
+        .with_size(330, 25);
+
+    flex.set_size(&frame_hook, 60);
+    flex.set_size(&frame_timeout, 55);
+    flex.set_size(&hook_timeout_input, 60);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let frame_triggers = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("Also on"));
+
+    let mut window_trigger_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("window switch"));
+
+    window_trigger_checkbutton.set_tooltip(tr("Take a screenshot when the focused window changes (X11 only)"));
+
+    let mut clipboard_trigger_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("clipboard"));
+
+    clipboard_trigger_checkbutton.set_tooltip(tr("Take a screenshot when something is copied (X11 only)"));
+
+    let frame_debounce = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr(", after"));
+
+    let mut debounce_input = input::Input::default()
+        .with_size(330, 25);
+
+    debounce_input.set_tooltip(tr("Events until then are folded into one screenshot"));
+
+    flex.set_size(&frame_triggers, 55);
+    flex.set_size(&window_trigger_checkbutton, 115);
+    flex.set_size(&clipboard_trigger_checkbutton, 90);
+    flex.set_size(&frame_debounce, 45);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let frame_watch = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("and writes to"));
+
+    let mut watch_input = input::Input::default()
+        .with_size(330, 25);
+
+    watch_input.set_tooltip(tr("A file or folder; a screenshot is taken when it is written.\nLeave empty to disable."));
+
+    flex.set_size(&frame_watch, 95);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let mut motion_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("Motion"));
+
+    motion_checkbutton.set_tooltip(tr("Sample the screen and save a burst of frames when it changes"));
+
+    let frame_motion_rate = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("every"));
+
+    let mut motion_rate_input = input::Input::default()
+        .with_size(330, 25);
+
+    motion_rate_input.set_tooltip(tr("Time between samples, and between the frames of a burst"));
+
+    let frame_motion_threshold = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("over"));
+
+    let mut motion_threshold_input = input::Input::default()
+        .with_size(330, 25);
+
+    motion_threshold_input.set_tooltip(tr("How much of the picture must change, like \"2%\""));
+
+    let frame_burst = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("frames"));
+
+    let mut burst_before_input = input::Input::default()
+        .with_size(330, 25);
+
+    burst_before_input.set_tooltip(tr("Frames saved from before the change, kept in memory until then"));
+
+    let frame_burst_after = frame::Frame::default()
+        .with_align(enums::Align::Center | enums::Align::Inside)
+        .with_label(tr("+"));
+
+    let mut burst_after_input = input::Input::default()
+        .with_size(330, 25);
+
+    burst_after_input.set_tooltip(tr("Frames saved after the change"));
+
+    flex.set_size(&motion_checkbutton, 75);
+    flex.set_size(&frame_motion_rate, 40);
+    flex.set_size(&frame_motion_threshold, 35);
+    flex.set_size(&motion_threshold_input, 45);
+    flex.set_size(&frame_burst, 50);
+    flex.set_size(&burst_before_input, 35);
+    flex.set_size(&frame_burst_after, 15);
+    flex.set_size(&burst_after_input, 35);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let frame_hotkey = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("Hotkey"));
+
+    let mut hotkey_input = input::Input::default()
+        .with_size(330, 25);
+
+    hotkey_input.set_tooltip(tr("Takes an extra screenshot in every running job (X11 only).\nLeave empty to disable."));
+
+    let note_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("Ask for a note"));
+
+    flex.set_size(&frame_hotkey, 60);
+    flex.set_size(&note_checkbutton, 120);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let frame_serve = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("Serve at"));
+
+    let mut serve_input = input::Input::default()
+        .with_size(330, 25);
+
+    serve_input.set_value(server::DEFAULT_ADDRESS);
+    serve_input.set_tooltip(tr("A port, served to this computer only, or an address and port.\nOpen it in a browser to see the latest screenshot."));
+
+    let frame_token = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("token"));
+
+    let mut token_input = input::SecretInput::default()
+        .with_size(330, 25);
+
+    token_input.set_tooltip(tr("Optional, then requests need ?token=TOKEN.\nRequired to serve other computers."));
+
+    let mut serve_checkbutton = button::CheckButton::default()
+        .with_size(0, 25)
+        .with_label(tr("On"));
+
+    flex.set_size(&frame_serve, 60);
+    flex.set_size(&frame_token, 45);
+    flex.set_size(&token_input, 80);
+    flex.set_size(&serve_checkbutton, 45);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(280, 25)
+        .with_type(group::FlexType::Row);
+
+    let frame_profile = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Inside)
+        .with_label(tr("Profile"));
+
+    let mut profile_input = input::Input::default()
+        .with_size(330, 25);
+
+    profile_input.set_tooltip(tr("The jobs are saved under this name.\nThe daemon started at login runs them."));
+
+    let mut button_profile_save = button::Button::default()
+        .with_label(tr("Save"));
+
+    let mut button_profile_load = button::Button::default()
+        .with_label(tr("Load"));
+
+    let mut autostart_choice = menu::Choice::default()
+        .with_size(330, 25);
+
+    autostart_choice.add_choice(tr(autostart::CHOICES));
+    autostart_choice.set_tooltip(tr("Run the jobs of the profile without the window when you log in.\nThe window attaches to it when opened."));
+
+    match autostart::current() {
+        Some((method, profile)) => {
+            profile_input.set_value(&profile);
+            autostart_choice.set_value(Method::choice(Some(method)));
+        }
+        None => {
+            profile_input.set_value(profile::DEFAULT);
+            autostart_choice.set_value(Method::choice(None));
+        }
+    }
+
+    flex.set_size(&frame_profile, 55);
+    flex.set_size(&button_profile_save, 50);
+    flex.set_size(&button_profile_load, 50);
+    flex.set_size(&autostart_choice, 140);
+    flex.end();
+
+    let mut flex = group::Flex::default()
+        .with_size(320, 25);
 
     let minimize_checkbutton = button::CheckButton::default()
         .with_size(0, 25)
-        .with_label("Minimize the window");
+        .with_label(tr("Minimize the window"));
 
     minimize_checkbutton.set_checked(true);
-    
-    flex.set_size(&minimize_checkbutton, 150);
-    flex.end();    
+
+    let mut button_report = button::Button::default()
+        .with_label(tr("Report"));
+
+    button_report.set_tooltip(tr("Write an HTML activity report for the output folder of the job"));
+
+    #[cfg(feature = "ocr")]
+    let mut button_search = button::Button::default()
+        .with_label(tr("Search"));
+
+    #[cfg(feature = "ocr")]
+    button_search.set_tooltip(tr("Find screenshots in the output folder of the job by their text"));
+
+    flex.set_size(&button_report, 60);
+    #[cfg(feature = "ocr")]
+    flex.set_size(&button_search, 60);
+    flex.end();
     vpack.end();
 
     main_window.end();
     main_window.show();
 
+    let mut form = JobForm {
+        name: name_input,
+        target: target_input,
+        cursor: cursor_checkbutton,
+        resize: resize_input,
+        filter: filter_choice,
+        grayscale: grayscale_checkbutton,
+        output_folder: output_folder_input.clone(),
+        format: filename_format_input.clone(),
+        archive: archive_checkbutton.clone(),
+        stop_mode: stop_mode_choice.clone(),
+        stop_value: stop_time_input.clone(),
+        interval: duration_input.clone(),
+        hook_command: hook_command_input,
+        hook_timeout: hook_timeout_input,
+        window_trigger: window_trigger_checkbutton,
+        clipboard_trigger: clipboard_trigger_checkbutton,
+        watch_path: watch_input,
+        debounce: debounce_input,
+        motion: motion_checkbutton,
+        motion_rate: motion_rate_input,
+        motion_threshold: motion_threshold_input,
+        burst_before: burst_before_input,
+        burst_after: burst_after_input,
+        #[cfg(feature = "ocr")]
+        ocr: ocr_checkbutton,
+        preview: preview_display,
+    };
+
+    // Sessions this window was running when it was killed, or the computer
+    // restarted. Closing the question asks again next time.
+    let interrupted = resume::interrupted(Local::now());
+    if !interrupted.is_empty() {
+        let list: Vec<String> = interrupted.iter().map(|state| {
+            trf("{job} in {folder}, started {started}, {captures} screenshots", &[("job", &state.settings.name), ("folder", &state.settings.output_folder), ("started", &state.started.format("%Y-%m-%d %H:%M")), ("captures", &state.captures)])
+        }).collect();
+        let question = trf("These sessions were interrupted:\n\n{sessions}\n\nResume them?", &[("sessions", &list.join("\n"))]);
+        match dialog::choice2_default(&question, tr("Discard"), tr("Resume"), "") {
+            Some(0) => {
+                if let Some(folder) = resume::folder() {
+                    for state in &interrupted {
+                        resume::remove(&folder, state);
+                    }
+                }
+            }
+            Some(_) => {
+                for state in interrupted {
+                    let name = state.settings.name.clone();
+                    if let Err(error) = jobs.resume(state) {
+                        println!("{error}");
+                        dialog::message_default(&trf("Failed to resume \"{name}\": {error}", &[("name", &name), ("error", &error)]));
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    for job in jobs.all() {
+        job_browser.add(&job_line(&job));
+    }
+    job_browser.select(1);
+    form.show(&jobs.all()[0].settings);
+
+    let mut hotkey_registration: Option<hotkey::Registration> = None;
+    let mut http_server: Option<tokio::task::JoinHandle<()>> = None;
+    let mut any_running = false;
+
     let (s, r) = app::channel();
+    let (report_s, report_r) = app::channel::<Result<PathBuf, String>>();
     button_select.emit(s, "dialog");
     button_start.emit(s, "start");
+    button_add.emit(s, "add");
+    button_remove.emit(s, "remove");
+    job_browser.emit(s, "select");
+    stop_mode_choice.emit(s, "stop_mode");
+    button_report.emit(s, "report");
+    #[cfg(feature = "ocr")]
+    button_search.emit(s, "search");
+    serve_checkbutton.emit(s, "serve");
+    button_profile_save.emit(s, "profile_save");
+    button_profile_load.emit(s, "profile_load");
+    autostart_choice.emit(s, "autostart");
+    output_folder_input.emit(s, "preview");
+    filename_format_input.emit(s, "preview");
+    stop_time_input.emit(s, "preview");
+    duration_input.emit(s, "preview");
+    archive_checkbutton.emit(s, "preview");
     // button_minimize.emit(s, "hide");
-    
+
+    app::add_timeout3(0.5, move |handle| {
+        s.send("refresh");
+        app::repeat_timeout3(0.5, handle);
+    });
+
+    #[cfg(unix)]
+    if let Some(listener) = control_listener {
+        tokio::spawn(control::serve(listener, jobs.clone(), None));
+    }
+
+    // The preview is made once typing pauses, not on every key.
+    let mut preview_timeout: Option<app::TimeoutHandle> = None;
+
     while app.wait() {
+        if let Some(result) = report_r.recv() {
+            button_report.activate();
+            match result {
+                Ok(path) => dialog::message_default(&trf("Report written to {path}", &[("path", &path.display())])),
+                Err(error) => dialog::message_default(&error),
+            }
+        }
+
         if let Some(msg) = r.recv() {
             match msg {
                 "dialog" => {
                     let mut dialog = dialog::FileDialog::new(dialog::FileDialogType::BrowseDir);
                     dialog.show();
                     let path = dialog.filename();
-                    if let Some(p) = path.to_str() {            
-                        println!("{p}");                        
-                        output_folder_input.set_value(p);                       
+                    if let Some(p) = path.to_str() {
+                        println!("{p}");
+                        form.output_folder.set_value(p);
+                        form.show_preview();
                     };
                 },
-                "start" => {                    
-                    let format = filename_format_input.value();
-
-                    let durationt_time = duration_input.value().parse::<humantime::Duration>();
-                    let stop_time = stop_time_input.value().parse::<humantime::Duration>();
-
-                    let mut strftime_items = strftime::StrftimeItems::new(format.as_str());
-                    let corret_format = !strftime_items.any(|item| matches!(item, Item::Error));
-                    
-                    let output_folder = output_folder_input.value();
-
-                    match (durationt_time, stop_time, corret_format) {
-                        (Ok(duration), Ok(stop), true) => {
-                            let times = stop.as_millis() / duration.as_millis();
-                            if stop.as_millis() > 0 {
-
-                                if minimize_checkbutton.is_checked() {
-                                    main_window.iconize();
-                                    tokio::time::sleep(std::time::Duration::from_millis(600)).await;
-                                }
-                                button_start.deactivate();
-
-                                tokio::spawn(async move {
-                                    
-                                    let mut interval = tokio::time::interval(duration.into());
-
-                                    for _ in 0..times {
-                                        interval.tick().await;
-                                        screenshot(&format, &output_folder).await;
-                                    }
-
-                                    s.send("activate");
-                                });                                
-                            }                              
-                        },
-                        _ => {
-                            println!("Failed to parse time...");
-                            dialog::message_default("Failed to parse time...");
+                "preview" => {
+                    if let Some(handle) = preview_timeout.take() {
+                        app::remove_timeout3(handle);
+                    }
+                    preview_timeout = Some(app::add_timeout3(0.3, move |_| s.send("show_preview")));
+                },
+                "show_preview" => {
+                    preview_timeout = None;
+                    form.show_preview();
+                },
+                "stop_mode" => {
+                    let (example, tooltip) = match form.stop_mode.value() {
+                        0 => (i18n::localize_duration("2 hours"), "How long to keep taking screenshots"),
+                        1 => ("18:00".to_owned(), "Time of day (HH:MM) or date and time (YYYY-MM-DD HH:MM)"),
+                        2 => ("100".to_owned(), "Number of screenshots to take"),
+                        _ => (String::new(), "Runs until \"Stop screenshot\" is pressed"),
+                    };
+                    form.stop_value.set_value(&example);
+                    form.stop_value.set_tooltip(tr(tooltip));
+                    if form.stop_mode.value() == 3 {
+                        form.stop_value.deactivate();
+                    } else {
+                        form.stop_value.activate();
+                    }
+                    form.show_preview();
+                },
+                "select" => {
+                    // Clicking below the last job selects nothing, keep the current one.
+                    if job_browser.value() <= 0 {
+                        job_browser.select(selected as i32 + 1);
+                        continue;
+                    }
+                    jobs.set_settings(selected, form.read());
+                    selected = job_browser.value() as usize - 1;
+                    if let Some(job) = jobs.get(selected) {
+                        form.show(&job.settings);
+                    }
+                    s.send("refresh");
+                },
+                "add" => {
+                    jobs.set_settings(selected, form.read());
+                    let count = jobs.all().len();
+                    let settings = JobSettings::new(&trf("Job {number}", &[("number", &(count + 1))]), &form.output_folder.value());
+                    selected = jobs.add(settings.clone());
+                    job_browser.add("");
+                    job_browser.select(selected as i32 + 1);
+                    form.show(&settings);
+                    s.send("refresh");
+                },
+                "remove" => {
+                    if jobs.all().len() == 1 {
+                        dialog::message_default(tr("The last job can't be removed."));
+                        continue;
+                    }
+                    if let Err(error) = jobs.remove(selected) {
+                        dialog::message_default(&error);
+                        continue;
+                    }
+                    job_browser.remove(selected as i32 + 1);
+                    selected = selected.min(jobs.all().len() - 1);
+                    job_browser.select(selected as i32 + 1);
+                    if let Some(job) = jobs.get(selected) {
+                        form.show(&job.settings);
+                    }
+                    s.send("refresh");
+                },
+                "start" => {
+                    jobs.set_settings(selected, form.read());
+                    let Some(job) = jobs.get(selected) else {
+                        continue;
+                    };
+
+                    if job.controller.is_running() {
+                        job.controller.send(SessionCommand::Stop);
+                        button_start.deactivate();
+                        continue;
+                    }
+
+                    if !hotkey_input.value().trim().is_empty() {
+                        if let Err(error) = hotkey::Hotkey::parse(&hotkey_input.value()) {
+                            println!("{error}");
+                            dialog::message_default(&error);
+                            continue;
+                        }
+                    }
+
+                    form.show_preview();
+                    if let Ok(mut preview) = preview::preview(&job.settings, Local::now()) {
+                        // Too slow for the live preview, only checked here.
+                        let folder = std::path::Path::new(&job.settings.output_folder);
+                        if folder.is_dir() {
+                            if let Err(error) = preview::check_writable(folder) {
+                                preview.warnings.push(error);
+                            }
+                        }
+                        if !preview.warnings.is_empty() {
+                            let question = trf("{warnings}\n\nStart anyway?", &[("warnings", &preview.warnings.join("\n\n"))]);
+                            if dialog::choice2_default(&question, tr("Cancel"), tr("Start"), "") != Some(1) {
+                                continue;
+                            }
                         }
                     }
+
+                    if minimize_checkbutton.is_checked() {
+                        main_window.iconize();
+                        tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+                    }
+
+                    if let Err(error) = jobs.start(selected) {
+                        println!("{error}");
+                        main_window.show();
+                        dialog::message_default(&error);
+                        continue;
+                    }
+                    s.send("refresh");
+                },
+                "report" => {
+                    let folder = PathBuf::from(form.output_folder.value());
+                    button_report.deactivate();
+                    tokio::task::spawn_blocking(move || {
+                        report_s.send(report::generate(&folder));
+                    });
+                },
+                #[cfg(feature = "ocr")]
+                "search" => {
+                    search_window(&form.output_folder.value());
+                },
+                "serve" => {
+                    if let Some(server) = http_server.take() {
+                        server.abort();
+                    }
+                    if !serve_checkbutton.is_checked() {
+                        continue;
+                    }
+
+                    let token = token_input.value();
+                    let listener = match server::parse_address(&serve_input.value(), &token) {
+                        Ok(address) => tokio::net::TcpListener::bind(address).await.map_err(|error| trf("Failed to serve at {address}: {error}", &[("address", &address), ("error", &error)])),
+                        Err(error) => Err(error),
+                    };
+                    match listener {
+                        Ok(listener) => {
+                            let token = Some(token.trim().to_owned()).filter(|token| !token.is_empty());
+                            http_server = Some(tokio::spawn(server::serve(listener, jobs.clone(), token)));
+                        }
+                        Err(error) => {
+                            println!("{error}");
+                            serve_checkbutton.set_checked(false);
+                            dialog::message_default(&error);
+                        }
+                    }
+                },
+                "profile_save" => {
+                    jobs.set_settings(selected, form.read());
+                    let settings: Vec<JobSettings> = jobs.all().into_iter().map(|job| job.settings).collect();
+                    let saved = profile::folder().ok_or_else(|| tr("Failed to find the configuration folder.").to_owned()).and_then(|folder| profile::save(&folder, &profile_input.value(), &settings));
+                    match saved {
+                        Ok(path) => println!("Saved {}", path.display()),
+                        Err(error) => dialog::message_default(&error),
+                    }
+                },
+                "profile_load" => {
+                    if jobs.any_running() {
+                        dialog::message_default(tr("Stop the jobs before loading a profile."));
+                        continue;
+                    }
+                    let loaded = profile::folder().ok_or_else(|| tr("Failed to find the configuration folder.").to_owned()).and_then(|folder| profile::load(&folder, &profile_input.value()));
+                    let settings = match loaded {
+                        Ok(settings) => settings,
+                        Err(error) => {
+                            dialog::message_default(&error);
+                            continue;
+                        }
+                    };
+                    for index in (0..jobs.all().len()).rev() {
+                        let _ = jobs.remove(index);
+                    }
+                    job_browser.clear();
+                    for settings in settings {
+                        jobs.add(settings);
+                        job_browser.add("");
+                    }
+                    selected = 0;
+                    job_browser.select(1);
+                    form.show(&jobs.all()[0].settings);
+                    s.send("refresh");
+                },
+                "autostart" => {
+                    // The daemon runs the profile as saved now.
+                    let result = match Method::from_choice(autostart_choice.value()) {
+                        Some(method) => {
+                            jobs.set_settings(selected, form.read());
+                            let settings: Vec<JobSettings> = jobs.all().into_iter().map(|job| job.settings).collect();
+                            let profile = profile_input.value();
+                            profile::folder()
+                                .ok_or_else(|| tr("Failed to find the configuration folder.").to_owned())
+                                .and_then(|folder| profile::save(&folder, &profile, &settings))
+                                .and_then(|_| autostart::enable(method, profile.trim()))
+                                .map(|path| println!("Wrote {}", path.display()))
+                        }
+                        None => autostart::disable(),
+                    };
+                    if let Err(error) = result {
+                        println!("{error}");
+                        dialog::message_default(&error);
+                        autostart_choice.set_value(Method::choice(autostart::current().map(|(method, _)| method)));
+                    }
                 },
                 "hide" => {
-                    main_window.iconize();                    
+                    main_window.iconize();
+                },
+                "hotkey" => {
+                    let running: Vec<Job> = jobs.all().into_iter().filter(|job| job.controller.is_running()).collect();
+                    if !note_checkbutton.is_checked() {
+                        for job in &running {
+                            job.controller.send(SessionCommand::CaptureNow { done: None });
+                        }
+                        continue;
+                    }
+
+                    // Ask only once the screenshots are taken, so the popup isn't in them.
+                    let mut captures = Vec::new();
+                    for job in running {
+                        let (done, result) = tokio::sync::oneshot::channel();
+                        if job.controller.send(SessionCommand::CaptureNow { done: Some(done) }) {
+                            captures.push((job, result));
+                        }
+                    }
+                    let mut taken = Vec::new();
+                    for (job, result) in captures {
+                        if let Ok(Ok(path)) = result.await {
+                            taken.push((job, path));
+                        }
+                    }
+                    if taken.is_empty() {
+                        continue;
+                    }
+                    if let Some(note) = dialog::input_default(tr("Note for this screenshot:"), "") {
+                        let note = note.trim().to_owned();
+                        if !note.is_empty() {
+                            for (job, path) in taken {
+                                job.controller.send(SessionCommand::Annotate { path, note: note.clone() });
+                            }
+                        }
+                    }
+                },
+                "refresh" => {
+                    for (index, job) in jobs.all().iter().enumerate() {
+                        let line = job_line(job);
+                        if job_browser.text(index as i32 + 1).as_deref() != Some(line.as_str()) {
+                            job_browser.set_text(index as i32 + 1, &line);
+                        }
+                    }
+
+                    if let Some(job) = jobs.get(selected) {
+                        if job.controller.is_running() {
+                            button_start.set_label(tr("Stop screenshot"));
+                        } else {
+                            button_start.set_label(tr("Start screenshot"));
+                            button_start.activate();
+                        }
+                    }
+
+                    // Jobs can also be started and stopped from the control socket,
+                    // so the hotkey follows whether any job runs.
+                    if jobs.any_running() != any_running {
+                        any_running = !any_running;
+                        hotkey_registration.take();
+                        let hotkey = hotkey_input.value();
+                        if any_running && !hotkey.trim().is_empty() {
+                            match hotkey::Hotkey::parse(&hotkey).and_then(|hotkey| hotkey::register(hotkey, move || s.send("hotkey"))) {
+                                Ok(registration) => hotkey_registration = Some(registration),
+                                Err(error) => println!("Failed to register the hotkey: {error}"),
+                            }
+                        }
+                    }
                 },
-                "activate" => {
-                    button_start.activate();
-                }
                 _ => {}
             }
         }
     }
-}
-
-async fn screenshot(format: &str, output_folder: &str) {
-    let screens = Screen::all().unwrap();
-    if screens.len() > 1 {
-        return;
-    }
 
-    let screen = screens[0];
-    let now: DateTime<Local> = Utc::now().into();     
-    let filename = now.format(&format);  
-    let image = screen.capture().unwrap();
-    let buffer = image.to_png().unwrap();
-    tokio::fs::write(format!("{output_folder}/{filename}"), &buffer).await.unwrap();
-}
\ No newline at end of file
+    #[cfg(unix)]
+    control::release();
+}
@@ -0,0 +1,248 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::capture::Capture;
+use crate::cursor::Pointer;
+
+/// Every session appends to this file in its output folder, one JSON object
+/// per line.
+pub const MANIFEST_FILE: &str = "take-screenshots.jsonl";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Start,
+    /// An interrupted session carries on.
+    Resume,
+    Capture,
+    Failed,
+    End,
+    /// A note added to an earlier capture, matched by `file`.
+    Note,
+}
+
+/// What caused a capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// The interval of the session.
+    Timer,
+    /// The hotkey or `ctl capture-now`.
+    Manual,
+    /// The focused window changed.
+    Window,
+    /// Something was copied to the clipboard.
+    Clipboard,
+    /// The watched file or folder was written.
+    File,
+    /// Part of a burst around motion on the screen.
+    Motion,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub session: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+    pub event: EntryKind,
+    pub time: DateTime<Local>,
+    /// Path of the saved image, relative to the output folder when possible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<Trigger>,
+    /// Pointer position on the image, when it was on it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<Pointer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl Entry {
+    fn new(session: &str, event: EntryKind, time: DateTime<Local>) -> Self {
+        Entry {
+            session: session.to_owned(),
+            job: None,
+            event,
+            time,
+            file: None,
+            screen: None,
+            interval_ms: None,
+            error: None,
+            trigger: None,
+            pointer: None,
+            note: None,
+        }
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval_ms.map(Duration::from_millis)
+    }
+}
+
+/// Appends the entries of one session to the manifest of its output folder.
+/// Write errors are logged, a broken manifest never stops the session.
+pub struct Manifest {
+    folder: PathBuf,
+    job: String,
+    session: String,
+}
+
+impl Manifest {
+    /// The session id is the start time and the job name, so jobs started
+    /// together into the same folder stay apart.
+    pub fn new(folder: &str, job: &str, started: DateTime<Local>) -> Self {
+        let slug: String = job
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
+        Manifest {
+            folder: PathBuf::from(folder),
+            job: job.to_owned(),
+            session: format!("{}-{slug}", started.format("%Y%m%d-%H%M%S")),
+        }
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    pub fn start(&self, time: DateTime<Local>, interval: Duration) {
+        let mut entry = Entry::new(&self.session, EntryKind::Start, time);
+        entry.job = Some(self.job.clone());
+        entry.interval_ms = Some(interval.as_millis() as u64);
+        self.append(&entry);
+    }
+
+    pub fn resume(&self, time: DateTime<Local>, interval: Duration) {
+        let mut entry = Entry::new(&self.session, EntryKind::Resume, time);
+        entry.job = Some(self.job.clone());
+        entry.interval_ms = Some(interval.as_millis() as u64);
+        self.append(&entry);
+    }
+
+    pub fn capture(&self, capture: &Capture, trigger: Trigger) {
+        let mut entry = Entry::new(&self.session, EntryKind::Capture, capture.time);
+        entry.file = Some(self.relative(&capture.path));
+        entry.screen = Some(capture.screen_id);
+        entry.trigger = Some(trigger);
+        entry.pointer = capture.pointer;
+        self.append(&entry);
+    }
+
+    /// Attaches `note` to the capture saved at `path`. The capture is already
+    /// written, so the note gets its own entry that `read` merges back.
+    pub fn note(&self, time: DateTime<Local>, path: &Path, note: &str) {
+        let mut entry = Entry::new(&self.session, EntryKind::Note, time);
+        entry.file = Some(self.relative(path));
+        entry.note = Some(note.to_owned());
+        self.append(&entry);
+    }
+
+    pub fn failed(&self, time: DateTime<Local>, error: &str) {
+        let mut entry = Entry::new(&self.session, EntryKind::Failed, time);
+        entry.error = Some(error.to_owned());
+        self.append(&entry);
+    }
+
+    pub fn end(&self, time: DateTime<Local>) {
+        self.append(&Entry::new(&self.session, EntryKind::End, time));
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.folder)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn append(&self, entry: &Entry) {
+        let path = self.folder.join(MANIFEST_FILE);
+        let result = serde_json::to_string(entry)
+            .map_err(|error| error.to_string())
+            .and_then(|line| {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|error| error.to_string())?;
+                writeln!(file, "{line}").map_err(|error| error.to_string())
+            });
+
+        if let Err(error) = result {
+            println!("Failed to write {}: {error}", path.display());
+        }
+    }
+}
+
+/// Reads the manifest of `folder`. A missing manifest reads as empty and lines
+/// that can't be parsed, such as one cut short by a crash, are skipped. Notes
+/// are merged into the captures they belong to.
+pub fn read(folder: &Path) -> Vec<Entry> {
+    let Ok(file) = File::open(folder.join(MANIFEST_FILE)) else {
+        return Vec::new();
+    };
+
+    merge_notes(
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect(),
+    )
+}
+
+fn merge_notes(entries: Vec<Entry>) -> Vec<Entry> {
+    let (notes, mut entries): (Vec<Entry>, Vec<Entry>) = entries
+        .into_iter()
+        .partition(|entry| entry.event == EntryKind::Note);
+
+    for note in notes {
+        if let Some(capture) = entries.iter_mut().rev().find(|entry| {
+            entry.event == EntryKind::Capture
+                && entry.session == note.session
+                && entry.file == note.file
+        }) {
+            capture.note = note.note;
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_merge_notes() {
+        let time = Local.with_ymd_and_hms(2023, 2, 20, 12, 0, 0).unwrap();
+        let mut capture = Entry::new("s", EntryKind::Capture, time);
+        capture.file = Some("a.png".to_owned());
+        capture.trigger = Some(Trigger::Manual);
+        let mut note = Entry::new("s", EntryKind::Note, time);
+        note.file = Some("a.png".to_owned());
+        note.note = Some("Error dialog".to_owned());
+        let mut stray = note.clone();
+        stray.file = Some("gone.png".to_owned());
+
+        let entries = merge_notes(vec![capture, note, stray]);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].note.as_deref(), Some("Error dialog"));
+        assert_eq!(entries[0].trigger, Some(Trigger::Manual));
+    }
+}
@@ -0,0 +1,643 @@
+//! Capture details embedded in the saved files, so a screenshot still says
+//! when, where and what it captured after it is copied out of its session
+//! folder. PNG files get text chunks, JPEG and WebP files get EXIF and XMP.
+
+use std::{io::Cursor, path::Path};
+
+use chrono::{DateTime, Local};
+use exif::{Context, Field, In, Tag, Value};
+
+/// Container of a saved screenshot, picked from the file extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl Container {
+    /// Anything that is not `.jpg`, `.jpeg` or `.webp` is saved as PNG.
+    pub fn from_path(path: &Path) -> Container {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("jpg" | "jpeg") => Container::Jpeg,
+            Some("webp") => Container::WebP,
+            _ => Container::Png,
+        }
+    }
+
+    fn detect(data: &[u8]) -> Option<Container> {
+        if data.starts_with(PNG_SIGNATURE) {
+            Some(Container::Png)
+        } else if data.starts_with(&[0xff, 0xd8]) {
+            Some(Container::Jpeg)
+        } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(Container::WebP)
+        } else {
+            None
+        }
+    }
+}
+
+/// What is known about a capture when it is saved.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    pub time: DateTime<Local>,
+    pub screen: u32,
+    /// The captured area on the desktop, before resizing, as `WxH+X+Y`.
+    pub geometry: String,
+    /// Title of the focused window, on X11.
+    pub window: Option<String>,
+    pub session: String,
+    pub job: String,
+}
+
+/// A field with its PNG keyword and XMP property.
+struct Key {
+    png: &'static str,
+    xmp: &'static str,
+}
+
+const CREATION_TIME: Key = Key {
+    png: "Creation Time",
+    xmp: "xmp:CreateDate",
+};
+const SOFTWARE: Key = Key {
+    png: "Software",
+    xmp: "xmp:CreatorTool",
+};
+const HOST: Key = Key {
+    png: "Host",
+    xmp: "ts:Host",
+};
+const SCREEN: Key = Key {
+    png: "Screen",
+    xmp: "ts:Screen",
+};
+const GEOMETRY: Key = Key {
+    png: "Geometry",
+    xmp: "ts:Geometry",
+};
+const WINDOW: Key = Key {
+    png: "Window",
+    xmp: "ts:Window",
+};
+const SESSION: Key = Key {
+    png: "Session",
+    xmp: "ts:Session",
+};
+const JOB: Key = Key {
+    png: "Job",
+    xmp: "ts:Job",
+};
+
+const KEYS: [Key; 8] = [
+    CREATION_TIME,
+    SOFTWARE,
+    HOST,
+    SCREEN,
+    GEOMETRY,
+    WINDOW,
+    SESSION,
+    JOB,
+];
+
+/// Namespace of the XMP properties that have no standard equivalent.
+const XMP_NAMESPACE: &str = "urn:take-screenshots:xmp:1.0#";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_EXIF: &[u8] = b"Exif\0\0";
+const JPEG_XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+impl Metadata {
+    fn software() -> String {
+        format!("take-screenshots {}", env!("CARGO_PKG_VERSION"))
+    }
+
+    fn fields(&self) -> Vec<(&'static Key, String)> {
+        let mut fields = vec![
+            (&CREATION_TIME, self.time.to_rfc3339()),
+            (&SOFTWARE, Metadata::software()),
+            (
+                &HOST,
+                gethostname::gethostname().to_string_lossy().into_owned(),
+            ),
+            (&SCREEN, self.screen.to_string()),
+            (&GEOMETRY, self.geometry.clone()),
+        ];
+        if let Some(window) = &self.window {
+            fields.push((&WINDOW, window.clone()));
+        }
+        fields.push((&SESSION, self.session.clone()));
+        fields.push((&JOB, self.job.clone()));
+        fields
+    }
+
+    /// Adds the metadata to an encoded image.
+    pub fn embed(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        match Container::detect(data) {
+            Some(Container::Png) => png_embed(data, &self.fields()),
+            Some(Container::Jpeg) => {
+                let exif = self.exif()?;
+                jpeg_embed(data, &exif, &xmp_packet(&self.fields()))
+            }
+            Some(Container::WebP) => {
+                let exif = self.exif()?;
+                webp_embed(data, &exif, &xmp_packet(&self.fields()))
+            }
+            None => Err("Unknown image format".to_owned()),
+        }
+    }
+
+    /// EXIF as a TIFF structure, for the fields common viewers show.
+    fn exif(&self) -> Result<Vec<u8>, String> {
+        let ascii = |tag: Tag, text: String| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.into_bytes()]),
+        };
+
+        let mut fields = vec![
+            ascii(
+                Tag::DateTimeOriginal,
+                self.time.format("%Y:%m:%d %H:%M:%S").to_string(),
+            ),
+            ascii(Tag::OffsetTimeOriginal, self.time.format("%:z").to_string()),
+            ascii(Tag::Software, Metadata::software()),
+            ascii(
+                // HostComputer, which kamadak-exif has no constant for.
+                Tag(Context::Tiff, 0x13c),
+                gethostname::gethostname().to_string_lossy().into_owned(),
+            ),
+        ];
+        if let Some(window) = &self.window {
+            fields.push(ascii(Tag::ImageDescription, window.clone()));
+        }
+
+        let mut writer = exif::experimental::Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut buffer = Cursor::new(Vec::new());
+        writer
+            .write(&mut buffer, true)
+            .map_err(|error| format!("Failed to write EXIF: {error}"))?;
+        Ok(buffer.into_inner())
+    }
+}
+
+/// Reads the metadata of an image file as keys and values. EXIF fields,
+/// which may come from other programs, are listed after ours with an
+/// `EXIF` prefix.
+pub fn read(data: &[u8]) -> Result<Vec<(String, String)>, String> {
+    let mut fields = Vec::new();
+    let (exif, xmp) = match Container::detect(data) {
+        Some(Container::Png) => {
+            fields = png_read(data)?;
+            (None, None)
+        }
+        Some(Container::Jpeg) => jpeg_read(data)?,
+        Some(Container::WebP) => webp_read(data)?,
+        None => return Err("Not a PNG, JPEG or WebP image".to_owned()),
+    };
+
+    if let Some(xmp) = xmp {
+        let xmp = String::from_utf8_lossy(&xmp);
+        for key in &KEYS {
+            if let Some(value) = xmp_property(&xmp, key.xmp) {
+                fields.push((key.png.to_owned(), value));
+            }
+        }
+    }
+
+    if let Some(exif) = exif {
+        let exif = exif::Reader::new()
+            .read_raw(exif)
+            .map_err(|error| format!("Failed to read EXIF: {error}"))?;
+        for field in exif.fields() {
+            fields.push((
+                format!("EXIF {}", field.tag),
+                field.display_value().to_string(),
+            ));
+        }
+    }
+    Ok(fields)
+}
+
+fn truncated() -> String {
+    "The image is truncated".to_owned()
+}
+
+/// The type and the data of a PNG or WebP chunk.
+type Chunk<'a> = (&'a [u8], &'a [u8]);
+
+/// Splits PNG data after the signature into chunks.
+fn png_chunks(data: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+    let mut chunks = Vec::new();
+    let mut rest = data.get(PNG_SIGNATURE.len()..).ok_or_else(truncated)?;
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes(rest.get(..4).ok_or_else(truncated)?.try_into().unwrap());
+        let end = 8 + length as usize;
+        let chunk = rest.get(4..end).ok_or_else(truncated)?;
+        chunks.push((&chunk[..4], &chunk[4..]));
+        rest = rest.get(end + 4..).ok_or_else(truncated)?;
+    }
+    Ok(chunks)
+}
+
+fn png_chunk(output: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    let crc = crc32fast::hash(&output[start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Adds a tEXt chunk per field, or an iTXt chunk when the value is not
+/// ASCII, just before the end of the image.
+fn png_embed(data: &[u8], fields: &[(&Key, String)]) -> Result<Vec<u8>, String> {
+    let mut output = PNG_SIGNATURE.to_vec();
+    for (kind, chunk) in png_chunks(data)? {
+        if kind == b"IEND" {
+            for (key, value) in fields {
+                let mut text = key.png.as_bytes().to_vec();
+                if value.is_ascii() {
+                    text.push(0);
+                    text.extend_from_slice(value.as_bytes());
+                    png_chunk(&mut output, b"tEXt", &text);
+                } else {
+                    // Uncompressed, no language tag or translated keyword.
+                    text.extend_from_slice(&[0, 0, 0, 0, 0]);
+                    text.extend_from_slice(value.as_bytes());
+                    png_chunk(&mut output, b"iTXt", &text);
+                }
+            }
+        }
+        png_chunk(&mut output, kind, chunk);
+    }
+    Ok(output)
+}
+
+fn png_read(data: &[u8]) -> Result<Vec<(String, String)>, String> {
+    let mut fields = Vec::new();
+    for (kind, chunk) in png_chunks(data)? {
+        let Some((keyword, rest)) = split_nul(chunk) else {
+            continue;
+        };
+        let keyword = String::from_utf8_lossy(keyword).into_owned();
+        match kind {
+            // Latin-1.
+            b"tEXt" => fields.push((keyword, rest.iter().map(|&byte| byte as char).collect())),
+            // Compressed text is left out.
+            b"iTXt" if rest.first() == Some(&0) => {
+                let Some((_, rest)) = rest.get(2..).and_then(split_nul) else {
+                    continue;
+                };
+                let Some((_, text)) = split_nul(rest) else {
+                    continue;
+                };
+                fields.push((keyword, String::from_utf8_lossy(text).into_owned()));
+            }
+            _ => {}
+        }
+    }
+    Ok(fields)
+}
+
+fn split_nul(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let nul = data.iter().position(|&byte| byte == 0)?;
+    Some((&data[..nul], &data[nul + 1..]))
+}
+
+fn jpeg_segment(
+    output: &mut Vec<u8>,
+    marker: u8,
+    header: &[u8],
+    data: &[u8],
+) -> Result<(), String> {
+    let length = 2 + header.len() + data.len();
+    if length > u16::MAX as usize {
+        return Err("The metadata is too large for a JPEG segment".to_owned());
+    }
+    output.extend_from_slice(&[0xff, marker]);
+    output.extend_from_slice(&(length as u16).to_be_bytes());
+    output.extend_from_slice(header);
+    output.extend_from_slice(data);
+    Ok(())
+}
+
+/// The marker and the data of a JPEG segment.
+type Segment<'a> = (u8, &'a [u8]);
+
+/// Splits JPEG data after the start of image into segments, up to the start
+/// of scan. Also returns where the segments end.
+fn jpeg_segments(data: &[u8]) -> Result<(Vec<Segment<'_>>, usize), String> {
+    let mut segments = Vec::new();
+    let mut position = 2;
+    loop {
+        let header = data.get(position..position + 4).ok_or_else(truncated)?;
+        if header[0] != 0xff {
+            return Err("The JPEG image is damaged".to_owned());
+        }
+        if header[1] == 0xda {
+            return Ok((segments, position));
+        }
+        let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let segment = data
+            .get(position + 4..position + 2 + length)
+            .ok_or_else(truncated)?;
+        segments.push((header[1], segment));
+        position += 2 + length;
+    }
+}
+
+/// Adds an APP1 segment with the EXIF and one with the XMP, after the JFIF
+/// header if there is one.
+fn jpeg_embed(data: &[u8], exif: &[u8], xmp: &str) -> Result<Vec<u8>, String> {
+    let (segments, end) = jpeg_segments(data)?;
+    let app0 = segments
+        .iter()
+        .take_while(|(marker, _)| *marker == 0xe0)
+        .count();
+
+    let mut output = vec![0xff, 0xd8];
+    for (marker, segment) in &segments[..app0] {
+        jpeg_segment(&mut output, *marker, &[], segment)?;
+    }
+    jpeg_segment(&mut output, 0xe1, JPEG_EXIF, exif)?;
+    jpeg_segment(&mut output, 0xe1, JPEG_XMP, xmp.as_bytes())?;
+    for (marker, segment) in &segments[app0..] {
+        jpeg_segment(&mut output, *marker, &[], segment)?;
+    }
+    output.extend_from_slice(&data[end..]);
+    Ok(output)
+}
+
+/// The EXIF and the XMP found in a file.
+type Found = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+fn jpeg_read(data: &[u8]) -> Result<Found, String> {
+    let (mut exif, mut xmp) = (None, None);
+    for (marker, segment) in jpeg_segments(data)?.0 {
+        if marker != 0xe1 {
+            continue;
+        }
+        if let Some(data) = segment.strip_prefix(JPEG_EXIF) {
+            exif = Some(data.to_vec());
+        } else if let Some(data) = segment.strip_prefix(JPEG_XMP) {
+            xmp = Some(data.to_vec());
+        }
+    }
+    Ok((exif, xmp))
+}
+
+/// Splits a WebP file into its chunks.
+fn webp_chunks(data: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+    let mut chunks = Vec::new();
+    let mut rest = data.get(12..).ok_or_else(truncated)?;
+    while !rest.is_empty() {
+        let header = rest.get(..8).ok_or_else(truncated)?;
+        let length = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        let chunk = rest.get(8..8 + length).ok_or_else(truncated)?;
+        chunks.push((&header[..4], chunk));
+        // Chunks are padded to an even size.
+        rest = rest.get(8 + length + length % 2..).unwrap_or_default();
+    }
+    Ok(chunks)
+}
+
+fn webp_chunk(output: &mut Vec<u8>, fourcc: &[u8], data: &[u8]) {
+    output.extend_from_slice(fourcc);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        output.push(0);
+    }
+}
+
+/// Turns a simple lossless WebP file, as the image crate writes it, into an
+/// extended one with EXIF and XMP chunks.
+fn webp_embed(data: &[u8], exif: &[u8], xmp: &str) -> Result<Vec<u8>, String> {
+    let chunks = webp_chunks(data)?;
+    let [(b"VP8L", bitstream)] = chunks.as_slice() else {
+        return Err("Only simple lossless WebP images can get metadata".to_owned());
+    };
+
+    // Signature byte, then 14 bits of width - 1, 14 bits of height - 1 and
+    // an alpha bit.
+    let header = bitstream.get(1..5).ok_or_else(truncated)?;
+    let bits = u32::from_le_bytes(header.try_into().unwrap());
+    let width = bits & 0x3fff;
+    let height = (bits >> 14) & 0x3fff;
+    let alpha = (bits >> 28) & 1 == 1;
+
+    let mut vp8x = vec![0x08 | 0x04 | if alpha { 0x10 } else { 0 }, 0, 0, 0];
+    vp8x.extend_from_slice(&width.to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&height.to_le_bytes()[..3]);
+
+    let mut output = b"RIFF\0\0\0\0WEBP".to_vec();
+    webp_chunk(&mut output, b"VP8X", &vp8x);
+    webp_chunk(&mut output, b"VP8L", bitstream);
+    webp_chunk(&mut output, b"EXIF", exif);
+    webp_chunk(&mut output, b"XMP ", xmp.as_bytes());
+    let size = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&size.to_le_bytes());
+    Ok(output)
+}
+
+fn webp_read(data: &[u8]) -> Result<Found, String> {
+    let (mut exif, mut xmp) = (None, None);
+    for (fourcc, chunk) in webp_chunks(data)? {
+        match fourcc {
+            b"EXIF" => exif = Some(chunk.to_vec()),
+            b"XMP " => xmp = Some(chunk.to_vec()),
+            _ => {}
+        }
+    }
+    Ok((exif, xmp))
+}
+
+fn xmp_packet(fields: &[(&Key, String)]) -> String {
+    let mut packet = String::from(concat!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+        " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+        "  <rdf:Description rdf:about=\"\"\n",
+        "    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n",
+    ));
+    packet.push_str(&format!("    xmlns:ts=\"{XMP_NAMESPACE}\">\n"));
+    for (key, value) in fields {
+        packet.push_str(&format!("   <{0}>{1}</{0}>\n", key.xmp, xml_escape(value)));
+    }
+    packet.push_str("  </rdf:Description>\n </rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>");
+    packet
+}
+
+/// Finds the value of a simple property written by `xmp_packet`.
+fn xmp_property(xmp: &str, property: &str) -> Option<String> {
+    let start = xmp.find(&format!("<{property}>"))? + property.len() + 2;
+    let end = start + xmp[start..].find(&format!("</{property}>"))?;
+    Some(xml_unescape(&xmp[start..end]))
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Title of the focused window, from the X server.
+#[cfg(target_os = "linux")]
+pub fn active_window() -> Result<Option<String>, String> {
+    use x11rb::{
+        connection::Connection,
+        protocol::xproto::{AtomEnum, ConnectionExt as _},
+    };
+
+    let (connection, screen) = x11rb::connect(None).map_err(|error| error.to_string())?;
+    let root = connection.setup().roots[screen].root;
+    let atom = |name: &[u8]| -> Result<u32, String> {
+        Ok(connection
+            .intern_atom(false, name)
+            .map_err(|error| error.to_string())?
+            .reply()
+            .map_err(|error| error.to_string())?
+            .atom)
+    };
+
+    let active = connection
+        .get_property(
+            false,
+            root,
+            atom(b"_NET_ACTIVE_WINDOW")?,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )
+        .map_err(|error| error.to_string())?
+        .reply()
+        .map_err(|error| error.to_string())?;
+    let Some(window) = active.value32().and_then(|mut value| value.next()) else {
+        return Ok(None);
+    };
+    if window == 0 {
+        return Ok(None);
+    }
+
+    let utf8_string = atom(b"UTF8_STRING")?;
+    for (property, kind) in [
+        (atom(b"_NET_WM_NAME")?, utf8_string),
+        (AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()),
+    ] {
+        let name = connection
+            .get_property(false, window, property, kind, 0, 1024)
+            .map_err(|error| error.to_string())?
+            .reply()
+            .map_err(|error| error.to_string())?;
+        if !name.value.is_empty() {
+            return Ok(Some(String::from_utf8_lossy(&name.value).into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn active_window() -> Result<Option<String>, String> {
+    Err("Reading the focused window is only supported on X11".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            time: DateTime::parse_from_rfc3339("2023-03-01T09:30:00+01:00")
+                .unwrap()
+                .into(),
+            screen: 2,
+            geometry: "800x600+1920+0".to_owned(),
+            window: Some("Notes — <draft> & ideas".to_owned()),
+            session: "20230301-093000-Desktop".to_owned(),
+            job: "Desktop".to_owned(),
+        }
+    }
+
+    fn encode(format: ImageOutputFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(5, 3));
+        let image = match format {
+            ImageOutputFormat::Jpeg(_) => DynamicImage::ImageRgb8(image.to_rgb8()),
+            _ => image,
+        };
+        let mut buffer = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut buffer), format)
+            .unwrap();
+        buffer
+    }
+
+    fn value<'a>(fields: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        fields
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_container_from_path() {
+        assert_eq!(Container::from_path(Path::new("a/b.JPG")), Container::Jpeg);
+        assert_eq!(Container::from_path(Path::new("b.webp")), Container::WebP);
+        assert_eq!(Container::from_path(Path::new("b")), Container::Png);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        // Times are saved in the local time zone.
+        let time = metadata().time;
+        for format in [
+            ImageOutputFormat::Png,
+            ImageOutputFormat::Jpeg(90),
+            ImageOutputFormat::WebP,
+        ] {
+            let data = metadata().embed(&encode(format.clone())).unwrap();
+            image::load_from_memory(&data).unwrap();
+
+            let fields = read(&data).unwrap();
+            assert_eq!(
+                value(&fields, "Creation Time"),
+                Some(time.to_rfc3339().as_str()),
+                "{format:?}"
+            );
+            assert_eq!(value(&fields, "Screen"), Some("2"));
+            assert_eq!(value(&fields, "Geometry"), Some("800x600+1920+0"));
+            assert_eq!(value(&fields, "Window"), Some("Notes — <draft> & ideas"));
+            assert_eq!(value(&fields, "Session"), Some("20230301-093000-Desktop"));
+            assert_eq!(value(&fields, "Job"), Some("Desktop"));
+            assert!(value(&fields, "Software")
+                .unwrap()
+                .starts_with("take-screenshots "));
+
+            if format != ImageOutputFormat::Png {
+                assert_eq!(
+                    value(&fields, "EXIF OffsetTimeOriginal"),
+                    Some(format!("\"{}\"", time.format("%:z")).as_str())
+                );
+            }
+        }
+    }
+}
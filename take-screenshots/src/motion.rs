@@ -0,0 +1,246 @@
+//! Bursts of screenshots on motion. The screen is sampled at a high rate and
+//! each sample is compared with the previous one on a small grayscale copy.
+//! When the share of its pixels that changed is over the threshold, the
+//! samples kept from just before and the next few after are saved.
+
+use std::{collections::VecDeque, time::Duration};
+
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+
+use crate::i18n::{format_duration, parse_duration, tr, trf};
+
+/// Side of the grayscale copies samples are compared on.
+const SIGNATURE_SIZE: u32 = 64;
+
+/// How much the brightness of a pixel of the copy must change, out of 255,
+/// for it to count as changed. Less is noise from scaling and compression.
+const PIXEL_CHANGE: u8 = 24;
+
+/// Limit of the frames saved before or after the motion. The ones before are
+/// kept in memory at full size until then.
+const MAX_FRAMES: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Motion {
+    /// Time between samples, which is also the time between the frames of a
+    /// burst.
+    pub rate: Duration,
+    /// Share of the pixels of the picture that must change, 0 to 1.
+    pub threshold: f64,
+    /// Frames saved from before the motion.
+    pub before: usize,
+    /// Frames saved after the one with the motion.
+    pub after: usize,
+}
+
+impl Motion {
+    /// Checks that `backend`, which can sample at most every `min_rate` or
+    /// not at all, keeps up with the sampling rate. Faster samples would
+    /// only repeat frames.
+    pub fn check_rate(&self, backend: &str, min_rate: Option<Duration>) -> Result<(), String> {
+        match min_rate {
+            Some(min_rate) if self.rate >= min_rate => Ok(()),
+            Some(min_rate) => Err(trf(
+                "{backend} can sample the screen for motion at most every {rate}.",
+                &[("backend", &backend), ("rate", &format_duration(min_rate))],
+            )),
+            None => Err(trf(
+                "{backend} can't sample the screen for motion.",
+                &[("backend", &backend)],
+            )),
+        }
+    }
+
+    /// Parses the sampling rate like "200ms", the threshold like "2%" and the
+    /// number of frames to save before and after the motion.
+    pub fn parse(rate: &str, threshold: &str, before: &str, after: &str) -> Result<Motion, String> {
+        let rate = parse_duration(rate.trim()).map_err(|error| {
+            trf(
+                "Failed to parse the motion sampling rate: {error}.\nTry something like \"200ms\".",
+                &[("error", &error)],
+            )
+        })?;
+        if rate < Duration::from_millis(10) {
+            return Err(tr("Sample the screen for motion at most every 10ms.").to_owned());
+        }
+
+        let threshold = threshold
+            .trim()
+            .trim_end_matches('%')
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|percent| *percent > 0.0 && *percent <= 100.0)
+            .ok_or_else(|| {
+                trf(
+                    "The motion threshold \"{threshold}\" should be a percentage like \"2%\".",
+                    &[("threshold", &threshold.trim())],
+                )
+            })?;
+
+        let frames = |value: &str, which: &'static str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|frames| *frames <= MAX_FRAMES)
+                .ok_or_else(|| trf(which, &[("max", &MAX_FRAMES)]))
+        };
+
+        Ok(Motion {
+            rate,
+            threshold: threshold / 100.0,
+            before: frames(
+                before,
+                "The frames before the motion should be a number from 0 to {max}.",
+            )?,
+            after: frames(
+                after,
+                "The frames after the motion should be a number from 0 to {max}.",
+            )?,
+        })
+    }
+}
+
+/// The small grayscale copy of `image` samples are compared on.
+pub fn signature(image: &DynamicImage) -> GrayImage {
+    image
+        .resize_exact(SIGNATURE_SIZE, SIGNATURE_SIZE, FilterType::Triangle)
+        .to_luma8()
+}
+
+/// Mean absolute difference of two equally sized grayscale images, 0 to 1.
+pub fn difference(a: &GrayImage, b: &GrayImage) -> f64 {
+    let total: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| a.abs_diff(*b) as u64)
+        .sum();
+    let pixels = a.as_raw().len().max(1) as f64;
+    total as f64 / pixels / 255.0
+}
+
+/// Share of the pixels of two equally sized grayscale images whose brightness
+/// differs by more than `PIXEL_CHANGE`, 0 to 1.
+pub fn changed(a: &GrayImage, b: &GrayImage) -> f64 {
+    let changed = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .filter(|(a, b)| a.abs_diff(**b) > PIXEL_CHANGE)
+        .count();
+    changed as f64 / a.as_raw().len().max(1) as f64
+}
+
+/// Decides which samples are saved. Motion during a burst doesn't make it
+/// longer, but motion right after it starts the next one.
+pub struct Burst<T> {
+    motion: Motion,
+    /// The latest samples, kept in case motion follows.
+    kept: VecDeque<T>,
+    previous: Option<GrayImage>,
+    /// Frames of the current burst still to save.
+    remaining: usize,
+}
+
+impl<T> Burst<T> {
+    pub fn new(motion: Motion) -> Self {
+        Burst {
+            motion,
+            kept: VecDeque::with_capacity(motion.before + 1),
+            previous: None,
+            remaining: 0,
+        }
+    }
+
+    /// Adds a sample and returns the frames to save now, oldest first.
+    pub fn sample(&mut self, frame: T, signature: GrayImage) -> Vec<T> {
+        let change = self
+            .previous
+            .as_ref()
+            .map(|previous| changed(previous, &signature));
+        self.previous = Some(signature);
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return vec![frame];
+        }
+
+        if change.is_some_and(|change| change >= self.motion.threshold) {
+            self.remaining = self.motion.after;
+            let mut frames: Vec<T> = self.kept.drain(..).collect();
+            frames.push(frame);
+            return frames;
+        }
+
+        self.kept.push_back(frame);
+        if self.kept.len() > self.motion.before {
+            self.kept.pop_front();
+        }
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let motion = Motion::parse("200ms", " 2.5 % ", "3", "0").unwrap();
+        assert_eq!(motion.rate, Duration::from_millis(200));
+        assert!((motion.threshold - 0.025).abs() < 1e-9);
+        assert_eq!((motion.before, motion.after), (3, 0));
+
+        assert!(Motion::parse("1ms", "2%", "3", "3").is_err());
+        assert!(Motion::parse("200ms", "0%", "3", "3").is_err());
+        assert!(Motion::parse("200ms", "2%", "-1", "3").is_err());
+        assert!(Motion::parse("200ms", "2%", "3", "51").is_err());
+
+        let min_rate = Some(Duration::from_millis(100));
+        assert!(motion.check_rate("x11", min_rate).is_ok());
+        let fast = Motion::parse("50ms", "2%", "3", "3").unwrap();
+        assert!(fast.check_rate("x11", min_rate).is_err());
+        assert!(motion.check_rate("portal", None).is_err());
+    }
+
+    #[test]
+    fn test_changed() {
+        let black = GrayImage::new(10, 10);
+        // A tenth of the picture turns white.
+        let mut corner = black.clone();
+        for x in 0..10 {
+            corner.put_pixel(x, 0, image::Luma([255]));
+        }
+        // All of it gets a little brighter.
+        let brighter = GrayImage::from_pixel(10, 10, image::Luma([PIXEL_CHANGE]));
+
+        assert!((changed(&black, &corner) - 0.1).abs() < 1e-9);
+        assert_eq!(changed(&black, &brighter), 0.0);
+        // Both change the mean brightness alike.
+        assert!((difference(&black, &corner) - difference(&black, &brighter)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_burst() {
+        let still = GrayImage::new(4, 4);
+        let moved = GrayImage::from_pixel(4, 4, image::Luma([255]));
+        let mut burst = Burst::new(Motion {
+            rate: Duration::from_millis(100),
+            threshold: 0.02,
+            before: 2,
+            after: 2,
+        });
+
+        for frame in 0..4 {
+            assert!(burst.sample(frame, still.clone()).is_empty());
+        }
+        // Frames 2 and 3 were kept, 4 moved, 5 and 6 follow.
+        assert_eq!(burst.sample(4, moved.clone()), vec![2, 3, 4]);
+        assert_eq!(burst.sample(5, still.clone()), vec![5]);
+        assert_eq!(burst.sample(6, moved.clone()), vec![6]);
+        assert!(burst.sample(7, moved.clone()).is_empty());
+        assert_eq!(burst.sample(8, still.clone()), vec![7, 8]);
+    }
+}
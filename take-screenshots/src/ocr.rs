@@ -0,0 +1,318 @@
+//! Text recognition. Jobs with OCR on run a local `tesseract` on each saved
+//! screenshot and store the text in a SQLite full-text index in the output
+//! folder, searched from the window and with `take-screenshots search`.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection};
+use tokio::{io::AsyncWriteExt, process::Command, sync::mpsc};
+
+use crate::archive;
+use crate::capture::Capture;
+
+/// The index, next to the manifest.
+pub const INDEX_NAME: &str = "take-screenshots-ocr.sqlite";
+
+/// Longest a recognition may take before tesseract is killed.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Most matches a search returns.
+const MAX_MATCHES: usize = 500;
+
+/// A screenshot to recognize.
+pub struct Entry {
+    /// Relative to the output folder, like `NAME.png` or
+    /// `SESSION.tar/NAME.png`.
+    pub path: String,
+    pub time: DateTime<Local>,
+    pub job: String,
+    pub session: String,
+}
+
+impl Entry {
+    pub fn new(folder: &Path, capture: &Capture, job: &str, session: &str) -> Self {
+        Entry {
+            path: capture
+                .path
+                .strip_prefix(folder)
+                .unwrap_or(&capture.path)
+                .to_string_lossy()
+                .into_owned(),
+            time: capture.time,
+            job: job.to_owned(),
+            session: session.to_owned(),
+        }
+    }
+}
+
+/// A screenshot whose text matches a search.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub path: PathBuf,
+    pub time: DateTime<Local>,
+    pub job: String,
+    pub session: String,
+    /// The matching words in brackets, with some text around them.
+    pub snippet: String,
+}
+
+/// Checks that tesseract can be run.
+pub fn check() -> Result<(), String> {
+    std::process::Command::new("tesseract")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|_| ())
+        .map_err(|error| format!("Text recognition needs tesseract, which failed to run: {error}"))
+}
+
+/// Recognizes the text of an encoded image.
+pub async fn recognize(image: Vec<u8>) -> Result<String, String> {
+    let mut child = Command::new("tesseract")
+        .args(["stdin", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| format!("Failed to run tesseract: {error}"))?;
+
+    let mut stdin = child.stdin.take().ok_or("Failed to write to tesseract")?;
+    let write = async move {
+        // tesseract reads the whole image before it writes anything.
+        let _ = stdin.write_all(&image).await;
+    };
+    let output = tokio::time::timeout(TIMEOUT, async {
+        tokio::join!(write, child.wait_with_output()).1
+    })
+    .await
+    .map_err(|_| {
+        format!(
+            "tesseract took longer than {}",
+            humantime::format_duration(TIMEOUT)
+        )
+    })?
+    .map_err(|error| format!("Failed to run tesseract: {error}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "tesseract failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// The full-text index of an output folder.
+pub struct Index {
+    connection: Connection,
+}
+
+impl Index {
+    pub fn open(folder: &Path) -> Result<Index, String> {
+        let path = folder.join(INDEX_NAME);
+        let error = |error: rusqlite::Error| format!("Failed to open {}: {error}", path.display());
+        let connection = Connection::open(&path).map_err(error)?;
+        // Jobs saving into the same folder write to the same index.
+        connection
+            .busy_timeout(Duration::from_secs(10))
+            .map_err(error)?;
+        connection
+            .execute_batch(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS captures
+                 USING fts5(text, path UNINDEXED, time UNINDEXED, job UNINDEXED, session UNINDEXED);",
+            )
+            .map_err(error)?;
+        Ok(Index { connection })
+    }
+
+    pub fn add(&self, entry: &Entry, text: &str) -> Result<(), String> {
+        self.connection
+            .execute(
+                "INSERT INTO captures (text, path, time, job, session) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    text,
+                    entry.path,
+                    entry.time.to_rfc3339(),
+                    entry.job,
+                    entry.session
+                ],
+            )
+            .map(|_| ())
+            .map_err(|error| format!("Failed to index {}: {error}", entry.path))
+    }
+
+    /// The screenshots with every word of `query`, oldest first. Words match
+    /// the beginning of words, so "screensh" finds "screenshots".
+    pub fn search(&self, folder: &Path, query: &str) -> Result<Vec<Match>, String> {
+        let Some(query) = match_query(query) else {
+            return Ok(Vec::new());
+        };
+
+        let error = |error: rusqlite::Error| format!("Failed to search: {error}");
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT path, time, job, session, snippet(captures, 0, '[', ']', '…', 12)
+                 FROM captures WHERE captures MATCH ?1 ORDER BY time LIMIT ?2",
+            )
+            .map_err(error)?;
+        let rows = statement
+            .query_map(params![query, MAX_MATCHES as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })
+            .map_err(error)?;
+
+        let mut matches = Vec::new();
+        for row in rows {
+            let (path, time, job, session, snippet) = row.map_err(error)?;
+            let Ok(time) = DateTime::parse_from_rfc3339(&time) else {
+                continue;
+            };
+            matches.push(Match {
+                path: folder.join(path),
+                time: time.with_timezone(&Local),
+                job,
+                session,
+                snippet: snippet.split_whitespace().collect::<Vec<_>>().join(" "),
+            });
+        }
+        Ok(matches)
+    }
+}
+
+/// Searches the index of `folder`.
+pub fn search(folder: &Path, query: &str) -> Result<Vec<Match>, String> {
+    if !folder.join(INDEX_NAME).is_file() {
+        return Err(format!(
+            "{} has no text index, turn on OCR for a job saving there.",
+            folder.display()
+        ));
+    }
+    Index::open(folder)?.search(folder, query)
+}
+
+/// Turns what was typed into an FTS5 query for every word as a prefix.
+/// Each word is quoted, so punctuation isn't read as query syntax.
+fn match_query(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Recognizes the screenshots of a session in the background, one at a
+/// time, so a slow recognition doesn't hold up the schedule. What is queued
+/// when the session ends is still recognized.
+pub struct Indexer {
+    sender: mpsc::UnboundedSender<Entry>,
+}
+
+impl Indexer {
+    pub fn spawn(folder: PathBuf) -> Indexer {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Entry>();
+        tokio::spawn(async move {
+            while let Some(entry) = receiver.recv().await {
+                if let Err(error) = index(&folder, entry).await {
+                    println!("{error}");
+                }
+            }
+        });
+        Indexer { sender }
+    }
+
+    pub fn index(&self, entry: Entry) {
+        let _ = self.sender.send(entry);
+    }
+}
+
+async fn index(folder: &Path, entry: Entry) -> Result<(), String> {
+    let path = folder.join(&entry.path);
+    let image = tokio::task::spawn_blocking(move || archive::read_path(&path))
+        .await
+        .map_err(|error| error.to_string())??;
+    let text = recognize(image)
+        .await
+        .map_err(|error| format!("Failed to recognize {}: {error}", entry.path))?;
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    let folder = folder.to_owned();
+    tokio::task::spawn_blocking(move || Index::open(&folder)?.add(&entry, &text))
+        .await
+        .map_err(|error| error.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_match_query() {
+        assert_eq!(match_query("  "), None);
+        assert_eq!(
+            match_query("Build failed"),
+            Some("\"Build\"* \"failed\"*".to_owned())
+        );
+        assert_eq!(
+            match_query("a\"b OR"),
+            Some("\"a\"\"b\"* \"OR\"*".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        let folder =
+            std::env::temp_dir().join(format!("take-screenshots-ocr-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        assert!(search(&folder, "anything").is_err());
+
+        let time = Local.with_ymd_and_hms(2023, 3, 1, 9, 30, 0).unwrap();
+        let entry = |path: &str, time| Entry {
+            path: path.to_owned(),
+            time,
+            job: "Desktop".to_owned(),
+            session: "20230301-093000-Desktop".to_owned(),
+        };
+        let index = Index::open(&folder).unwrap();
+        index
+            .add(
+                &entry("b.png", time + chrono::Duration::minutes(5)),
+                "Build failed: 3 tests",
+            )
+            .unwrap();
+        index
+            .add(&entry("s.tar/a.png", time), "cargo build\nfinished")
+            .unwrap();
+        drop(index);
+
+        let matches = search(&folder, "build").unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].path, folder.join("s.tar/a.png"));
+        assert_eq!(matches[0].time, time);
+        assert_eq!(matches[0].snippet, "cargo [build] finished");
+
+        let matches = search(&folder, "fail \"tests").unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].path, folder.join("b.png"));
+        assert!(search(&folder, "missing").unwrap().is_empty());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
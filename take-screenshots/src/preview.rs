@@ -0,0 +1,234 @@
+//! Preview of the files a job would write if started now, with warnings for
+//! names that repeat during the session, files that already exist and
+//! folders that are missing. Whether the folder can be written is checked
+//! apart, when the job starts, since it writes a file there.
+
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+
+use crate::archive;
+use crate::i18n::{format_duration, trf};
+use crate::jobs::JobSettings;
+use crate::manifest::Manifest;
+use crate::session::StopMode;
+
+/// How many paths the preview lists.
+const SHOWN: usize = 5;
+
+/// How many captures of the schedule are checked. A day of captures every
+/// minute fits, so names that repeat daily are caught.
+const CHECKED: usize = 2000;
+
+pub struct Preview {
+    /// The next few files, in capture order.
+    pub paths: Vec<PathBuf>,
+    pub warnings: Vec<String>,
+}
+
+/// Previews the files `settings` would write in a session started at `now`.
+/// Fails like starting the job would on a bad format, interval or stop mode.
+pub fn preview(settings: &JobSettings, now: DateTime<Local>) -> Result<Preview, String> {
+    let (interval, stop) = settings.schedule(now)?;
+
+    // Captures happen right away and then every interval, as long as the
+    // stop mode allows. Times past what a date can hold are never reached.
+    let times: Vec<DateTime<Local>> = (0..CHECKED)
+        .map_while(|index| {
+            let elapsed = interval.checked_mul(index as u32)?;
+            let time = now.checked_add_signed(chrono::Duration::from_std(elapsed).ok()?)?;
+            let reached = match stop {
+                StopMode::After(stop) => elapsed >= stop,
+                StopMode::At(stop) => time >= stop,
+                StopMode::Count(count) => index as u64 >= count,
+                StopMode::Never => false,
+            };
+            (!reached).then_some(time)
+        })
+        .collect();
+    let names: Vec<String> = times
+        .iter()
+        .map(|time| time.format(&settings.format).to_string())
+        .collect();
+    // A new session archive has no files yet, and its members can be in
+    // folders that don't exist.
+    let folder = match settings.archive {
+        true => {
+            let manifest = Manifest::new(&settings.output_folder, settings.name.trim(), now);
+            format!(
+                "{}/{}{}",
+                settings.output_folder,
+                manifest.session(),
+                archive::EXTENSION
+            )
+        }
+        false => settings.output_folder.clone(),
+    };
+    let paths: Vec<PathBuf> = names
+        .iter()
+        .map(|name| PathBuf::from(format!("{folder}/{name}")))
+        .collect();
+
+    let mut warnings = Vec::new();
+
+    let mut seen = HashMap::new();
+    for (index, name) in names.iter().enumerate() {
+        if let Some(first) = seen.insert(name, index) {
+            let every = interval.saturating_mul((index - first) as u32);
+            warnings.push(trf(
+                "Screenshots {first} and {second} would both be saved as \"{name}\", the name repeats after {every}. Add finer fields like %S, or the date, to the name.",
+                &[
+                    ("first", &(first + 1)),
+                    ("second", &(index + 1)),
+                    ("name", name),
+                    ("every", &format_duration(every)),
+                ],
+            ));
+            break;
+        }
+    }
+
+    let output_folder = Path::new(&settings.output_folder);
+    if !output_folder.is_dir() {
+        warnings.push(trf(
+            "The folder \"{folder}\" doesn't exist.",
+            &[("folder", &output_folder.display())],
+        ));
+    } else if settings.archive {
+        // Nothing on disk to check.
+    } else if let Some(parent) = paths.first().and_then(|path| path.parent()) {
+        // The name can put screenshots in subfolders, which aren't created.
+        if !parent.is_dir() {
+            warnings.push(trf(
+                "The folder \"{folder}\" doesn't exist.",
+                &[("folder", &parent.display())],
+            ));
+        }
+    }
+
+    let existing: Vec<&PathBuf> = match settings.archive {
+        true => Vec::new(),
+        false => paths.iter().filter(|path| path.exists()).collect(),
+    };
+    if let Some(first) = existing.first() {
+        warnings.push(trf(
+            "{existing} of the next {count} screenshots would overwrite existing files, starting with \"{path}\".",
+            &[
+                ("existing", &existing.len()),
+                ("count", &paths.len()),
+                ("path", &first.display()),
+            ],
+        ));
+    }
+
+    Ok(Preview {
+        paths: paths.into_iter().take(SHOWN).collect(),
+        warnings,
+    })
+}
+
+/// Checks that files can be created in `folder` by creating one.
+pub fn check_writable(folder: &Path) -> Result<(), String> {
+    let probe = folder.join(format!(".take-screenshots-{}", std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .map_err(|error| {
+            trf(
+                "Can't write to \"{folder}\": {error}",
+                &[("folder", &folder.display()), ("error", &error)],
+            )
+        })?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn settings(folder: &Path, format: &str, interval: &str) -> JobSettings {
+        JobSettings {
+            format: format.to_owned(),
+            interval: interval.to_owned(),
+            stop_mode: 3,
+            ..JobSettings::new("Desktop", folder.to_str().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_preview() {
+        let folder =
+            std::env::temp_dir().join(format!("take-screenshots-preview-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let now = Local.with_ymd_and_hms(2023, 3, 1, 9, 30, 0).unwrap();
+
+        let shown = preview(&settings(&folder, "%H_%M_%S.png", "30s"), now).unwrap();
+        assert_eq!(shown.paths.len(), 5);
+        assert_eq!(shown.paths[1], folder.join("09_30_30.png"));
+        // Repeats after a day, which is past the checked captures.
+        assert!(shown.warnings.is_empty(), "{:?}", shown.warnings);
+
+        let warnings = preview_warnings(&settings(&folder, "%H_%M.png", "30s"), now);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Screenshots 1 and 2 would both"));
+
+        // Only 3 screenshots are taken, they don't repeat.
+        let mut short = settings(&folder, "%M.png", "20 minutes");
+        short.stop_mode = 2;
+        short.stop_value = "3".to_owned();
+        assert!(preview_warnings(&short, now).is_empty());
+
+        std::fs::write(folder.join("01_09_31.png"), b"").unwrap();
+        let warnings = preview_warnings(&settings(&folder, "%d_%H_%M.png", "1m"), now);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("1 of the next 2000 screenshots would overwrite"));
+
+        let mut archived = settings(&folder, "%d_%H_%M.png", "1m");
+        archived.archive = true;
+        let shown = preview(&archived, now).unwrap();
+        assert!(shown.warnings.is_empty(), "{:?}", shown.warnings);
+        assert_eq!(
+            shown.paths[0],
+            folder.join("20230301-093000-Desktop.tar/01_09_30.png")
+        );
+
+        let warnings = preview_warnings(
+            &settings(&folder.join("missing"), "%Y%m%d_%H_%M.png", "1m"),
+            now,
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].ends_with("doesn't exist."));
+
+        // The longest duration still has a date for every checked capture.
+        let shown = preview(&settings(&folder, "%Y%m%d_%H_%M.png", "100y"), now).unwrap();
+        assert_eq!(shown.paths.len(), 5);
+        assert!(preview(&settings(&folder, "%Y%m%d_%H_%M.png", "200y"), now).is_err());
+
+        assert!(preview(&settings(&folder, "%Q", "1m"), now).is_err());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let folder =
+            std::env::temp_dir().join(format!("take-screenshots-writable-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        assert!(check_writable(&folder).is_ok());
+        // The probe is gone.
+        assert_eq!(std::fs::read_dir(&folder).unwrap().count(), 0);
+        assert!(check_writable(&folder.join("missing")).is_err());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    fn preview_warnings(settings: &JobSettings, now: DateTime<Local>) -> Vec<String> {
+        preview(settings, now).unwrap().warnings
+    }
+}
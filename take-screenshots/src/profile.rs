@@ -0,0 +1,108 @@
+//! Profiles: the jobs of the window saved under a name, which the daemon and
+//! the autostart entry start.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+
+use crate::i18n::{tr, trf};
+use crate::jobs::JobSettings;
+
+pub const DEFAULT: &str = "default";
+
+/// The profile folder, in the configuration folder of the user.
+pub fn folder() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "take-screenshots").map(|dirs| dirs.config_dir().join("profiles"))
+}
+
+/// Checks a profile name. It names a file and goes on command lines, so only
+/// letters, digits, spaces, "-" and "_" are allowed.
+pub fn check_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(tr("Give the profile a name.").to_owned());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+    {
+        return Err(trf(
+            "The profile name \"{name}\" may only have letters, digits, spaces, \"-\" and \"_\".",
+            &[("name", &name)],
+        ));
+    }
+    Ok(name)
+}
+
+pub fn save(folder: &Path, name: &str, jobs: &[JobSettings]) -> Result<PathBuf, String> {
+    let name = check_name(name)?;
+    fs::create_dir_all(folder).map_err(|error| {
+        trf(
+            "Failed to create {path}: {error}",
+            &[("path", &folder.display()), ("error", &error)],
+        )
+    })?;
+    let path = folder.join(format!("{name}.json"));
+    let json = serde_json::to_string_pretty(jobs).map_err(|error| error.to_string())?;
+    fs::write(&path, json).map_err(|error| {
+        trf(
+            "Failed to write {path}: {error}",
+            &[("path", &path.display()), ("error", &error)],
+        )
+    })?;
+    Ok(path)
+}
+
+pub fn load(folder: &Path, name: &str) -> Result<Vec<JobSettings>, String> {
+    let name = check_name(name)?;
+    let path = folder.join(format!("{name}.json"));
+    let failed = |error: &dyn std::fmt::Display| {
+        trf(
+            "Failed to read {path}: {error}",
+            &[("path", &path.display()), ("error", error)],
+        )
+    };
+    let json = fs::read_to_string(&path).map_err(|error| match error.kind() {
+        std::io::ErrorKind::NotFound => {
+            trf("There is no profile called \"{name}\".", &[("name", &name)])
+        }
+        _ => failed(&error),
+    })?;
+    let jobs: Vec<JobSettings> = serde_json::from_str(&json).map_err(|error| failed(&error))?;
+    if jobs.is_empty() {
+        return Err(trf(
+            "The profile \"{name}\" has no jobs.",
+            &[("name", &name)],
+        ));
+    }
+    Ok(jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load() {
+        let folder =
+            std::env::temp_dir().join(format!("take-screenshots-profile-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        let jobs = vec![
+            JobSettings::new("Desktop", "/tmp"),
+            JobSettings::new("Job 2", "/tmp/other"),
+        ];
+
+        save(&folder, " Work day ", &jobs).unwrap();
+        let loaded = load(&folder, "Work day").unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[1].output_folder, "/tmp/other");
+
+        assert!(load(&folder, "missing").is_err());
+        assert!(save(&folder, "../escape", &jobs).is_err());
+        assert!(save(&folder, "", &jobs).is_err());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
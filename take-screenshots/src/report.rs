@@ -0,0 +1,583 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local};
+use image::{DynamicImage, GrayImage};
+
+use crate::archive;
+use crate::manifest::{self, EntryKind, Trigger};
+use crate::motion::{difference, signature};
+
+/// The report is written to this subfolder of the session folder, next to the
+/// screenshots it links to.
+pub const REPORT_FOLDER: &str = "report";
+
+const THUMBNAIL_SIZE: u32 = 240;
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "webp"];
+
+struct Frame {
+    session: Option<String>,
+    time: DateTime<Local>,
+    file: String,
+    size: u64,
+    thumbnail: Option<String>,
+    /// Share of the picture that changed since the previous frame, 0 to 1.
+    change: Option<f64>,
+    /// What caused the capture, when it wasn't the timer.
+    trigger: Option<Trigger>,
+    note: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Gap {
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    missed: u64,
+    reason: String,
+    failed: bool,
+}
+
+/// Builds `REPORT_FOLDER/index.html` for a session folder and returns its
+/// path. The page only links to files inside the folder, so it can be viewed
+/// offline or copied along with the screenshots.
+pub fn generate(folder: &Path) -> Result<PathBuf, String> {
+    let entries = manifest::read(folder);
+    let mut files = Files {
+        folder,
+        archives: HashMap::new(),
+    };
+
+    let mut frames: Vec<Frame> = entries
+        .iter()
+        .filter(|entry| entry.event == EntryKind::Capture)
+        .filter_map(|entry| {
+            let file = entry.file.clone()?;
+            let size = files.size(&file)?;
+            Some(Frame {
+                session: Some(entry.session.clone()),
+                time: entry.time,
+                file,
+                size,
+                thumbnail: None,
+                change: None,
+                trigger: entry.trigger.filter(|trigger| *trigger != Trigger::Timer),
+                note: entry.note.clone(),
+            })
+        })
+        .collect();
+
+    if frames.is_empty() {
+        frames = frames_from_folder(folder)?;
+    }
+
+    let failures: Vec<Gap> = entries
+        .iter()
+        .filter(|entry| entry.event == EntryKind::Failed)
+        .map(|entry| Gap {
+            from: entry.time,
+            to: entry.time,
+            missed: 1,
+            reason: entry.error.clone().unwrap_or_default(),
+            failed: true,
+        })
+        .collect();
+
+    if frames.is_empty() && failures.is_empty() {
+        return Err(format!("No screenshots found in {}", folder.display()));
+    }
+
+    let report_folder = folder.join(REPORT_FOLDER);
+    fs::create_dir_all(report_folder.join("thumbs"))
+        .map_err(|error| format!("Failed to create {}: {error}", report_folder.display()))?;
+
+    // Jobs sharing a folder interleave their frames, so compare each frame
+    // with the previous one of its own session.
+    let mut previous: HashMap<Option<String>, GrayImage> = HashMap::new();
+    for (index, frame) in frames.iter_mut().enumerate() {
+        let image = match files.open(&frame.file) {
+            Ok(image) => image,
+            Err(error) => {
+                println!("Failed to read {}: {error}", frame.file);
+                previous.remove(&frame.session);
+                continue;
+            }
+        };
+
+        let thumbnail = format!("thumbs/{index:05}.jpg");
+        match image
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .to_rgb8()
+            .save(report_folder.join(&thumbnail))
+        {
+            Ok(()) => frame.thumbnail = Some(thumbnail),
+            Err(error) => println!("Failed to write thumbnail for {}: {error}", frame.file),
+        }
+
+        let small = signature(&image);
+        frame.change = previous
+            .get(&frame.session)
+            .map(|previous| difference(previous, &small));
+        previous.insert(frame.session.clone(), small);
+    }
+
+    let mut gaps = failures;
+    gaps.extend(session_gaps(&entries, &frames));
+    gaps.sort_by_key(|gap| gap.from);
+
+    let path = report_folder.join("index.html");
+    fs::write(&path, render(folder, &frames, &gaps))
+        .map_err(|error| format!("Failed to write {}: {error}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Fallback for folders without a manifest: every image in the folder and in
+/// its session archives, ordered by modification time.
+fn frames_from_folder(folder: &Path) -> Result<Vec<Frame>, String> {
+    let read_dir = fs::read_dir(folder)
+        .map_err(|error| format!("Failed to read {}: {error}", folder.display()))?;
+
+    let mut frames = Vec::new();
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let is_image = is_image_name(&path.to_string_lossy());
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(archive::EXTENSION) && metadata.is_file() {
+            let members = match archive::members(&path) {
+                Ok((members, _)) => members,
+                Err(error) => {
+                    println!("{error}");
+                    continue;
+                }
+            };
+            for member in members.iter().filter(|member| is_image_name(&member.name)) {
+                let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(member.mtime);
+                frames.push(Frame {
+                    session: None,
+                    time: modified.into(),
+                    file: format!("{name}/{}", member.name),
+                    size: member.size,
+                    thumbnail: None,
+                    change: None,
+                    trigger: None,
+                    note: None,
+                });
+            }
+            continue;
+        }
+        if !is_image || !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        frames.push(Frame {
+            session: None,
+            time: modified.into(),
+            file: entry.file_name().to_string_lossy().into_owned(),
+            size: metadata.len(),
+            thumbnail: None,
+            change: None,
+            trigger: None,
+            note: None,
+        });
+    }
+
+    frames.sort_by_key(|frame| frame.time);
+    Ok(frames)
+}
+
+fn is_image_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Reads captures, which are files in the folder or in one of its session
+/// archives.
+struct Files<'a> {
+    folder: &'a Path,
+    /// Members of the archives read so far.
+    archives: HashMap<String, Vec<archive::Member>>,
+}
+
+impl Files<'_> {
+    fn member(&mut self, archive: &str, name: &str) -> Option<archive::Member> {
+        let members = self.archives.entry(archive.to_owned()).or_insert_with(|| {
+            match archive::members(&self.folder.join(archive)) {
+                Ok((members, _)) => members,
+                Err(error) => {
+                    println!("{error}");
+                    Vec::new()
+                }
+            }
+        });
+        members.iter().find(|member| member.name == name).cloned()
+    }
+
+    fn size(&mut self, file: &str) -> Option<u64> {
+        match archive::split(file) {
+            Some((archive, name)) => Some(self.member(archive, name)?.size),
+            None => Some(fs::metadata(self.folder.join(file)).ok()?.len()),
+        }
+    }
+
+    fn open(&mut self, file: &str) -> Result<DynamicImage, String> {
+        let Some((archive, name)) = archive::split(file) else {
+            return image::open(self.folder.join(file)).map_err(|error| error.to_string());
+        };
+        let member = self
+            .member(archive, name)
+            .ok_or_else(|| format!("{name} is not in {archive}"))?;
+        let data = archive::read(&self.folder.join(archive), &member)?;
+        image::load_from_memory(&data).map_err(|error| error.to_string())
+    }
+}
+
+/// Gaps in the schedule of every session. Sessions from the manifest use the
+/// interval they were started with, a folder without manifest uses the median
+/// time between its images.
+fn session_gaps(entries: &[manifest::Entry], frames: &[Frame]) -> Vec<Gap> {
+    let mut sessions: BTreeMap<Option<String>, Vec<DateTime<Local>>> = BTreeMap::new();
+    for frame in frames {
+        sessions
+            .entry(frame.session.clone())
+            .or_default()
+            .push(frame.time);
+    }
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.event == EntryKind::Failed)
+    {
+        sessions
+            .entry(Some(entry.session.clone()))
+            .or_default()
+            .push(entry.time);
+    }
+
+    let mut gaps = Vec::new();
+    for (session, mut times) in sessions {
+        times.sort();
+        let interval = match &session {
+            Some(session) => entries
+                .iter()
+                .find(|entry| entry.event == EntryKind::Start && entry.session == *session)
+                .and_then(|entry| entry.interval()),
+            None => median_interval(&times),
+        };
+
+        if let Some(interval) = interval {
+            gaps.extend(timing_gaps(&times, interval));
+        }
+    }
+    gaps
+}
+
+fn median_interval(times: &[DateTime<Local>]) -> Option<Duration> {
+    let mut deltas: Vec<Duration> = times
+        .windows(2)
+        .filter_map(|pair| (pair[1] - pair[0]).to_std().ok())
+        .collect();
+    deltas.sort();
+    deltas.get(deltas.len() / 2).copied()
+}
+
+/// Finds places where consecutive captures are more than one and a half
+/// intervals apart, for example because the computer was asleep.
+fn timing_gaps(times: &[DateTime<Local>], interval: Duration) -> Vec<Gap> {
+    if interval.is_zero() {
+        return Vec::new();
+    }
+
+    times
+        .windows(2)
+        .filter_map(|pair| {
+            let delta = (pair[1] - pair[0]).to_std().ok()?;
+            if delta.as_secs_f64() <= interval.as_secs_f64() * 1.5 {
+                return None;
+            }
+            let missed = (delta.as_secs_f64() / interval.as_secs_f64()).round() as u64 - 1;
+            Some(Gap {
+                from: pair[0],
+                to: pair[1],
+                missed,
+                reason: "No screenshots were taken".to_owned(),
+                failed: false,
+            })
+        })
+        .collect()
+}
+
+fn render(folder: &Path, frames: &[Frame], gaps: &[Gap]) -> String {
+    let mut html = String::new();
+    let title = format!("Take Screenshots report: {}", folder.display());
+
+    let _ = write!(
+        html,
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; }}
+td, th {{ padding: 2px 12px 2px 0; text-align: left; }}
+.bar {{ background: #4a90d9; height: 14px; }}
+.chart td:nth-child(2) {{ width: 400px; }}
+.timeline {{ display: flex; flex-wrap: wrap; gap: 8px; }}
+figure {{ margin: 0; width: {THUMBNAIL_SIZE}px; font-size: 12px; }}
+figure img {{ max-width: 100%; border: 1px solid #ccc; }}
+figure.manual img {{ border: 2px solid #e08e0b; }}
+figure.event img {{ border: 2px solid #2e9d5b; }}
+.gap {{ color: #b00; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+"#,
+        title = escape(&title),
+    );
+
+    let sessions: std::collections::BTreeSet<_> = frames
+        .iter()
+        .filter_map(|frame| frame.session.as_ref())
+        .collect();
+    let failed = gaps.iter().filter(|gap| gap.failed).count();
+    let missed: u64 = gaps
+        .iter()
+        .filter(|gap| !gap.failed)
+        .map(|gap| gap.missed)
+        .sum();
+    let size: u64 = frames.iter().map(|frame| frame.size).sum();
+    let changes: Vec<f64> = frames.iter().filter_map(|frame| frame.change).collect();
+
+    html.push_str("<h2>Totals</h2>\n<table>\n");
+    let mut row = |name: &str, value: String| {
+        let _ = writeln!(html, "<tr><th>{name}</th><td>{}</td></tr>", escape(&value));
+    };
+    if let (Some(first), Some(last)) = (frames.first(), frames.last()) {
+        row(
+            "First screenshot",
+            first.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        );
+        row(
+            "Last screenshot",
+            last.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        );
+    }
+    if !sessions.is_empty() {
+        row("Sessions", sessions.len().to_string());
+    }
+    row("Screenshots", frames.len().to_string());
+    row("Failed captures", failed.to_string());
+    row("Missed captures", missed.to_string());
+    row("Total size", format!("{:.1} MB", size as f64 / 1_000_000.0));
+    if !changes.is_empty() {
+        let average = changes.iter().sum::<f64>() / changes.len() as f64;
+        row(
+            "Average change between screenshots",
+            format!("{:.1}%", average * 100.0),
+        );
+    }
+    html.push_str("</table>\n");
+
+    let mut hours: BTreeMap<String, (usize, f64, usize)> = BTreeMap::new();
+    for frame in frames {
+        let hour = hours
+            .entry(frame.time.format("%Y-%m-%d %H:00").to_string())
+            .or_default();
+        hour.0 += 1;
+        if let Some(change) = frame.change {
+            hour.1 += change;
+            hour.2 += 1;
+        }
+    }
+    let average = |(_, sum, count): &(usize, f64, usize)| sum / (*count).max(1) as f64;
+    let busiest = hours.values().map(average).fold(0.0, f64::max);
+
+    html.push_str("<h2>Activity per hour</h2>\n<table class=\"chart\">\n");
+    for (hour, totals) in &hours {
+        let change = average(totals);
+        let width = if busiest > 0.0 {
+            change / busiest * 100.0
+        } else {
+            0.0
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{hour}</td><td><div class=\"bar\" style=\"width: {width:.1}%\"></div></td><td>{} screenshots, {:.1}% change</td></tr>",
+            totals.0,
+            change * 100.0
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Gaps</h2>\n");
+    if gaps.is_empty() {
+        html.push_str("<p>No failed or missed captures.</p>\n");
+    } else {
+        html.push_str("<table>\n");
+        for gap in gaps {
+            let when = if gap.failed {
+                gap.from.format("%Y-%m-%d %H:%M:%S").to_string()
+            } else {
+                format!(
+                    "{} to {}",
+                    gap.from.format("%Y-%m-%d %H:%M:%S"),
+                    gap.to.format("%H:%M:%S")
+                )
+            };
+            let what = if gap.failed {
+                format!("Failed: {}", gap.reason)
+            } else {
+                format!("{} ({} missed)", gap.reason, gap.missed)
+            };
+            let _ = writeln!(
+                html,
+                "<tr class=\"gap\"><td>{}</td><td>{}</td></tr>",
+                escape(&when),
+                escape(&what)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<h2>Timeline</h2>\n<div class=\"timeline\">\n");
+    for frame in frames {
+        let mut caption = match frame.change {
+            Some(change) => format!("{} · {:.1}%", frame.time.format("%H:%M:%S"), change * 100.0),
+            None => frame.time.format("%H:%M:%S").to_string(),
+        };
+        let class = match frame.trigger {
+            Some(Trigger::Manual) => " class=\"manual\"",
+            Some(_) => " class=\"event\"",
+            None => "",
+        };
+        match frame.trigger {
+            Some(Trigger::Timer) | None => {}
+            Some(Trigger::Manual) => caption.push_str(" · manual"),
+            Some(Trigger::Window) => caption.push_str(" · window switch"),
+            Some(Trigger::Clipboard) => caption.push_str(" · clipboard"),
+            Some(Trigger::File) => caption.push_str(" · file change"),
+            Some(Trigger::Motion) => caption.push_str(" · motion"),
+        }
+        if let Some(note) = &frame.note {
+            let _ = write!(caption, " · {note}");
+        }
+        let image = match &frame.thumbnail {
+            Some(thumbnail) => format!("<img src=\"{}\" loading=\"lazy\">", escape(thumbnail)),
+            None => escape(&frame.file),
+        };
+        // Browsers can't open a file inside an archive, so those link to
+        // their thumbnail.
+        let link = match (archive::split(&frame.file), &frame.thumbnail) {
+            (Some(_), Some(thumbnail)) => url_path(thumbnail),
+            _ => format!("../{}", url_path(&frame.file)),
+        };
+        let _ = writeln!(
+            html,
+            "<figure{}><a href=\"{}\" title=\"{}\">{image}</a><figcaption>{}</figcaption></figure>",
+            class,
+            escape(&link),
+            escape(&frame.file),
+            escape(&caption)
+        );
+    }
+    html.push_str("</div>\n");
+
+    let _ = writeln!(
+        html,
+        "<p><small>Generated {}.</small></p>\n</body>\n</html>",
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+
+    html
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encodes a relative file path for use in a link.
+fn url_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.replace('\\', "/").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2023, 2, 20, 12, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_timing_gaps() {
+        let times = vec![at(0), at(5), at(10), at(25), at(30)];
+        let gaps = timing_gaps(&times, Duration::from_secs(300));
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(
+            (gaps[0].from, gaps[0].to, gaps[0].missed),
+            (at(10), at(25), 2)
+        );
+    }
+
+    #[test]
+    fn test_median_interval() {
+        let times = vec![at(0), at(5), at(10), at(25), at(30)];
+        assert_eq!(median_interval(&times), Some(Duration::from_secs(300)));
+        assert_eq!(median_interval(&times[..1]), None);
+    }
+
+    #[test]
+    fn test_difference() {
+        let black = GrayImage::new(4, 4);
+        let mut half = GrayImage::new(4, 4);
+        for (x, _, pixel) in half.enumerate_pixels_mut() {
+            if x < 2 {
+                pixel.0 = [255];
+            }
+        }
+        assert_eq!(difference(&black, &black), 0.0);
+        assert!((difference(&black, &half) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_url_path() {
+        assert_eq!(url_path("ts 2023#1.png"), "ts%202023%231.png");
+        assert_eq!(url_path("2023\\02\\a.png"), "2023/02/a.png");
+    }
+}
@@ -0,0 +1,205 @@
+//! Shrinks and converts captures before they are encoded.
+
+use image::{imageops::FilterType, DynamicImage, RgbaImage};
+
+use crate::cursor::Pointer;
+use crate::i18n::trf;
+
+/// Items of the filter choice, in the order `Resize::parse` expects.
+pub const FILTER_CHOICES: &str = "Lanczos3|Catmull-Rom|Triangle|Gaussian|Nearest";
+
+const FILTERS: [FilterType; 5] = [
+    FilterType::Lanczos3,
+    FilterType::CatmullRom,
+    FilterType::Triangle,
+    FilterType::Gaussian,
+    FilterType::Nearest,
+];
+
+/// How big a saved screenshot may be. Images are only ever made smaller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Original,
+    /// Percentage of the captured size.
+    Scale(u32),
+    /// Fits the image in this box, keeping its aspect ratio. A missing side
+    /// is not limited.
+    Fit {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+}
+
+impl Size {
+    /// Parses `""`, `50%`, `1920x1080`, `1920x` or `x1080`.
+    fn parse(text: &str) -> Result<Size, String> {
+        let text = text.trim();
+        let error = || {
+            trf("Failed to parse the size \"{text}\".\nUse a percentage like \"50%\" or a maximum size like \"1920x1080\", \"1920x\" or \"x1080\".", &[("text", &text)])
+        };
+
+        if text.is_empty() {
+            return Ok(Size::Original);
+        }
+
+        if let Some(percent) = text.strip_suffix('%') {
+            return match percent.trim().parse::<u32>() {
+                Ok(100) => Ok(Size::Original),
+                Ok(percent) if (1..100).contains(&percent) => Ok(Size::Scale(percent)),
+                _ => Err(error()),
+            };
+        }
+
+        let (width, height) = text.split_once('x').ok_or_else(error)?;
+        let side = |side: &str| match side.trim() {
+            "" => Ok(None),
+            side => match side.parse::<u32>() {
+                Ok(side) if side > 0 => Ok(Some(side)),
+                _ => Err(error()),
+            },
+        };
+        match (side(width)?, side(height)?) {
+            (None, None) => Err(error()),
+            (width, height) => Ok(Size::Fit { width, height }),
+        }
+    }
+
+    /// The size of an image of `width` by `height` after resizing.
+    fn apply(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = match *self {
+            Size::Original => 1.0,
+            Size::Scale(percent) => percent as f64 / 100.0,
+            Size::Fit {
+                width: max_width,
+                height: max_height,
+            } => {
+                let x = max_width.map_or(1.0, |max| max as f64 / width as f64);
+                let y = max_height.map_or(1.0, |max| max as f64 / height as f64);
+                x.min(y).min(1.0)
+            }
+        };
+
+        let scaled = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+}
+
+/// The resize stage of a job.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Resize {
+    pub size: Size,
+    pub filter: FilterType,
+    pub grayscale: bool,
+}
+
+impl Resize {
+    /// `filter` is the index into `FILTER_CHOICES`.
+    pub fn parse(size: &str, filter: i32, grayscale: bool) -> Result<Resize, String> {
+        Ok(Resize {
+            size: Size::parse(size)?,
+            filter: FILTERS
+                .get(filter as usize)
+                .copied()
+                .unwrap_or(FilterType::Lanczos3),
+            grayscale,
+        })
+    }
+
+    /// Resizes `image` and moves `pointer`, which is on it, along.
+    pub fn apply(
+        &self,
+        image: RgbaImage,
+        pointer: Option<Pointer>,
+    ) -> (DynamicImage, Option<Pointer>) {
+        let (width, height) = image.dimensions();
+        let (new_width, new_height) = self.size.apply(width, height);
+
+        let mut image = DynamicImage::ImageRgba8(image);
+        let mut pointer = pointer;
+        if (new_width, new_height) != (width, height) {
+            image = image.resize_exact(new_width, new_height, self.filter);
+            pointer = pointer.map(|pointer| Pointer {
+                x: (pointer.x as i64 * new_width as i64 / width as i64) as i32,
+                y: (pointer.y as i64 * new_height as i64 / height as i64) as i32,
+                ..pointer
+            });
+        }
+
+        if self.grayscale {
+            image = DynamicImage::ImageLuma8(image.to_luma8());
+        }
+        (image, pointer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Size::parse("").unwrap(), Size::Original);
+        assert_eq!(Size::parse("100%").unwrap(), Size::Original);
+        assert_eq!(Size::parse(" 50 %").unwrap(), Size::Scale(50));
+        assert_eq!(
+            Size::parse("1920x1080").unwrap(),
+            Size::Fit {
+                width: Some(1920),
+                height: Some(1080)
+            }
+        );
+        assert_eq!(
+            Size::parse("x720").unwrap(),
+            Size::Fit {
+                width: None,
+                height: Some(720)
+            }
+        );
+        assert!(Size::parse("150%").is_err());
+        assert!(Size::parse("0%").is_err());
+        assert!(Size::parse("x").is_err());
+        assert!(Size::parse("1920").is_err());
+    }
+
+    #[test]
+    fn test_apply_size() {
+        assert_eq!(Size::Scale(50).apply(3840, 2160), (1920, 1080));
+        let fit = Size::Fit {
+            width: Some(1920),
+            height: Some(1080),
+        };
+        assert_eq!(fit.apply(3840, 2160), (1920, 1080));
+        assert_eq!(fit.apply(2560, 1600), (1728, 1080));
+        // Smaller images, like regions, are left alone.
+        assert_eq!(fit.apply(800, 600), (800, 600));
+        assert_eq!(
+            Size::Fit {
+                width: Some(1000),
+                height: None
+            }
+            .apply(4000, 10),
+            (1000, 3)
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let resize = Resize::parse("50%", 4, true).unwrap();
+        let pointer = Pointer {
+            x: 30,
+            y: 11,
+            buttons: 1,
+        };
+        let (image, pointer) = resize.apply(RgbaImage::new(40, 20), Some(pointer));
+        assert_eq!((image.width(), image.height()), (20, 10));
+        assert!(matches!(image, DynamicImage::ImageLuma8(_)));
+        assert_eq!(
+            pointer,
+            Some(Pointer {
+                x: 15,
+                y: 5,
+                buttons: 1
+            })
+        );
+    }
+}
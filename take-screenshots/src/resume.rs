@@ -0,0 +1,206 @@
+//! Saved state of running sessions, so a session cut short by a crash or a
+//! reboot can carry on at the next launch. Each running session keeps one
+//! file in the state folder, rewritten as it goes and removed when it ends.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobSettings;
+use crate::manifest::Manifest;
+use crate::session::StopMode;
+
+/// Where a running session is up to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct State {
+    pub settings: JobSettings,
+    /// Start of the session, which its id is made from, so the manifest and
+    /// the archive carry on under the same id.
+    pub started: DateTime<Local>,
+    /// The stop mode as parsed at the start, so "Stop at 18:00" keeps its
+    /// day.
+    pub stop: StopMode,
+    pub first_capture: Option<DateTime<Local>>,
+    pub next_capture: DateTime<Local>,
+    /// Captures of the schedule, which "Stop after" counts.
+    pub taken: u64,
+    pub captures: u64,
+    pub failed: u64,
+    pub paused: bool,
+}
+
+impl State {
+    pub fn new(settings: JobSettings, started: DateTime<Local>, stop: StopMode) -> Self {
+        State {
+            settings,
+            started,
+            stop,
+            first_capture: None,
+            next_capture: started,
+            taken: 0,
+            captures: 0,
+            failed: 0,
+            paused: false,
+        }
+    }
+
+    /// Whether the session would have ended by `now` if it had kept running.
+    pub fn is_finished(&self, now: DateTime<Local>) -> bool {
+        let elapsed = self
+            .first_capture
+            .and_then(|first| (now - first).to_std().ok())
+            .unwrap_or(Duration::ZERO);
+        self.stop.is_reached(elapsed, now, self.taken)
+    }
+
+    /// The session id, as in the manifest.
+    pub fn session(&self) -> String {
+        Manifest::new(
+            &self.settings.output_folder,
+            self.settings.name.trim(),
+            self.started,
+        )
+        .session()
+        .to_owned()
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.json", self.session())
+    }
+}
+
+/// The state folder, in the local data folder of the user.
+pub fn folder() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "take-screenshots").map(|dirs| dirs.data_local_dir().join("sessions"))
+}
+
+/// Writes `state` into `folder`. The file is replaced in one step, so a crash
+/// leaves the previous state.
+pub fn save(folder: &Path, state: &State) -> Result<(), String> {
+    fs::create_dir_all(folder)
+        .map_err(|error| format!("Failed to create {}: {error}", folder.display()))?;
+    let path = folder.join(state.file_name());
+    let temporary = path.with_extension("json.tmp");
+    let json = serde_json::to_string_pretty(state).map_err(|error| error.to_string())?;
+    fs::write(&temporary, json)
+        .and_then(|_| fs::rename(&temporary, &path))
+        .map_err(|error| format!("Failed to write {}: {error}", path.display()))
+}
+
+pub fn remove(folder: &Path, state: &State) {
+    let path = folder.join(state.file_name());
+    if let Err(error) = fs::remove_file(&path) {
+        if error.kind() != std::io::ErrorKind::NotFound {
+            println!("Failed to remove {}: {error}", path.display());
+        }
+    }
+}
+
+/// Reads the states in `folder`, oldest session first. Files that can't be
+/// read are skipped.
+pub fn load(folder: &Path) -> Vec<State> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return Vec::new();
+    };
+
+    let mut states: Vec<State> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter_map(|path| {
+            let state = fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|json| serde_json::from_str(&json).map_err(|error| error.to_string()));
+            match state {
+                Ok(state) => Some(state),
+                Err(error) => {
+                    println!("Failed to read {}: {error}", path.display());
+                    None
+                }
+            }
+        })
+        .collect();
+    states.sort_by_key(|state| state.started);
+    states
+}
+
+/// The sessions that were running when the last instance was killed. Those
+/// that would have ended since are forgotten.
+pub fn interrupted(now: DateTime<Local>) -> Vec<State> {
+    let Some(folder) = folder() else {
+        return Vec::new();
+    };
+
+    let (finished, interrupted): (Vec<State>, Vec<State>) = load(&folder)
+        .into_iter()
+        .partition(|state| state.is_finished(now));
+    for state in &finished {
+        remove(&folder, state);
+    }
+    interrupted
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_save_load() {
+        let folder =
+            std::env::temp_dir().join(format!("take-screenshots-resume-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        let started = Local.with_ymd_and_hms(2023, 3, 1, 9, 30, 0).unwrap();
+        let settings = JobSettings::new("Desktop", "/tmp");
+
+        let mut second = State::new(settings.clone(), started, StopMode::Count(10));
+        second.taken = 4;
+        save(&folder, &second).unwrap();
+        let first = State::new(
+            settings,
+            started - chrono::Duration::minutes(1),
+            StopMode::Never,
+        );
+        save(&folder, &first).unwrap();
+        fs::write(folder.join("broken.json"), "{").unwrap();
+
+        let states = load(&folder);
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0].stop, StopMode::Never);
+        assert_eq!(states[1].taken, 4);
+        assert_eq!(states[1].started, started);
+
+        remove(&folder, &states[0]);
+        assert_eq!(load(&folder).len(), 1);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_is_finished() {
+        let started = Local.with_ymd_and_hms(2023, 3, 1, 9, 30, 0).unwrap();
+        let settings = JobSettings::new("Desktop", "/tmp");
+        let hour = chrono::Duration::hours(1);
+
+        let mut state = State::new(
+            settings.clone(),
+            started,
+            StopMode::After(Duration::from_secs(7200)),
+        );
+        assert!(!state.is_finished(started + hour * 5));
+        state.first_capture = Some(started);
+        assert!(!state.is_finished(started + hour));
+        assert!(state.is_finished(started + hour * 2));
+
+        let state = State::new(settings, started, StopMode::At(started + hour));
+        assert!(state.is_finished(started + hour));
+    }
+}
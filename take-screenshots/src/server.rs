@@ -0,0 +1,510 @@
+//! Optional HTTP server for wall displays and other local tools:
+//!
+//! - `/` is a page showing the latest capture, reloading itself,
+//! - `/latest.png` is the latest capture of any job, or of `?job=NAME`,
+//! - `/captures.json` lists the captures of the running sessions.
+//!
+//! With a token, every request needs `?token=TOKEN` or an
+//! `Authorization: Bearer TOKEN` header. Without one, only requests naming
+//! this computer in their `Host` header are answered, so web pages can't
+//! reach the server by pointing their own name at 127.0.0.1.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::archive;
+use crate::i18n::trf;
+use crate::jobs::{Job, Jobs};
+use crate::manifest::{self, EntryKind};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8765";
+
+/// Seconds between reloads of the page.
+const REFRESH: u32 = 5;
+
+/// Longest request head read, requests never have a body.
+const MAX_HEAD: usize = 8192;
+
+/// How long a client gets to send its request head.
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parses the address to serve at: a port, served on loopback only, or an
+/// address and port. Other computers can only be served with a token.
+pub fn parse_address(value: &str, token: &str) -> Result<SocketAddr, String> {
+    let value = value.trim();
+    let address = match value.parse::<u16>() {
+        Ok(port) => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
+        Err(_) => value.parse::<SocketAddr>().map_err(|_| {
+            trf(
+                "\"{value}\" is not an address to serve at.\nUse a port like \"8765\" or an address like \"{example}\".",
+                &[("value", &value), ("example", &DEFAULT_ADDRESS)],
+            )
+        })?,
+    };
+    if !address.ip().is_loopback() && token.trim().is_empty() {
+        return Err(trf(
+            "Set a token to serve at {address}, other computers could see the screenshots.",
+            &[("address", &address)],
+        ));
+    }
+    Ok(address)
+}
+
+/// Answers HTTP requests until the task is aborted.
+pub async fn serve(listener: TcpListener, jobs: Arc<Jobs>, token: Option<String>) {
+    let token = Arc::new(token);
+    let hosts = match listener.local_addr() {
+        Ok(address) => Arc::new(allowed_hosts(address)),
+        Err(error) => {
+            println!("Failed to read the HTTP server address: {error}");
+            return;
+        }
+    };
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                println!("Failed to accept HTTP connection: {error}");
+                continue;
+            }
+        };
+
+        let jobs = jobs.clone();
+        let token = token.clone();
+        let hosts = hosts.clone();
+        tokio::spawn(async move {
+            if let Err(error) = answer(stream, &jobs, token.as_deref(), &hosts).await {
+                println!("Failed to answer HTTP request: {error}");
+            }
+        });
+    }
+}
+
+/// The `Host` headers naming this computer at `address`, lowercase.
+fn allowed_hosts(address: SocketAddr) -> Vec<String> {
+    let port = address.port();
+    let mut hosts: Vec<String> = ["localhost", "127.0.0.1", "[::1]"]
+        .iter()
+        .map(|host| format!("{host}:{port}"))
+        .chain([address.to_string()])
+        .collect();
+    // The port can be left out when it is the default one.
+    if port == 80 {
+        let without_port: Vec<String> = hosts
+            .iter()
+            .filter_map(|host| host.strip_suffix(":80"))
+            .map(|host| host.to_owned())
+            .collect();
+        hosts.extend(without_port);
+    }
+    hosts
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    bearer: Option<String>,
+    host: Option<String>,
+}
+
+impl Request {
+    /// Parses the request line and headers, up to the empty line.
+    fn parse(head: &str) -> Option<Request> {
+        let mut lines = head.lines();
+        let mut parts = lines.next()?.split_whitespace();
+        let method = parts.next()?.to_owned();
+        let target = parts.next()?;
+        parts
+            .next()
+            .filter(|version| version.starts_with("HTTP/"))?;
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(key), decode(value))
+            })
+            .collect();
+        let headers: Vec<(&str, &str)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .collect();
+        let header = |wanted: &str| {
+            headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(wanted))
+                .map(|(_, value)| *value)
+        };
+        let bearer = header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_owned());
+        let host = header("host").map(|host| host.to_ascii_lowercase());
+
+        Some(Request {
+            method,
+            path: decode(path),
+            query,
+            bearer,
+            host,
+        })
+    }
+
+    /// Whether the request is meant for this computer. Only checked without a
+    /// token, the token already keeps other pages out.
+    fn is_local(&self, hosts: &[String], token: Option<&str>) -> bool {
+        token.is_some() || self.host.as_ref().is_some_and(|host| hosts.contains(host))
+    }
+
+    fn is_authorized(&self, token: Option<&str>) -> bool {
+        let Some(token) = token else {
+            return true;
+        };
+        [self.query.get("token"), self.bearer.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(|given| same(given.as_bytes(), token.as_bytes()))
+    }
+}
+
+/// Compares in a time that doesn't depend on where the first difference is.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Decodes `%XX` escapes and `+` in a URL.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: &'static str, text: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{text}\n").into_bytes(),
+        }
+    }
+}
+
+async fn answer(
+    mut stream: TcpStream,
+    jobs: &Jobs,
+    token: Option<&str>,
+    hosts: &[String],
+) -> std::io::Result<()> {
+    let head = tokio::time::timeout(HEAD_TIMEOUT, read_head(&mut stream))
+        .await
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "the request head didn't arrive in time",
+            )
+        })??;
+
+    let request = Request::parse(&String::from_utf8_lossy(&head));
+    let response = match &request {
+        None => Response::text("400 Bad Request", "Bad request"),
+        Some(request) if !request.is_local(hosts, token) => {
+            Response::text("403 Forbidden", "Unknown host")
+        }
+        Some(request) if !request.is_authorized(token) => {
+            Response::text("401 Unauthorized", "Add ?token=TOKEN to the address")
+        }
+        Some(request) if request.method != "GET" && request.method != "HEAD" => {
+            Response::text("405 Method Not Allowed", "Only GET is supported")
+        }
+        Some(request) => route(request, jobs).await,
+    };
+
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    if request.is_none_or(|request| request.method != "HEAD") {
+        stream.write_all(&response.body).await?;
+    }
+    stream.shutdown().await
+}
+
+/// Reads up to the empty line ending the request head.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+        if head.len() > MAX_HEAD {
+            break;
+        }
+    }
+    Ok(head)
+}
+
+async fn route(request: &Request, jobs: &Jobs) -> Response {
+    let jobs = jobs.all();
+    match request.path.as_str() {
+        "/" => Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: page(request, &jobs).into_bytes(),
+        },
+        "/latest.png" => {
+            let name = request.query.get("job");
+            let latest = jobs
+                .iter()
+                .filter(|job| name.is_none_or(|name| job.settings.name.trim() == name))
+                .map(|job| job.controller.status())
+                .filter(|status| status.last_capture.is_some())
+                .max_by_key(|status| status.last_capture_time);
+            let Some(path) = latest.and_then(|status| status.last_capture) else {
+                return Response::text("404 Not Found", "No screenshot yet");
+            };
+            match tokio::task::spawn_blocking(move || png(&path)).await {
+                Ok(Ok(body)) => Response {
+                    status: "200 OK",
+                    content_type: "image/png",
+                    body,
+                },
+                Ok(Err(error)) => Response::text("500 Internal Server Error", &error),
+                Err(error) => Response::text("500 Internal Server Error", &error.to_string()),
+            }
+        }
+        "/captures.json" => match tokio::task::spawn_blocking(move || feed(&jobs)).await {
+            Ok(feed) => Response {
+                status: "200 OK",
+                content_type: "application/json",
+                body: serde_json::to_vec(&feed).unwrap_or_default(),
+            },
+            Err(error) => Response::text("500 Internal Server Error", &error.to_string()),
+        },
+        _ => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+/// The capture at `path` as PNG, converted if it was saved in another format.
+fn png(path: &Path) -> Result<Vec<u8>, String> {
+    let data = archive::read_path(path)?;
+    if data.starts_with(b"\x89PNG") {
+        return Ok(data);
+    }
+
+    let image = image::load_from_memory(&data)
+        .map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+    let mut buffer = Vec::new();
+    image
+        .write_to(
+            &mut std::io::Cursor::new(&mut buffer),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(|error| error.to_string())?;
+    Ok(buffer)
+}
+
+#[derive(Serialize)]
+struct Feed {
+    job: String,
+    running: bool,
+    paused: bool,
+    session: Option<String>,
+    captures: Vec<manifest::Entry>,
+}
+
+/// The captures of the session each job runs, or ran last, from the
+/// manifests.
+fn feed(jobs: &[Job]) -> Vec<Feed> {
+    jobs.iter()
+        .map(|job| {
+            let status = job.controller.status();
+            let captures = match (&status.session, &status.folder) {
+                (Some(session), Some(folder)) => manifest::read(Path::new(folder))
+                    .into_iter()
+                    .filter(|entry| entry.event == EntryKind::Capture && entry.session == *session)
+                    .collect(),
+                _ => Vec::new(),
+            };
+            Feed {
+                job: job.settings.name.clone(),
+                running: status.running,
+                paused: status.paused,
+                session: status.session,
+                captures,
+            }
+        })
+        .collect()
+}
+
+fn page(request: &Request, jobs: &[Job]) -> String {
+    // The image needs the token too, when the page was opened with one.
+    let query = match request.query.get("token") {
+        Some(token) => format!("?token={}", escape_url(token)),
+        None => String::new(),
+    };
+    let rows: String = jobs
+        .iter()
+        .map(|job| {
+            let status = job.controller.status();
+            let state = match (status.running, status.paused) {
+                (true, true) => "paused",
+                (true, false) => "running",
+                _ => "stopped",
+            };
+            format!(
+                "<li>{} · {state} · {} screenshots{}</li>",
+                escape_html(&job.settings.name),
+                status.captures,
+                status
+                    .last_capture_time
+                    .map(|time| format!(", last at {}", time.format("%H:%M:%S")))
+                    .unwrap_or_default()
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{REFRESH}">
+<title>Take Screenshots</title>
+<style>
+body {{ margin: 0; background: #111; color: #ddd; font: 14px sans-serif; }}
+img {{ display: block; max-width: 100vw; max-height: calc(100vh - 3em); margin: auto; }}
+ul {{ list-style: none; margin: 0.5em; padding: 0; text-align: center; }}
+li {{ display: inline; margin: 0 1em; }}
+</style>
+</head>
+<body>
+<img src="/latest.png{query}" alt="No screenshot yet">
+<ul>{rows}</ul>
+</body>
+</html>
+"#
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_url(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parse_address("8765", "").unwrap(),
+            "127.0.0.1:8765".parse().unwrap()
+        );
+        assert!(parse_address("[::1]:8765", "").is_ok());
+        assert!(parse_address("0.0.0.0:8765", "").is_err());
+        assert!(parse_address("0.0.0.0:8765", "secret").is_ok());
+        assert!(parse_address("localhost", "").is_err());
+    }
+
+    #[test]
+    fn test_request() {
+        let request = Request::parse(
+            "GET /latest.png?job=My%20job&token=a+b HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.path, "/latest.png");
+        assert_eq!(request.query["job"], "My job");
+        assert!(request.is_authorized(Some("a b")));
+        assert!(!request.is_authorized(Some("a")));
+
+        let request =
+            Request::parse("GET / HTTP/1.1\r\nauthorization: Bearer secret\r\n\r\n").unwrap();
+        assert!(request.is_authorized(Some("secret")));
+        assert!(request.is_authorized(None));
+        assert!(Request::parse("GET /\r\n\r\n").is_none());
+        assert_eq!(escape_url("a b&c"), "a%20b%26c");
+    }
+
+    #[test]
+    fn test_is_local() {
+        let hosts = allowed_hosts("127.0.0.1:8765".parse().unwrap());
+        let request = |host: &str| {
+            Request::parse(&format!("GET / HTTP/1.1\r\nHost: {host}\r\n\r\n")).unwrap()
+        };
+        assert!(request("localhost:8765").is_local(&hosts, None));
+        assert!(request("LocalHost:8765").is_local(&hosts, None));
+        assert!(request("127.0.0.1:8765").is_local(&hosts, None));
+        assert!(request("[::1]:8765").is_local(&hosts, None));
+        // A page that pointed its own name at 127.0.0.1.
+        assert!(!request("attacker.example:8765").is_local(&hosts, None));
+        assert!(!request("localhost:8080").is_local(&hosts, None));
+        assert!(!Request::parse("GET / HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .is_local(&hosts, None));
+        assert!(request("attacker.example:8765").is_local(&hosts, Some("secret")));
+
+        let hosts = allowed_hosts("[::1]:80".parse().unwrap());
+        assert!(request("localhost").is_local(&hosts, None));
+        assert!(request("[::1]").is_local(&hosts, None));
+    }
+}
@@ -0,0 +1,683 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+
+use crate::archive;
+use crate::capture::{grab_sample, save, screenshot, Capture, Grabber, Sample, Shot, Target};
+use crate::hooks::{Hook, HookContext, HookEvent, TempCopy};
+use crate::i18n::{format_duration, parse_duration, tr, trf};
+use crate::manifest::{Manifest, Trigger};
+use crate::motion::{self, Burst, Motion};
+#[cfg(feature = "ocr")]
+use crate::ocr::{Entry, Indexer};
+use crate::resize::Resize;
+use crate::resume::{self, State};
+use crate::triggers::{Debounce, Triggers};
+
+/// Items of the stop mode choice, in the order `StopMode::parse` expects.
+pub const STOP_MODE_CHOICES: &str = "Stop in|Stop at|Stop after|Never stop";
+
+/// Longest duration between screenshots, and longest "Stop in". Captures
+/// of a long session must still have a date.
+const MAX_DURATION: Duration = Duration::from_secs(100 * 31_557_600);
+
+/// Without the ocr feature no session has an indexer.
+#[cfg(not(feature = "ocr"))]
+enum Indexer {}
+
+/// When a session ends on its own. Any session can also be stopped by hand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopMode {
+    /// Stop once this much time has passed since the first capture.
+    After(Duration),
+    /// Stop at a wall-clock time.
+    At(DateTime<Local>),
+    /// Stop after this many captures.
+    Count(u64),
+    /// Keep capturing until stopped.
+    Never,
+}
+
+impl StopMode {
+    /// Parses the value typed next to the stop mode choice. `choice` is the
+    /// index into `STOP_MODE_CHOICES`.
+    pub fn parse(choice: i32, value: &str, now: DateTime<Local>) -> Result<StopMode, String> {
+        let value = value.trim();
+        match choice {
+            0 => parse_duration(value)
+                .map(StopMode::After)
+                .map_err(|error| {
+                    trf("Failed to parse \"Stop in\": {error}.\nTry something like \"2 hours\".", &[("error", &error)])
+                }),
+            1 => parse_wall_clock(value, now).map(StopMode::At).ok_or_else(|| {
+                tr("Failed to parse \"Stop at\".\nUse a time like \"18:30\" or a date and time like \"2023-02-20 18:30\".")
+                    .to_owned()
+            }),
+            2 => value
+                .parse::<u64>()
+                .map(StopMode::Count)
+                .map_err(|_| tr("\"Stop after\" expects a number of screenshots, like \"100\".").to_owned()),
+            _ => Ok(StopMode::Never),
+        }
+    }
+
+    /// Checks that a session with this stop mode and `interval` between
+    /// captures would take more than one screenshot.
+    pub fn validate(&self, interval: Duration, now: DateTime<Local>) -> Result<(), String> {
+        if interval.is_zero() {
+            return Err(
+                tr("The duration between screenshots must be longer than zero.").to_owned(),
+            );
+        }
+        if interval > MAX_DURATION {
+            return Err(trf(
+                "The duration between screenshots can be at most {max}.",
+                &[("max", &format_duration(MAX_DURATION))],
+            ));
+        }
+
+        match *self {
+            StopMode::After(stop) if stop > MAX_DURATION => Err(trf(
+                "\"Stop in\" can be at most {max}.",
+                &[("max", &format_duration(MAX_DURATION))],
+            )),
+            StopMode::After(stop) if stop < interval => Err(trf(
+                "\"Stop in\" ({stop}) is shorter than the duration ({interval}), so only one screenshot would be taken.\nMake \"Stop in\" longer or the duration shorter.",
+                &[
+                    ("stop", &format_duration(stop)),
+                    ("interval", &format_duration(interval)),
+                ],
+            )),
+            StopMode::At(stop) if stop <= now => Err(trf(
+                "\"Stop at\" ({time}) is already in the past.",
+                &[("time", &stop.format("%Y-%m-%d %H:%M:%S"))],
+            )),
+            StopMode::At(stop) if (stop - now).to_std().unwrap_or_default() < interval => {
+                Err(trf(
+                    "\"Stop at\" ({time}) comes before the second screenshot would be taken ({interval} from now), so only one screenshot would be taken.\nPick a later time or a shorter duration.",
+                    &[
+                        ("time", &stop.format("%Y-%m-%d %H:%M:%S")),
+                        ("interval", &format_duration(interval)),
+                    ],
+                ))
+            }
+            StopMode::Count(0) => Err(tr("\"Stop after\" must be at least 1 screenshot.").to_owned()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the session should end instead of taking the next capture.
+    /// `elapsed` is measured from the first capture and `taken` counts the
+    /// captures so far.
+    pub fn is_reached(&self, elapsed: Duration, now: DateTime<Local>, taken: u64) -> bool {
+        match *self {
+            StopMode::After(stop) => elapsed >= stop,
+            StopMode::At(stop) => now >= stop,
+            StopMode::Count(count) => taken >= count,
+            StopMode::Never => false,
+        }
+    }
+}
+
+/// Accepts `HH:MM[:SS]`, meaning the next time the clock shows it, or a full
+/// `YYYY-MM-DD HH:MM[:SS]`.
+fn parse_wall_clock(value: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            let time = ["%H:%M:%S", "%H:%M"]
+                .iter()
+                .find_map(|format| NaiveTime::parse_from_str(value, format).ok())?;
+            let today = now.date_naive().and_time(time);
+            if today > now.naive_local() {
+                Some(today)
+            } else {
+                Some(today + chrono::Duration::days(1))
+            }
+        })?;
+
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Commands the window, the control socket or a hotkey can send to the
+/// running session.
+#[derive(Debug)]
+pub enum SessionCommand {
+    Stop,
+    Pause,
+    Resume,
+    /// Takes a screenshot outside the schedule, tagged as manual. `done`
+    /// receives the path of the image or why it failed.
+    CaptureNow {
+        done: Option<oneshot::Sender<Result<PathBuf, String>>>,
+    },
+    /// Attaches a note to a capture of the session.
+    Annotate {
+        path: PathBuf,
+        note: String,
+    },
+}
+
+/// What the running session, or the last one, is doing.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Status {
+    pub running: bool,
+    pub paused: bool,
+    pub session: Option<String>,
+    pub folder: Option<String>,
+    pub backend: Option<&'static str>,
+    pub started: Option<DateTime<Local>>,
+    pub captures: u64,
+    pub failed: u64,
+    pub last_capture: Option<PathBuf>,
+    pub last_capture_time: Option<DateTime<Local>>,
+    pub last_error: Option<String>,
+}
+
+/// Shared handle to the session of this process. The window attaches a new
+/// session to it, everything else sends commands and reads the status.
+#[derive(Default)]
+pub struct Controller {
+    commands: Mutex<Option<mpsc::UnboundedSender<SessionCommand>>>,
+    status: Mutex<Status>,
+}
+
+impl Controller {
+    /// Marks a new session as running and returns the receiving end of its
+    /// commands, or `None` when a session is already running.
+    pub fn attach(&self) -> Option<mpsc::UnboundedReceiver<SessionCommand>> {
+        let mut commands = self.commands.lock().unwrap();
+        if commands.is_some() {
+            return None;
+        }
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *commands = Some(sender);
+        *self.status.lock().unwrap() = Status {
+            running: true,
+            ..Status::default()
+        };
+        Some(receiver)
+    }
+
+    fn detach(&self) {
+        *self.commands.lock().unwrap() = None;
+        self.update(|status| {
+            status.running = false;
+            status.paused = false;
+        });
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.commands.lock().unwrap().is_some()
+    }
+
+    /// Sends a command to the running session. Returns false when there is
+    /// none.
+    pub fn send(&self, command: SessionCommand) -> bool {
+        match &*self.commands.lock().unwrap() {
+            Some(sender) => sender.send(command).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn update(&self, update: impl FnOnce(&mut Status)) {
+        update(&mut self.status.lock().unwrap());
+    }
+}
+
+pub struct Session {
+    /// Name of the job this session runs.
+    pub name: String,
+    pub target: Target,
+    /// Draw the mouse cursor into the screenshots.
+    pub cursor: bool,
+    pub resize: Resize,
+    pub grabber: Arc<Grabber>,
+    pub format: String,
+    pub output_folder: String,
+    /// Save the screenshots into one archive instead of separate files.
+    pub archive: bool,
+    pub interval: Duration,
+    pub stop: StopMode,
+    pub hook: Hook,
+    pub triggers: Triggers,
+    pub motion: Option<Motion>,
+    /// Recognize the text of the screenshots into the index of the folder.
+    pub ocr: bool,
+    /// Where the session is up to, saved into `state_folder` as it goes.
+    pub state: State,
+    pub state_folder: Option<PathBuf>,
+    /// Carries on an interrupted session from `state`.
+    pub resumed: bool,
+}
+
+impl Session {
+    /// Takes screenshots until the stop mode is reached or a `Stop` command
+    /// arrives. `commands` comes from `controller.attach()`.
+    pub async fn run(
+        self,
+        mut commands: mpsc::UnboundedReceiver<SessionCommand>,
+        controller: Arc<Controller>,
+    ) {
+        let mut state = self.state.clone();
+        let manifest = Manifest::new(&self.output_folder, &self.name, state.started);
+        if self.resumed {
+            manifest.resume(Local::now(), self.interval);
+        } else {
+            manifest.start(state.started, self.interval);
+        }
+        controller.update(|status| {
+            status.session = Some(manifest.session().to_owned());
+            status.folder = Some(self.output_folder.clone());
+            status.backend = Some(self.grabber.backend().name());
+            status.started = Some(state.started);
+            status.paused = state.paused;
+            status.captures = state.captures;
+            status.failed = state.failed;
+        });
+        self.save(&mut state, &controller);
+
+        self.hook
+            .run(&HookContext {
+                event: HookEvent::SessionStart,
+                folder: &self.output_folder,
+                path: None,
+                time: Local::now(),
+                screen_id: None,
+                archive: None,
+            })
+            .await;
+
+        let archive = match self.archive {
+            true => {
+                let path = format!(
+                    "{}/{}{}",
+                    self.output_folder,
+                    manifest.session(),
+                    archive::EXTENSION
+                );
+                match archive::Writer::open(Path::new(&path)) {
+                    Ok(writer) => Some(std::sync::Mutex::new(writer)),
+                    Err(error) => {
+                        println!("{error}, saving separate files instead");
+                        controller.update(|status| status.last_error = Some(error));
+                        None
+                    }
+                }
+            }
+            false => None,
+        };
+        let archive = archive.as_ref();
+        #[cfg(feature = "ocr")]
+        let ocr = self
+            .ocr
+            .then(|| Indexer::spawn(PathBuf::from(&self.output_folder)));
+        #[cfg(not(feature = "ocr"))]
+        let ocr: Option<Indexer> = None;
+        let ocr = ocr.as_ref();
+
+        let (event_sender, mut events) = mpsc::unbounded_channel();
+        let _watchers = self
+            .triggers
+            .watch(event_sender.clone())
+            .map_err(|error| println!("Failed to watch the triggers: {error}"))
+            .ok();
+        let mut debounce = Debounce::new(self.triggers.debounce);
+
+        // A resumed session keeps its schedule: the next capture is when it
+        // would have been, and "Stop in" counts the time it was down.
+        let delay = (state.next_capture - Local::now())
+            .to_std()
+            .unwrap_or_default();
+        let mut interval = tokio::time::interval_at(Instant::now() + delay, self.interval);
+        let mut first_tick: Option<Instant> = state.first_capture.and_then(|first| {
+            Instant::now().checked_sub((Local::now() - first).to_std().unwrap_or_default())
+        });
+        let mut paused = state.paused;
+        let mut taken = state.taken;
+
+        let mut burst = self.motion.map(Burst::new);
+        let mut sampling = tokio::time::interval(
+            self.motion
+                .map_or(Duration::from_secs(3600), |motion| motion.rate),
+        );
+        sampling.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let deadline = debounce.deadline();
+            tokio::select! {
+                tick = interval.tick() => {
+                    if paused {
+                        continue;
+                    }
+
+                    let elapsed = tick - *first_tick.get_or_insert(tick);
+                    if self.stop.is_reached(elapsed, Local::now(), taken) {
+                        break;
+                    }
+
+                    state.first_capture.get_or_insert_with(Local::now);
+                    let _ = self.take(&manifest, &controller, Trigger::Timer, archive, ocr).await;
+                    taken += 1;
+                    state.taken = taken;
+                    state.next_capture = Local::now()
+                        + chrono::Duration::from_std(self.interval).unwrap_or_else(|_| chrono::Duration::zero());
+                    self.save(&mut state, &controller);
+                }
+                _ = sampling.tick(), if burst.is_some() => {
+                    if let Some(burst) = burst.as_mut().filter(|_| !paused) {
+                        if self.sample(&manifest, &controller, burst, archive, ocr).await {
+                            self.save(&mut state, &controller);
+                        }
+                    }
+                }
+                Some(trigger) = events.recv() => {
+                    if !paused {
+                        debounce.event(trigger, std::time::Instant::now());
+                    }
+                }
+                _ = tokio::time::sleep_until(deadline.map(Instant::from_std).unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    for trigger in debounce.due(std::time::Instant::now()) {
+                        let _ = self.take(&manifest, &controller, trigger, archive, ocr).await;
+                    }
+                    self.save(&mut state, &controller);
+                }
+                command = commands.recv() => match command {
+                    None | Some(SessionCommand::Stop) => break,
+                    Some(SessionCommand::Pause) | Some(SessionCommand::Resume) => {
+                        paused = matches!(command, Some(SessionCommand::Pause));
+                        controller.update(|status| status.paused = paused);
+                        self.save(&mut state, &controller);
+                    }
+                    Some(SessionCommand::CaptureNow { done }) => {
+                        let result = self.take(&manifest, &controller, Trigger::Manual, archive, ocr).await;
+                        if let Some(done) = done {
+                            let _ = done.send(result);
+                        }
+                        self.save(&mut state, &controller);
+                    }
+                    Some(SessionCommand::Annotate { path, note }) => {
+                        manifest.note(Local::now(), &path, &note);
+                    }
+                },
+            }
+        }
+
+        self.hook
+            .run(&HookContext {
+                event: HookEvent::SessionEnd,
+                folder: &self.output_folder,
+                path: None,
+                time: Local::now(),
+                screen_id: None,
+                archive: None,
+            })
+            .await;
+
+        manifest.end(Local::now());
+        if let Some(folder) = &self.state_folder {
+            resume::remove(folder, &state);
+        }
+        controller.detach();
+    }
+
+    /// What the capture layer needs to take and save a screenshot of this
+    /// session, whose manifest calls it `session_id`.
+    fn shot<'a>(&'a self, session_id: &'a str) -> Shot<'a> {
+        Shot {
+            grabber: &self.grabber,
+            target: self.target,
+            cursor: self.cursor,
+            resize: self.resize,
+            format: &self.format,
+            output_folder: &self.output_folder,
+            name: &self.name,
+            session_id,
+        }
+    }
+
+    /// Saves where the session is up to, so it can be resumed if this process
+    /// is killed.
+    fn save(&self, state: &mut State, controller: &Controller) {
+        let Some(folder) = &self.state_folder else {
+            return;
+        };
+
+        let status = controller.status();
+        state.captures = status.captures;
+        state.failed = status.failed;
+        state.paused = status.paused;
+        if let Err(error) = resume::save(folder, state) {
+            println!("{error}");
+        }
+    }
+
+    /// Takes one screenshot, records it in the manifest and the status, and
+    /// returns where it was saved.
+    async fn take(
+        &self,
+        manifest: &Manifest,
+        controller: &Controller,
+        trigger: Trigger,
+        archive: Option<&std::sync::Mutex<archive::Writer>>,
+        ocr: Option<&Indexer>,
+    ) -> Result<PathBuf, String> {
+        let result = screenshot(&self.shot(manifest.session()), archive).await;
+        self.record(manifest, controller, trigger, result, ocr)
+    }
+
+    /// Samples the screen for motion and saves the frames of a burst. Returns
+    /// whether any were.
+    async fn sample(
+        &self,
+        manifest: &Manifest,
+        controller: &Controller,
+        burst: &mut Burst<Sample>,
+        archive: Option<&std::sync::Mutex<archive::Writer>>,
+        ocr: Option<&Indexer>,
+    ) -> bool {
+        let shot = self.shot(manifest.session());
+        let sample = match grab_sample(&shot).await {
+            Ok(sample) => sample,
+            // Several times a second, so it only goes into the status.
+            Err(error) => {
+                controller.update(|status| status.last_error = Some(error));
+                return false;
+            }
+        };
+
+        let signature = motion::signature(&sample.image);
+        let frames = burst.sample(sample, signature);
+        let saved = !frames.is_empty();
+        for frame in frames {
+            let result = save(&shot, frame, true, archive).await;
+            let _ = self.record(manifest, controller, Trigger::Motion, result, ocr);
+        }
+        saved
+    }
+
+    /// Records a capture, or why it failed, in the manifest and the status,
+    /// queues it for text recognition, runs the capture hook and returns
+    /// where it was saved.
+    fn record(
+        &self,
+        manifest: &Manifest,
+        controller: &Controller,
+        trigger: Trigger,
+        result: Result<Capture, String>,
+        ocr: Option<&Indexer>,
+    ) -> Result<PathBuf, String> {
+        let capture = match result {
+            Ok(capture) => capture,
+            Err(error) => {
+                println!("Failed to take screenshot: {error}");
+                manifest.failed(Local::now(), &error);
+                controller.update(|status| {
+                    status.failed += 1;
+                    status.last_error = Some(error.clone());
+                });
+                return Err(error);
+            }
+        };
+        manifest.capture(&capture, trigger);
+        controller.update(|status| {
+            status.captures += 1;
+            status.last_capture = Some(capture.path.clone());
+            status.last_capture_time = Some(capture.time);
+        });
+        if let Some(ocr) = ocr {
+            #[cfg(feature = "ocr")]
+            ocr.index(Entry::new(
+                Path::new(&self.output_folder),
+                &capture,
+                &self.name,
+                manifest.session(),
+            ));
+            #[cfg(not(feature = "ocr"))]
+            match *ocr {}
+        }
+
+        let path = capture.path.clone();
+        if !self.hook.is_empty() {
+            let hook = self.hook.clone();
+            let output_folder = self.output_folder.clone();
+            tokio::spawn(async move {
+                // The hook gets a file of its own for a capture in an archive.
+                let copy = match &capture.archived {
+                    Some(archived) => TempCopy::write(&archived.member, &archived.data)
+                        .await
+                        .map_err(|error| println!("{error}"))
+                        .ok(),
+                    None => None,
+                };
+                hook.run(&HookContext {
+                    event: HookEvent::Capture,
+                    folder: &output_folder,
+                    path: Some(copy.as_ref().map_or(&capture.path, |copy| copy.path())),
+                    time: capture.time,
+                    screen_id: Some(capture.screen_id),
+                    archive: capture
+                        .archived
+                        .as_ref()
+                        .map(|archived| (archived.archive.as_path(), archived.member.as_str())),
+                })
+                .await;
+            });
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2023, 2, 20, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_stop_in() {
+        let stop = StopMode::parse(0, "2 hours", now()).unwrap();
+        assert_eq!(stop, StopMode::After(Duration::from_secs(7200)));
+        assert!(StopMode::parse(0, "2 lightyears", now()).is_err());
+    }
+
+    #[test]
+    fn test_parse_stop_at() {
+        let later_today = StopMode::parse(1, "18:30", now()).unwrap();
+        assert_eq!(
+            later_today,
+            StopMode::At(Local.with_ymd_and_hms(2023, 2, 20, 18, 30, 0).unwrap())
+        );
+
+        let tomorrow = StopMode::parse(1, "08:00", now()).unwrap();
+        assert_eq!(
+            tomorrow,
+            StopMode::At(Local.with_ymd_and_hms(2023, 2, 21, 8, 0, 0).unwrap())
+        );
+
+        let date_time = StopMode::parse(1, "2023-03-01 09:15:30", now()).unwrap();
+        assert_eq!(
+            date_time,
+            StopMode::At(Local.with_ymd_and_hms(2023, 3, 1, 9, 15, 30).unwrap())
+        );
+
+        assert!(StopMode::parse(1, "tonight", now()).is_err());
+    }
+
+    #[test]
+    fn test_parse_stop_after_and_never() {
+        assert_eq!(
+            StopMode::parse(2, " 100 ", now()).unwrap(),
+            StopMode::Count(100)
+        );
+        assert!(StopMode::parse(2, "-1", now()).is_err());
+        assert_eq!(StopMode::parse(3, "", now()).unwrap(), StopMode::Never);
+    }
+
+    #[test]
+    fn test_attach() {
+        let controller = Controller::default();
+        let commands = controller.attach();
+        assert!(commands.is_some());
+        assert!(controller.is_running());
+        assert!(controller.attach().is_none());
+        controller.detach();
+        assert!(controller.attach().is_some());
+    }
+
+    #[test]
+    fn test_validate() {
+        let five_minutes = Duration::from_secs(300);
+
+        assert!(StopMode::Never.validate(Duration::ZERO, now()).is_err());
+        assert!(StopMode::Never.validate(five_minutes, now()).is_ok());
+        assert!(StopMode::After(Duration::from_secs(7200))
+            .validate(five_minutes, now())
+            .is_ok());
+        assert!(StopMode::After(Duration::from_secs(60))
+            .validate(five_minutes, now())
+            .is_err());
+        assert!(StopMode::Count(0).validate(five_minutes, now()).is_err());
+        let centuries = Duration::from_secs(200 * 31_557_600);
+        assert!(StopMode::Never.validate(centuries, now()).is_err());
+        assert!(StopMode::After(centuries)
+            .validate(five_minutes, now())
+            .is_err());
+
+        let past = now() - chrono::Duration::minutes(1);
+        assert!(StopMode::At(past).validate(five_minutes, now()).is_err());
+        let too_soon = now() + chrono::Duration::minutes(2);
+        assert!(StopMode::At(too_soon)
+            .validate(five_minutes, now())
+            .is_err());
+        let later = now() + chrono::Duration::hours(1);
+        assert!(StopMode::At(later).validate(five_minutes, now()).is_ok());
+    }
+
+    #[test]
+    fn test_is_reached() {
+        let minute = Duration::from_secs(60);
+        assert!(!StopMode::After(minute * 10).is_reached(minute * 9, now(), 9));
+        assert!(StopMode::After(minute * 10).is_reached(minute * 10, now(), 10));
+        assert!(!StopMode::Count(3).is_reached(Duration::ZERO, now(), 2));
+        assert!(StopMode::Count(3).is_reached(Duration::ZERO, now(), 3));
+        assert!(StopMode::At(now()).is_reached(Duration::ZERO, now(), 0));
+        assert!(!StopMode::Never.is_reached(Duration::MAX, now(), u64::MAX));
+    }
+}
@@ -0,0 +1,335 @@
+//! Captures on events besides the timer: the focused window changing, the
+//! clipboard changing, or a watched file or folder being written. Events of a
+//! trigger are debounced, so a burst of them makes one capture.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::i18n::{tr, trf};
+use crate::manifest::Trigger;
+
+/// How often a watched file or folder is checked.
+const POLL: Duration = Duration::from_millis(500);
+
+/// The event triggers of a session.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Triggers {
+    /// The focused window changes, on X11.
+    pub window: bool,
+    /// Something is copied to the clipboard, on X11.
+    pub clipboard: bool,
+    /// A file, or a folder whose files, to watch for writes.
+    pub watch: Option<PathBuf>,
+    pub debounce: Duration,
+}
+
+impl Triggers {
+    /// Checks that the triggers can be watched by a session saving into
+    /// `output_folder`, on X11 if `x11` is set.
+    pub fn validate(&self, output_folder: &Path, x11: bool) -> Result<(), String> {
+        if (self.window || self.clipboard) && !x11 {
+            return Err(tr("Window and clipboard triggers need an X11 session.").to_owned());
+        }
+
+        let Some(watch) = &self.watch else {
+            return Ok(());
+        };
+        let watch = watch.canonicalize().map_err(|error| {
+            trf(
+                "Can't watch \"{path}\": {error}",
+                &[("path", &watch.display()), ("error", &error)],
+            )
+        })?;
+        // The screenshots of the session would trigger more screenshots.
+        if let Ok(output_folder) = output_folder.canonicalize() {
+            if watch.starts_with(&output_folder) || output_folder.parent() == Some(&watch) {
+                return Err(trf(
+                    "\"{path}\" can't be watched, the screenshots are saved there.",
+                    &[("path", &watch.display())],
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts watching and sends each event to `events`, until the returned
+    /// watchers are dropped.
+    pub fn watch(&self, events: UnboundedSender<Trigger>) -> Result<Watchers, String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut watchers = Watchers {
+            stop: stop.clone(),
+            wake: None,
+        };
+
+        if self.window || self.clipboard {
+            watchers.wake = Some(watch_x11(
+                self.window,
+                self.clipboard,
+                events.clone(),
+                stop.clone(),
+            )?);
+        }
+
+        if let Some(path) = self.watch.clone() {
+            std::thread::spawn(move || {
+                let mut last = stamp(&path);
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(POLL);
+                    let current = stamp(&path);
+                    if current != last {
+                        last = current;
+                        if events.send(Trigger::File).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        Ok(watchers)
+    }
+}
+
+/// Keeps the triggers watched until dropped.
+pub struct Watchers {
+    stop: Arc<AtomicBool>,
+    /// Wakes the X11 thread up, so it sees `stop`.
+    wake: Option<Box<dyn Fn() + Send>>,
+}
+
+impl Drop for Watchers {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(wake) = &self.wake {
+            wake();
+        }
+    }
+}
+
+/// Size and modification time of a file, or of the files in a folder. Sub
+/// folders are not looked into.
+fn stamp(path: &Path) -> Vec<(PathBuf, SystemTime, u64)> {
+    let files = match std::fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect(),
+        Err(_) => vec![path.to_owned()],
+    };
+
+    let mut stamp: Vec<_> = files
+        .into_iter()
+        .filter_map(|file| {
+            let metadata = std::fs::metadata(&file).ok()?;
+            Some((file, metadata.modified().ok()?, metadata.len()))
+        })
+        .collect();
+    stamp.sort();
+    stamp
+}
+
+/// Watches the focused window through `_NET_ACTIVE_WINDOW` property changes
+/// and the clipboard through XFixes selection events. Returns what wakes the
+/// watching thread up to stop it.
+#[cfg(target_os = "linux")]
+fn watch_x11(
+    window: bool,
+    clipboard: bool,
+    events: UnboundedSender<Trigger>,
+    stop: Arc<AtomicBool>,
+) -> Result<Box<dyn Fn() + Send>, String> {
+    use x11rb::{
+        connection::Connection,
+        protocol::{
+            xfixes::{ConnectionExt as _, SelectionEventMask},
+            xproto::{AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask},
+            Event,
+        },
+    };
+
+    let (connection, screen) = x11rb::connect(None).map_err(|error| {
+        trf(
+            "Window and clipboard triggers need an X11 display: {error}",
+            &[("error", &error)],
+        )
+    })?;
+    let connection = Arc::new(connection);
+    let root = connection.setup().roots[screen].root;
+    let atom = |name: &[u8]| -> Result<u32, String> {
+        Ok(connection
+            .intern_atom(false, name)
+            .map_err(|error| error.to_string())?
+            .reply()
+            .map_err(|error| error.to_string())?
+            .atom)
+    };
+    let active_window = atom(b"_NET_ACTIVE_WINDOW")?;
+
+    if window {
+        let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        connection
+            .change_window_attributes(root, &attributes)
+            .map_err(|error| error.to_string())?
+            .check()
+            .map_err(|error| error.to_string())?;
+    }
+    if clipboard {
+        connection
+            .xfixes_query_version(5, 0)
+            .map_err(|error| error.to_string())?
+            .reply()
+            .map_err(|error| format!("XFixes is not available: {error}"))?;
+        connection
+            .xfixes_select_selection_input(
+                root,
+                atom(b"CLIPBOARD")?,
+                SelectionEventMask::SET_SELECTION_OWNER,
+            )
+            .map_err(|error| error.to_string())?
+            .check()
+            .map_err(|error| error.to_string())?;
+    }
+
+    // Window managers also set the property when the focus stays, so the
+    // value is compared with the last one.
+    let focused = move |connection: &x11rb::rust_connection::RustConnection| {
+        connection
+            .get_property(false, root, active_window, AtomEnum::WINDOW, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?
+            .value32()?
+            .next()
+    };
+
+    let wake = crate::hotkey::waker(connection.clone(), root)?;
+    std::thread::spawn(move || {
+        let mut last_window = focused(&connection);
+        while !stop.load(Ordering::Relaxed) {
+            let trigger = match connection.wait_for_event() {
+                Ok(Event::PropertyNotify(event)) if event.atom == active_window => {
+                    let current = focused(&connection);
+                    if current == last_window {
+                        continue;
+                    }
+                    last_window = current;
+                    Trigger::Window
+                }
+                Ok(Event::XfixesSelectionNotify(_)) => Trigger::Clipboard,
+                Ok(_) => continue,
+                Err(error) => {
+                    println!("Lost the X11 connection of the triggers: {error}");
+                    return;
+                }
+            };
+            if events.send(trigger).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(wake)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch_x11(
+    _window: bool,
+    _clipboard: bool,
+    _events: UnboundedSender<Trigger>,
+    _stop: Arc<AtomicBool>,
+) -> Result<Box<dyn Fn() + Send>, String> {
+    Err(tr("Window and clipboard triggers are only supported on X11.").to_owned())
+}
+
+/// Turns events into captures. The first event of a trigger schedules a
+/// capture after the debounce time, and further events of that trigger until
+/// then are folded into it.
+pub struct Debounce {
+    delay: Duration,
+    pending: Vec<(Trigger, Instant)>,
+}
+
+impl Debounce {
+    pub fn new(delay: Duration) -> Self {
+        Debounce {
+            delay,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn event(&mut self, trigger: Trigger, now: Instant) {
+        if !self.pending.iter().any(|(pending, _)| *pending == trigger) {
+            self.pending.push((trigger, now + self.delay));
+        }
+    }
+
+    /// When the next capture is due.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|(_, due)| *due).min()
+    }
+
+    /// Removes and returns the triggers whose capture is due.
+    pub fn due(&mut self, now: Instant) -> Vec<Trigger> {
+        let (due, pending) = self.pending.iter().partition(|(_, due)| *due <= now);
+        self.pending = pending;
+        due.into_iter().map(|(trigger, _)| trigger).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut debounce = Debounce::new(2 * second);
+        assert_eq!(debounce.deadline(), None);
+
+        debounce.event(Trigger::Window, start);
+        debounce.event(Trigger::Window, start + second);
+        debounce.event(Trigger::Clipboard, start + second);
+        assert_eq!(debounce.deadline(), Some(start + 2 * second));
+        assert!(debounce.due(start + second).is_empty());
+
+        assert_eq!(debounce.due(start + 2 * second), vec![Trigger::Window]);
+        assert_eq!(debounce.deadline(), Some(start + 3 * second));
+        assert_eq!(debounce.due(start + 4 * second), vec![Trigger::Clipboard]);
+        assert_eq!(debounce.deadline(), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let folder =
+            std::env::temp_dir().join(format!("take-screenshots-triggers-{}", std::process::id()));
+        let output = folder.join("screenshots");
+        std::fs::create_dir_all(&output).unwrap();
+
+        let triggers = |watch: &Path| Triggers {
+            watch: Some(watch.to_owned()),
+            ..Triggers::default()
+        };
+        assert!(triggers(&folder).validate(&output, false).is_err());
+        assert!(triggers(&output).validate(&output, false).is_err());
+        assert!(triggers(&folder)
+            .validate(&folder.join("other"), false)
+            .is_ok());
+        assert!(triggers(&folder.join("missing"))
+            .validate(&output, false)
+            .is_err());
+
+        let window = Triggers {
+            window: true,
+            ..Triggers::default()
+        };
+        assert!(window.validate(&output, false).is_err());
+        assert!(window.validate(&output, true).is_ok());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
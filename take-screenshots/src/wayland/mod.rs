@@ -0,0 +1,16 @@
+//! Capture backends for Wayland sessions, where screenshots-rs can only see
+//! XWayland windows and returns black frames.
+
+use std::env;
+
+pub mod portal;
+pub mod screencopy;
+
+/// Whether this process runs inside a Wayland session, judging from the
+/// environment set up by the session manager and the compositor.
+pub fn is_wayland_session() -> bool {
+    env::var("XDG_SESSION_TYPE")
+        .map(|session_type| session_type.eq_ignore_ascii_case("wayland"))
+        .unwrap_or(false)
+        || env::var_os("WAYLAND_DISPLAY").is_some()
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use image::RgbaImage;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+
+static REQUEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Takes a screenshot through the xdg-desktop-portal Screenshot interface.
+///
+/// The portal saves the picture to a file of its own choosing and answers
+/// with its URI. The file is read and then removed, so only the copy saved by
+/// the session is left behind. Blocks until the portal responds.
+pub fn grab() -> Result<RgbaImage, String> {
+    let connection = Connection::session().map_err(|error| error.to_string())?;
+
+    // Subscribe to the response before making the call, the portal may answer
+    // before `Screenshot` returns.
+    let token = format!(
+        "take_screenshots_{}_{}",
+        std::process::id(),
+        REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let sender = connection
+        .unique_name()
+        .ok_or("The D-Bus connection has no unique name")?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let request = Proxy::new(
+        &connection,
+        DESTINATION,
+        format!("{PATH}/request/{sender}/{token}"),
+        "org.freedesktop.portal.Request",
+    )
+    .map_err(|error| error.to_string())?;
+    let mut responses = request
+        .receive_signal("Response")
+        .map_err(|error| error.to_string())?;
+
+    let screenshot = Proxy::new(
+        &connection,
+        DESTINATION,
+        PATH,
+        "org.freedesktop.portal.Screenshot",
+    )
+    .map_err(|error| error.to_string())?;
+
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("handle_token", Value::from(token.as_str()));
+    options.insert("interactive", Value::from(false));
+    let _handle: OwnedObjectPath = screenshot
+        .call("Screenshot", &("", options))
+        .map_err(|error| format!("The screenshot portal is not available: {error}"))?;
+
+    let message = responses
+        .next()
+        .ok_or("The screenshot portal closed the request without answering")?;
+    let (response, results): (u32, HashMap<String, OwnedValue>) =
+        message.body().map_err(|error| error.to_string())?;
+
+    match response {
+        0 => {}
+        1 => return Err("The screenshot was cancelled".to_owned()),
+        _ => return Err("The screenshot portal failed to take a screenshot".to_owned()),
+    }
+
+    let uri = results
+        .get("uri")
+        .and_then(|uri| <&str>::try_from(uri).ok())
+        .ok_or("The screenshot portal answered without a file")?;
+    let path = path_from_uri(uri).ok_or_else(|| format!("Unsupported screenshot URI {uri}"))?;
+
+    let buffer = std::fs::read(&path)
+        .map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+    if let Err(error) = std::fs::remove_file(&path) {
+        println!("Failed to remove {}: {error}", path.display());
+    }
+
+    image::load_from_memory(&buffer)
+        .map(|image| image.to_rgba8())
+        .map_err(|error| error.to_string())
+}
+
+/// Turns a `file://` URI into a path, undoing its percent-encoding.
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+    while index < encoded.len() {
+        if encoded[index] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(encoded[index]);
+            index += 1;
+        }
+    }
+
+    Some(PathBuf::from(OsString::from_vec(decoded)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_from_uri() {
+        assert_eq!(
+            path_from_uri("file:///home/me/Pictures/Screenshot%20from%202023.png"),
+            Some(PathBuf::from("/home/me/Pictures/Screenshot from 2023.png"))
+        );
+        assert_eq!(path_from_uri("https://example.com/a.png"), None);
+        assert_eq!(path_from_uri("file:///broken%2"), None);
+    }
+}
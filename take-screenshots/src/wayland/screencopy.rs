@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    os::fd::AsFd,
+};
+
+use image::RgbaImage;
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_output::{self, WlOutput},
+        wl_registry::WlRegistry,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+const MANAGER_INTERFACE: &str = "zwlr_screencopy_manager_v1";
+
+/// Whether the compositor offers wlr-screencopy, which wlroots based
+/// compositors such as sway do.
+pub fn is_supported() -> bool {
+    let Ok(connection) = Connection::connect_to_env() else {
+        return false;
+    };
+    let Ok((globals, _queue)) = registry_queue_init::<State>(&connection) else {
+        return false;
+    };
+
+    globals.contents().with_list(|list| {
+        list.iter()
+            .any(|global| global.interface == MANAGER_INTERFACE)
+    })
+}
+
+/// One copied output.
+pub struct Output {
+    pub image: RgbaImage,
+    /// Global name of the output, which serves as its screen id.
+    pub name: u32,
+    /// Position of the output in the compositor space.
+    pub position: (i32, i32),
+}
+
+/// Copies the outputs into shared memory buffers, in the order of their
+/// global names, which is the order screens are numbered in. With `screen`,
+/// only that 1-based output is copied.
+pub fn grab(screen: Option<usize>) -> Result<Vec<Output>, String> {
+    let connection = Connection::connect_to_env().map_err(|error| error.to_string())?;
+    let (globals, mut queue) =
+        registry_queue_init::<State>(&connection).map_err(|error| error.to_string())?;
+    let qh = queue.handle();
+
+    let shm: WlShm = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(|error| error.to_string())?;
+    let manager: ZwlrScreencopyManagerV1 = globals
+        .bind(&qh, 1..=3, ())
+        .map_err(|_| format!("The compositor doesn't support {MANAGER_INTERFACE}"))?;
+
+    let mut names: Vec<u32> = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|global| global.interface == WlOutput::interface().name)
+            .map(|global| global.name)
+            .collect()
+    });
+    names.sort_unstable();
+    if names.is_empty() {
+        return Err("The compositor has no outputs".to_owned());
+    }
+    if let Some(screen) = screen {
+        let name = *screen
+            .checked_sub(1)
+            .and_then(|index| names.get(index))
+            .ok_or_else(|| format!("Screen {screen} not found, there are {}", names.len()))?;
+        names = vec![name];
+    }
+
+    let outputs: Vec<(u32, WlOutput)> = names
+        .into_iter()
+        .map(|name| (name, globals.registry().bind(name, 1, &qh, name)))
+        .collect();
+    // Each output sends its geometry, with its position, once bound.
+    let mut state = State::default();
+    queue
+        .roundtrip(&mut state)
+        .map_err(|error| error.to_string())?;
+
+    outputs
+        .iter()
+        .map(|(name, output)| {
+            let image = copy(&manager, &shm, output, &mut queue, &mut state)?;
+            Ok(Output {
+                image,
+                name: *name,
+                position: state.positions.get(name).copied().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Copies one output.
+fn copy(
+    manager: &ZwlrScreencopyManagerV1,
+    shm: &WlShm,
+    output: &WlOutput,
+    queue: &mut EventQueue<State>,
+    state: &mut State,
+) -> Result<RgbaImage, String> {
+    let qh = queue.handle();
+    state.frame = FrameState::default();
+    let frame = manager.capture_output(0, output, &qh, ());
+
+    // Version 3 announces every buffer type it accepts and ends the list with
+    // `buffer_done`, older versions only send the shared memory one.
+    while state.frame.result.is_none()
+        && !(state.frame.buffer.is_some() && (state.frame.buffer_done || manager.version() < 3))
+    {
+        queue
+            .blocking_dispatch(state)
+            .map_err(|error| error.to_string())?;
+    }
+    if let Some(Err(error)) = state.frame.result.take() {
+        return Err(error);
+    }
+
+    let (format, width, height, stride) = state
+        .frame
+        .buffer
+        .ok_or("The compositor offered no shared memory buffer")?;
+    let size = stride as usize * height as usize;
+
+    let mut file = tempfile::tempfile().map_err(|error| error.to_string())?;
+    file.set_len(size as u64)
+        .map_err(|error| error.to_string())?;
+    let pool = shm.create_pool(file.as_fd(), size as i32, &qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        width as i32,
+        height as i32,
+        stride as i32,
+        format,
+        &qh,
+        (),
+    );
+
+    frame.copy(&buffer);
+    while state.frame.result.is_none() {
+        queue
+            .blocking_dispatch(state)
+            .map_err(|error| error.to_string())?;
+    }
+
+    frame.destroy();
+    buffer.destroy();
+    pool.destroy();
+    state.frame.result.take().unwrap_or(Ok(()))?;
+
+    let pixels = read_pixels(&mut file, size)?;
+    to_rgba(&pixels, format, width, height, stride, state.frame.y_invert)
+}
+
+fn read_pixels(file: &mut File, size: usize) -> Result<Vec<u8>, String> {
+    let mut pixels = vec![0; size];
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_exact(&mut pixels))
+        .map_err(|error| error.to_string())?;
+    Ok(pixels)
+}
+
+/// Converts the 32 bit formats compositors hand out in practice. Wayland
+/// formats name the channels of a little endian word, so `Argb8888` is
+/// stored as B, G, R, A bytes.
+fn to_rgba(
+    pixels: &[u8],
+    format: wl_shm::Format,
+    width: u32,
+    height: u32,
+    stride: u32,
+    y_invert: bool,
+) -> Result<RgbaImage, String> {
+    let (swap, opaque) = match format {
+        wl_shm::Format::Argb8888 => (true, false),
+        wl_shm::Format::Xrgb8888 => (true, true),
+        wl_shm::Format::Abgr8888 => (false, false),
+        wl_shm::Format::Xbgr8888 => (false, true),
+        format => return Err(format!("Unsupported screencopy format {format:?}")),
+    };
+
+    let mut image = RgbaImage::new(width, height);
+    for y in 0..height {
+        let source_y = if y_invert { height - 1 - y } else { y };
+        let row = &pixels[(source_y * stride) as usize..];
+        for x in 0..width {
+            let pixel = &row[(x * 4) as usize..(x * 4 + 4) as usize];
+            let (r, b) = if swap {
+                (pixel[2], pixel[0])
+            } else {
+                (pixel[0], pixel[2])
+            };
+            let a = if opaque { 255 } else { pixel[3] };
+            image.put_pixel(x, y, image::Rgba([r, pixel[1], b, a]));
+        }
+    }
+    Ok(image)
+}
+
+#[derive(Default)]
+struct State {
+    /// Positions of the outputs, by global name.
+    positions: HashMap<u32, (i32, i32)>,
+    frame: FrameState,
+}
+
+/// What the compositor said about the output being copied.
+#[derive(Default)]
+struct FrameState {
+    buffer: Option<(wl_shm::Format, u32, u32, u32)>,
+    buffer_done: bool,
+    y_invert: bool,
+    result: Option<Result<(), String>>,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlOutput, u32> for State {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Geometry { x, y, .. } = event {
+            state.positions.insert(*name, (x, y));
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let state = &mut state.frame;
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format: WEnum::Value(format),
+                width,
+                height,
+                stride,
+            } => {
+                state.buffer.get_or_insert((format, width, height, stride));
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => state.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Flags {
+                flags: WEnum::Value(flags),
+            } => state.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert),
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => state.result = Some(Ok(())),
+            zwlr_screencopy_frame_v1::Event::Failed => {
+                state.result = Some(Err("The compositor failed to copy the screen".to_owned()))
+            }
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: ignore WlShmPool);
+delegate_noop!(State: ignore WlBuffer);
+delegate_noop!(State: ignore ZwlrScreencopyManagerV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rgba() {
+        // Two pixels, a red one and a half transparent blue one, plus padding.
+        let pixels = [0, 0, 255, 255, 255, 0, 0, 128, 9, 9, 9, 9];
+
+        let image = to_rgba(&pixels, wl_shm::Format::Argb8888, 2, 1, 12, false).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255, 128]);
+
+        let image = to_rgba(&pixels, wl_shm::Format::Xbgr8888, 2, 1, 12, false).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+
+        assert!(to_rgba(&pixels, wl_shm::Format::Rgb565, 2, 1, 12, false).is_err());
+    }
+
+    #[test]
+    fn test_to_rgba_y_invert() {
+        let pixels = [1, 1, 1, 255, 2, 2, 2, 255];
+        let image = to_rgba(&pixels, wl_shm::Format::Xrgb8888, 1, 2, 4, true).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [2, 2, 2, 255]);
+    }
+}